@@ -0,0 +1,425 @@
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::CONFIG;
+use crate::semver::Bump;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Info,
+	Low,
+	Moderate,
+	High,
+	Critical,
+}
+
+impl FromStr for Severity {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"info" => Ok(Severity::Info),
+			"low" => Ok(Severity::Low),
+			"moderate" | "medium" => Ok(Severity::Moderate),
+			"high" => Ok(Severity::High),
+			"critical" => Ok(Severity::Critical),
+			_ => Err(anyhow!("unknown severity \"{}\"", s)),
+		}
+	}
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			Severity::Info => "info",
+			Severity::Low => "low",
+			Severity::Moderate => "moderate",
+			Severity::High => "high",
+			Severity::Critical => "critical",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/// A known vulnerability in some versions of a package
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Advisory {
+	pub id: String,
+	pub package: String,
+	pub title: String,
+	pub severity: Severity,
+	pub url: Option<String>,
+	/// A semver range, like `>=1.0.0 <1.2.3 || <0.9.1`
+	pub vulnerable_versions: String,
+}
+
+impl Advisory {
+	/// Whether `version` is vulnerable. An advisory whose range can't be read affects
+	/// every version, since an audit shouldn't quietly pass because of it.
+	pub fn affects(&self, version: &Version) -> bool {
+		match SemverRange::from_str(&self.vulnerable_versions) {
+			Ok(range) => version.satisfies(&range),
+			Err(_) => true,
+		}
+	}
+
+	/// Whether `vulnerable_versions` is a range that can be read
+	pub fn is_readable(&self) -> bool {
+		SemverRange::from_str(&self.vulnerable_versions).is_ok()
+	}
+
+	/// Versions which the advisory says the vulnerability was fixed in, which are the
+	/// upper bounds of its vulnerable ranges. A range that ends at the last affected
+	/// version (`<=x.y.z`) is fixed by the next patch.
+	fn fixed_versions(&self) -> Vec<Version> {
+		fn upper_bounds(range: &SemverRange, bounds: &mut Vec<Version>) {
+			match range {
+				SemverRange::LessThan(version) => bounds.push(version.clone()),
+				SemverRange::LessThanOrEqual(version) => bounds.push(version.bump(Bump::Patch, None)),
+				SemverRange::All(ranges) | SemverRange::Either(ranges) => {
+					for range in ranges {
+						upper_bounds(range, bounds);
+					}
+				}
+				_ => (),
+			}
+		}
+
+		let mut bounds = vec![];
+		if let Ok(range) = SemverRange::from_str(&self.vulnerable_versions) {
+			upper_bounds(&range, &mut bounds);
+		}
+		bounds
+	}
+}
+
+/// The lowest version newer than `version` which isn't affected by any of
+/// `advisories`, out of the versions that they were fixed in. Advisories for other
+/// packages are ignored.
+pub fn fixed_version(advisories: &[Advisory], package: &str, version: &Version) -> Option<Version> {
+	let advisories = advisories
+		.iter()
+		.filter(|advisory| advisory.package == package)
+		.collect::<Vec<_>>();
+
+	advisories
+		.iter()
+		.flat_map(|advisory| advisory.fixed_versions())
+		.filter(|fixed| fixed > version)
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.find(|fixed| !advisories.iter().any(|advisory| advisory.affects(fixed)))
+}
+
+/// An advisory as returned by npm's bulk advisory endpoint
+#[derive(Clone, Debug, Deserialize)]
+struct NpmAdvisory {
+	id: serde_json::Value,
+	title: String,
+	severity: String,
+	url: Option<String>,
+	vulnerable_versions: String,
+}
+
+/// Looks up advisories for every given version of each package from the registry
+pub async fn fetch(packages: &BTreeMap<String, BTreeSet<String>>) -> anyhow::Result<Vec<Advisory>> {
+	let response = CLIENT
+		.post(format!(
+			"{}-/npm/v1/security/advisories/bulk",
+			CONFIG.registry
+		))
+		.json(packages)
+		.send()
+		.await?
+		.error_for_status()
+		.map_err(|err| anyhow!("failed to fetch advisories: {}", err))?
+		.json::<BTreeMap<String, Vec<NpmAdvisory>>>()
+		.await?;
+
+	Ok(from_npm(response))
+}
+
+fn from_npm(advisories: BTreeMap<String, Vec<NpmAdvisory>>) -> Vec<Advisory> {
+	advisories
+		.into_iter()
+		.flat_map(|(package, advisories)| {
+			advisories.into_iter().map(move |advisory| Advisory {
+				id: match advisory.id {
+					serde_json::Value::String(id) => id,
+					id => id.to_string(),
+				},
+				package: package.clone(),
+				title: advisory.title,
+				severity: advisory.severity.parse().unwrap_or(Severity::Moderate),
+				url: advisory.url,
+				vulnerable_versions: advisory.vulnerable_versions,
+			})
+		})
+		.collect()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvAdvisory {
+	id: String,
+	summary: Option<String>,
+	withdrawn: Option<String>,
+	#[serde(default)]
+	affected: Vec<OsvAffected>,
+	#[serde(default)]
+	references: Vec<OsvReference>,
+	database_specific: Option<OsvDatabaseSpecific>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvAffected {
+	package: OsvPackage,
+	#[serde(default)]
+	ranges: Vec<OsvRange>,
+	#[serde(default)]
+	versions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvPackage {
+	ecosystem: String,
+	name: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvRange {
+	#[serde(rename = "type")]
+	kind: String,
+	events: Vec<BTreeMap<String, String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvReference {
+	#[serde(rename = "type")]
+	kind: String,
+	url: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct OsvDatabaseSpecific {
+	severity: Option<String>,
+}
+
+/// Turns the events of an OSV range into a semver range, where each `introduced`
+/// event starts a span of vulnerable versions that lasts until the next `fixed` or
+/// `last_affected` event
+fn osv_range(range: &OsvRange) -> Vec<String> {
+	let mut spans = vec![];
+	let mut introduced = None;
+	for event in &range.events {
+		if let Some(version) = event.get("introduced") {
+			introduced = Some(version.as_str());
+		}
+		let end = match (event.get("fixed"), event.get("last_affected")) {
+			(Some(fixed), _) => format!("<{}", fixed),
+			(_, Some(last)) => format!("<={}", last),
+			_ => continue,
+		};
+		spans.push(match introduced.take() {
+			Some("0") | None => end,
+			Some(start) => format!(">={} {}", start, end),
+		});
+	}
+	match introduced {
+		Some("0") => spans.push("*".to_string()),
+		Some(start) => spans.push(format!(">={}", start)),
+		None => (),
+	}
+	spans
+}
+
+fn from_osv(advisory: OsvAdvisory) -> Vec<Advisory> {
+	if advisory.withdrawn.is_some() {
+		return vec![];
+	}
+
+	let severity = advisory
+		.database_specific
+		.and_then(|it| it.severity)
+		.and_then(|it| it.parse().ok())
+		.unwrap_or(Severity::Moderate);
+	let url = advisory
+		.references
+		.iter()
+		.find(|reference| reference.kind == "ADVISORY")
+		.or(advisory.references.first())
+		.map(|reference| reference.url.clone());
+
+	advisory
+		.affected
+		.into_iter()
+		.filter(|affected| affected.package.ecosystem == "npm")
+		.filter_map(|affected| {
+			let spans = affected
+				.ranges
+				.iter()
+				.filter(|range| range.kind == "SEMVER" || range.kind == "ECOSYSTEM")
+				.flat_map(osv_range)
+				.chain(affected.versions)
+				.collect::<Vec<_>>();
+			if spans.is_empty() {
+				return None;
+			}
+
+			Some(Advisory {
+				id: advisory.id.clone(),
+				package: affected.package.name,
+				title: advisory
+					.summary
+					.clone()
+					.unwrap_or_else(|| advisory.id.clone()),
+				severity,
+				url: url.clone(),
+				vulnerable_versions: spans.join(" || "),
+			})
+		})
+		.collect()
+}
+
+/// Reads advisories from a file, or every `.json` file in a directory (like a clone of
+/// the GitHub advisory database). Files can contain a single OSV advisory, a list of
+/// them, or a response from npm's bulk advisory endpoint.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Advisory>> {
+	if path.is_dir() {
+		let mut entries = fs::read_dir(path)?
+			.map(|entry| entry.map(|entry| entry.path()))
+			.collect::<Result<Vec<_>, _>>()?;
+		entries.sort();
+
+		let mut advisories = vec![];
+		for entry in entries {
+			if entry.is_dir() || entry.extension().is_some_and(|it| it == "json") {
+				advisories.extend(load(&entry)?);
+			}
+		}
+		return Ok(advisories);
+	}
+
+	let text = fs::read_to_string(path)
+		.map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+	parse(&text).map_err(|err| anyhow!("failed to parse {}: {}", path.display(), err))
+}
+
+fn parse(text: &str) -> anyhow::Result<Vec<Advisory>> {
+	let json = serde_json::from_str::<serde_json::Value>(text)?;
+	Ok(match json {
+		serde_json::Value::Array(_) => serde_json::from_value::<Vec<OsvAdvisory>>(json)?
+			.into_iter()
+			.flat_map(from_osv)
+			.collect(),
+		serde_json::Value::Object(ref object) if object.contains_key("affected") => {
+			from_osv(serde_json::from_value(json)?)
+		}
+		_ => from_npm(serde_json::from_value(json)?),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn version(version: &str) -> Version {
+		Version::from_str(version).unwrap()
+	}
+
+	#[test]
+	fn osv() {
+		let advisories = parse(
+			r#"{
+				"id": "GHSA-0000-0000-0000",
+				"summary": "Prototype pollution",
+				"affected": [
+					{
+						"package": { "ecosystem": "npm", "name": "left" },
+						"ranges": [{
+							"type": "ECOSYSTEM",
+							"events": [
+								{ "introduced": "0" },
+								{ "fixed": "1.0.4" },
+								{ "introduced": "2.0.0" },
+								{ "last_affected": "2.1.0" }
+							]
+						}],
+						"versions": ["3.0.0"]
+					},
+					{
+						"package": { "ecosystem": "PyPI", "name": "left" },
+						"versions": ["1.0.0"]
+					}
+				],
+				"references": [{ "type": "WEB", "url": "https://example.com" }],
+				"database_specific": { "severity": "HIGH" }
+			}"#,
+		)
+		.unwrap();
+
+		assert_eq!(advisories.len(), 1);
+		let advisory = &advisories[0];
+		assert_eq!(advisory.package, "left");
+		assert_eq!(advisory.severity, Severity::High);
+		assert_eq!(
+			advisory.vulnerable_versions,
+			"<1.0.4 || >=2.0.0 <=2.1.0 || 3.0.0"
+		);
+		assert!(advisory.affects(&version("1.0.3")));
+		assert!(!advisory.affects(&version("1.0.4")));
+		assert!(advisory.affects(&version("2.1.0")));
+		assert!(advisory.affects(&version("3.0.0")));
+		assert_eq!(
+			fixed_version(&advisories, "left", &version("2.0.0")),
+			Some(version("2.1.1"))
+		);
+	}
+
+	#[test]
+	fn unreadable() {
+		let advisories = parse(
+			r#"{ "left": [{ "id": 1, "title": "a", "severity": "low", "url": null, "vulnerable_versions": "who knows" }] }"#,
+		)
+		.unwrap();
+
+		assert!(!advisories[0].is_readable());
+		assert!(advisories[0].affects(&version("1.0.0")));
+		assert_eq!(fixed_version(&advisories, "left", &version("1.0.0")), None);
+	}
+
+	#[test]
+	fn npm() {
+		let advisories = parse(
+			r#"{
+				"left": [
+					{ "id": 1, "title": "a", "severity": "low", "url": null, "vulnerable_versions": "<1.0.2" },
+					{ "id": 2, "title": "b", "severity": "critical", "url": null, "vulnerable_versions": ">=1.0.2 <1.0.5" }
+				]
+			}"#,
+		)
+		.unwrap();
+
+		assert_eq!(advisories[1].id, "2");
+		assert_eq!(advisories[1].severity, Severity::Critical);
+		// 1.0.2 fixes the first advisory, but is affected by the second
+		assert_eq!(
+			fixed_version(&advisories, "left", &version("1.0.0")),
+			Some(version("1.0.5"))
+		);
+		assert_eq!(fixed_version(&advisories, "right", &version("1.0.0")), None);
+	}
+}
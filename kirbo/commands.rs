@@ -8,7 +8,94 @@ pub mod run {
 	pub mod options;
 }
 
+pub mod audit {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod completions {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod config {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod create {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod dedupe {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod dlx {
+	pub mod main;
+	pub mod options;
+}
+
 pub mod exec {
 	pub mod main;
 	pub mod options;
 }
+
+pub mod patch {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod patch_commit {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod workspaces {
+	pub mod changed;
+	pub mod foreach;
+	pub mod main;
+	pub mod options;
+}
+
+pub mod init {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod insight {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod pack {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod publish {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod outdated {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod upgrade {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod version {
+	pub mod main;
+	pub mod options;
+}
+
+pub mod why {
+	pub mod main;
+	pub mod options;
+}
@@ -0,0 +1,384 @@
+use anyhow::anyhow;
+use colored::ColoredString;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::advisories;
+use crate::advisories::Advisory;
+use crate::advisories::Severity;
+use crate::commands;
+use crate::exit::ExitCode;
+use crate::graph::Chain;
+use crate::graph::DependencyGraph;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::manifest;
+use crate::options;
+use crate::semver::Version;
+use crate::specifier::DependencySpecifier;
+
+/// A change to a package.json which stops a vulnerable version from being installed
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Fix {
+	/// Force a fixed version with an override in the root package.json
+	Override { selector: String, range: String },
+	/// Bump the range of a dependency listed directly in a package.json, since those
+	/// can't be overridden
+	#[serde(rename_all = "camelCase")]
+	Dependency {
+		#[serde(skip)]
+		path: PathBuf,
+		dependent: String,
+		field: &'static str,
+		name: String,
+		from: String,
+		to: String,
+	},
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Finding {
+	name: String,
+	version: String,
+	/// The most severe of `advisories`
+	severity: Severity,
+	advisories: Vec<Advisory>,
+	/// How the vulnerable package ends up installed
+	paths: Vec<Chain>,
+	/// The lowest version which isn't affected by any known advisory
+	fixed_in: Option<String>,
+	fixes: Vec<Fix>,
+}
+
+fn color(severity: Severity, text: &str) -> ColoredString {
+	match severity {
+		Severity::Critical => text.bright_red().bold(),
+		Severity::High => text.red(),
+		Severity::Moderate => text.yellow(),
+		Severity::Low | Severity::Info => text.dimmed(),
+	}
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	let json = options::global().json;
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let lock = KirboLock::read(workspace.root())?
+		.ok_or_else(|| anyhow!("no Kirbo.lock found, try running `kirbo install` first"))?;
+	let graph = DependencyGraph::new(&lock, &workspace);
+
+	// Every installed version of each package, along with the descriptors which
+	// resolved to it. Aliases are audited as the package they really are.
+	let mut installed = BTreeMap::<(String, String), Vec<&str>>::new();
+	for (descriptor, package) in &lock.packages {
+		let local = ["workspace:", "firewall:", "file:", "link:"]
+			.iter()
+			.any(|prefix| package.resolved.starts_with(prefix));
+		if local || package.version.is_empty() {
+			continue;
+		}
+		let (name, range) = split_descriptor(descriptor);
+		let name = match DependencySpecifier::parse(range) {
+			DependencySpecifier::Alias { name, .. } => name,
+			_ => name.to_string(),
+		};
+		installed
+			.entry((name, package.version.clone()))
+			.or_default()
+			.push(descriptor);
+	}
+
+	let advisories = match &options.advisories {
+		Some(path) => advisories::load(path)?,
+		None => {
+			let mut versions = BTreeMap::<String, BTreeSet<String>>::new();
+			for (name, version) in installed.keys() {
+				versions
+					.entry(name.clone())
+					.or_default()
+					.insert(version.clone());
+			}
+			advisories::fetch(&versions).await?
+		}
+	};
+
+	for advisory in &advisories {
+		if !advisory.is_readable() && installed.keys().any(|(name, _)| *name == advisory.package) {
+			eprintln!(
+				"{} couldn't read the vulnerable versions of {} ({:?}), so every version of {} is treated as affected",
+				"warning:".yellow(),
+				advisory.id,
+				advisory.vulnerable_versions,
+				advisory.package
+			);
+		}
+	}
+
+	let mut findings = vec![];
+	for ((name, version), descriptors) in &installed {
+		let Ok(parsed) = Version::from_str(version) else {
+			continue;
+		};
+		let matched = advisories
+			.iter()
+			.filter(|advisory| {
+				advisory.package == *name && advisory.severity >= options.level && advisory.affects(&parsed)
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		let Some(severity) = matched.iter().map(|advisory| advisory.severity).max() else {
+			continue;
+		};
+
+		let mut paths = descriptors
+			.iter()
+			.flat_map(|descriptor| graph.chains_to(descriptor))
+			.collect::<Vec<_>>();
+		paths.sort_by(|a, b| a.links.len().cmp(&b.links.len()).then(a.cmp(b)));
+		paths.dedup();
+
+		let fixed_in = advisories::fixed_version(&advisories, name, &parsed);
+		let fixes = match &fixed_in {
+			Some(fixed) => fixes(&workspace, descriptors, &paths, version, fixed),
+			None => vec![],
+		};
+
+		findings.push(Finding {
+			name: name.clone(),
+			version: version.clone(),
+			severity,
+			advisories: matched,
+			paths,
+			fixed_in: fixed_in.map(|it| it.to_string()),
+			fixes,
+		});
+	}
+	findings.sort_by(|a, b| {
+		b.severity
+			.cmp(&a.severity)
+			.then_with(|| a.name.cmp(&b.name))
+	});
+
+	if json {
+		println!("{}", serde_json::to_string_pretty(&findings)?);
+	} else {
+		print_findings(&findings);
+	}
+
+	if options.fix && findings.iter().any(|finding| !finding.fixes.is_empty()) {
+		let changes = apply(&workspace, &findings)?;
+		if !json {
+			println!("{}", "fixes:".bold());
+			for change in changes {
+				println!("  {} {}", "→".green(), change);
+			}
+			println!();
+		}
+		commands::install::main::main(Default::default()).await?;
+
+		if findings.iter().any(|finding| finding.fixes.is_empty()) {
+			return Err(ExitCode(1).into());
+		}
+		return Ok(());
+	}
+
+	if !findings.is_empty() {
+		return Err(ExitCode(1).into());
+	}
+	Ok(())
+}
+
+/// Works out what has to change to stop `version` from being installed by any of
+/// `descriptors`
+fn fixes(
+	workspace: &Workspace,
+	descriptors: &[&str],
+	paths: &[Chain],
+	version: &str,
+	fixed: &Version,
+) -> Vec<Fix> {
+	let mut fixes = BTreeSet::new();
+
+	for package_json in workspace.members().chain([workspace.root_package()]) {
+		let dependent = package_json
+			.1
+			.name
+			.clone()
+			.unwrap_or_else(|| "package.json".to_string());
+		for (field, dependencies) in [
+			("dependencies", &package_json.1.dependencies),
+			("devDependencies", &package_json.1.dev_dependencies),
+		] {
+			for (name, specifier) in dependencies {
+				if !descriptors.contains(&format!("{}@{}", name, specifier).as_str()) {
+					continue;
+				}
+				let to = DependencySpecifier::parse(specifier)
+					.with_version(fixed)
+					.unwrap_or_else(|| format!("^{}", fixed));
+				fixes.insert(Fix::Dependency {
+					path: package_json.0.clone(),
+					dependent: dependent.clone(),
+					field,
+					name: name.clone(),
+					from: specifier.clone(),
+					to,
+				});
+			}
+		}
+	}
+
+	// Anything installed by another package has to be overridden. Overrides match
+	// on the name that the package is installed as, which is different for aliases.
+	for path in paths.iter().filter(|path| path.links.len() > 1) {
+		let installed_as = &path.links.last().unwrap().name;
+		fixes.insert(Fix::Override {
+			selector: format!("{}@{}", installed_as, version),
+			range: format!("^{}", fixed),
+		});
+	}
+
+	fixes.into_iter().collect()
+}
+
+/// Applies every fix, and returns a description of each of them
+fn apply(workspace: &Workspace, findings: &[Finding]) -> anyhow::Result<Vec<String>> {
+	let root = workspace.root_package().0.clone();
+	let mut edits = BTreeMap::<PathBuf, String>::new();
+	let text = |edits: &BTreeMap<PathBuf, String>, path: &PathBuf| match edits.get(path) {
+		Some(text) => Ok(text.clone()),
+		None => fs::read_to_string(path),
+	};
+
+	let mut changes = vec![];
+	for fix in findings.iter().flat_map(|finding| &finding.fixes) {
+		let (path, edited) = match fix {
+			Fix::Override { selector, range } => {
+				let mut edited = text(&edits, &root)?;
+				if manifest::find_value(&edited, &["overrides"]).is_none() {
+					edited = manifest::set_json(&edited, &["overrides"], "{}")
+						.ok_or_else(|| anyhow!("failed to add overrides to {}", root.display()))?;
+				}
+				let edited = manifest::set_json(
+					&edited,
+					&["overrides", selector],
+					&serde_json::to_string(range)?,
+				)
+				.ok_or_else(|| anyhow!("failed to add an override to {}", root.display()))?;
+				changes.push(format!("override {} with {}", selector, range));
+				(root.clone(), edited)
+			}
+			Fix::Dependency {
+				path,
+				dependent,
+				field,
+				name,
+				from,
+				to,
+			} => {
+				let edited = manifest::set_string(&text(&edits, path)?, &[field, name], to)
+					.ok_or_else(|| anyhow!("failed to find {} in {} of {}", name, field, path.display()))?;
+				changes.push(format!(
+					"bump {} from {} to {} in {}",
+					name, from, to, dependent
+				));
+				(path.clone(), edited)
+			}
+		};
+		edits.insert(path, edited);
+	}
+
+	for (path, text) in edits {
+		fs::write(path, text)?;
+	}
+	Ok(changes)
+}
+
+fn print_findings(findings: &[Finding]) {
+	if !options::global().silent {
+		println!("{}", "kirbo audit".bright_magenta().bold());
+	}
+	if findings.is_empty() {
+		println!("no known vulnerabilities");
+		return;
+	}
+
+	println!("========================================");
+	for finding in findings {
+		println!(
+			"{} {}@{}",
+			color(finding.severity, &format!("{:8}", finding.severity)),
+			finding.name.bold(),
+			finding.version
+		);
+		for advisory in &finding.advisories {
+			println!(
+				"  {} {} {}",
+				color(advisory.severity, "●"),
+				advisory.title,
+				format!("({})", advisory.id).dimmed()
+			);
+			if let Some(url) = &advisory.url {
+				println!("    {}", url.dimmed());
+			}
+		}
+		for path in finding.paths.iter().take(3) {
+			let links = path
+				.links
+				.iter()
+				.map(|link| format!("{}@{}", link.name, link.version))
+				.collect::<Vec<_>>()
+				.join(" → ");
+			println!("  via {} → {}", path.root.name, links);
+		}
+		if finding.paths.len() > 3 {
+			println!("  ...and {} more", finding.paths.len() - 3);
+		}
+		match &finding.fixed_in {
+			Some(fixed) => println!("  {} fixed in {}", "→".green(), fixed),
+			None => println!("  {} no fixed version is known", "✗".red()),
+		}
+		for fix in &finding.fixes {
+			match fix {
+				Fix::Override { selector, range } => {
+					println!("    \"overrides\": {{ \"{}\": \"{}\" }}", selector, range)
+				}
+				Fix::Dependency {
+					dependent,
+					name,
+					to,
+					..
+				} => println!("    bump {} to {} in {}", name, to, dependent),
+			}
+		}
+	}
+	println!("========================================");
+
+	let mut counts = BTreeMap::<Severity, usize>::new();
+	for finding in findings {
+		*counts.entry(finding.severity).or_default() += 1;
+	}
+	let counts = counts
+		.iter()
+		.rev()
+		.map(|(severity, count)| color(*severity, &format!("{} {}", count, severity)).to_string())
+		.collect::<Vec<_>>();
+	println!(
+		"{} vulnerable package{} ({})",
+		findings.len(),
+		if findings.len() == 1 { "" } else { "s" },
+		counts.join(", ")
+	);
+	if findings.iter().any(|finding| !finding.fixes.is_empty()) {
+		println!("run `kirbo audit --fix` to apply the fixes");
+	}
+}
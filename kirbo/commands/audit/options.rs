@@ -0,0 +1,19 @@
+use clap::Args;
+use std::path::PathBuf;
+
+use crate::advisories::Severity;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Apply the suggested fixes, and reinstall
+	#[arg(long)]
+	pub fix: bool,
+	/// Ignore advisories which are less severe than this (info, low, moderate, high,
+	/// critical)
+	#[arg(long, value_name = "severity", default_value = "info")]
+	pub level: Severity,
+	/// A file or directory of advisories to use instead of asking the registry
+	#[arg(long, value_name = "path")]
+	pub advisories: Option<PathBuf>,
+}
@@ -0,0 +1,22 @@
+use clap::CommandFactory;
+use clap_complete::generate;
+use std::io;
+
+use super::options::Options;
+use super::options::Shell;
+use crate::options;
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	let shell = match options.shell {
+		Shell::Bash => clap_complete::Shell::Bash,
+		Shell::Zsh => clap_complete::Shell::Zsh,
+		Shell::Fish => clap_complete::Shell::Fish,
+	};
+	generate(
+		shell,
+		&mut options::Options::command(),
+		"kirbo",
+		&mut io::stdout(),
+	);
+	Ok(())
+}
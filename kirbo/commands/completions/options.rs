@@ -0,0 +1,17 @@
+use clap::Args;
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Shell {
+	Bash,
+	Zsh,
+	Fish,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// The shell to print a completion script for
+	#[arg(value_name = "shell")]
+	pub shell: Shell,
+}
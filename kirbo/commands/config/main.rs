@@ -0,0 +1,72 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use std::env;
+use std::fs;
+use std::io;
+
+use super::options::Action;
+use super::options::Options;
+use crate::config;
+use crate::config::Origin;
+use crate::config::CONFIG;
+use crate::config::PROJECT_CONFIG_FILE_NAME;
+use crate::config::SETTINGS;
+
+fn print_value(value: &str, origin: &Origin, show_origin: bool) {
+	match show_origin {
+		true => println!("{}  {}", value, format!("# {}", origin).dimmed()),
+		false => println!("{}", value),
+	}
+}
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	match options.action.clone().unwrap_or_default() {
+		Action::List => {
+			for setting in SETTINGS {
+				let Some((value, origin)) = CONFIG.values.get(setting.key) else {
+					continue;
+				};
+				let value = match setting.secret {
+					true => "(hidden)".to_string(),
+					false => setting.format_value(value),
+				};
+				let line = format!("{} = {}", setting.key, value);
+				print_value(&line, origin, options.show_origin);
+			}
+		}
+		Action::Get { key, show_secret } => {
+			let setting = config::find_setting(&key)?;
+			if let Some((value, origin)) = CONFIG.values.get(setting.key) {
+				let value = match setting.secret && !show_secret {
+					true => "(hidden, use --show-secret to print it)",
+					false => value,
+				};
+				print_value(value, origin, options.show_origin);
+			}
+		}
+		Action::Set { key, value } => {
+			if options.project && config::find_setting(&key)?.secret {
+				return Err(anyhow!(
+					"{} can't be saved to {}, since everyone with the project could read it",
+					key,
+					PROJECT_CONFIG_FILE_NAME
+				));
+			}
+			let path = match options.project {
+				true => config::project_dir(&env::current_dir()?).join(PROJECT_CONFIG_FILE_NAME),
+				false => config::global_config_path(),
+			};
+			let text = match fs::read_to_string(&path) {
+				Ok(text) => text,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+				Err(err) => return Err(err.into()),
+			};
+			let text = config::set_in_file(&text, &key, &value)?;
+			fs::create_dir_all(path.parent().unwrap())?;
+			fs::write(&path, text)?;
+			println!("set {} in {}", key.bold(), path.display());
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,41 @@
+use clap::Args;
+use clap::Subcommand;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Subcommand)]
+pub enum Action {
+	/// Print every setting (the default)
+	#[default]
+	#[command(visible_alias = "ls")]
+	List,
+	/// Print the value of a setting
+	Get {
+		#[arg(value_name = "key")]
+		key: String,
+		/// Print the value even if it's a secret, like auth-token
+		#[arg(long)]
+		show_secret: bool,
+	},
+	/// Save a setting to the global config, or the project's .kirborc
+	Set {
+		#[arg(value_name = "key")]
+		key: String,
+		#[arg(value_name = "value")]
+		value: String,
+	},
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	#[command(subcommand)]
+	pub action: Option<Action>,
+	/// Print where each value came from
+	#[arg(long, global = true)]
+	pub show_origin: bool,
+	/// Save to the project's .kirborc instead of the global config file
+	#[arg(long, global = true, conflicts_with = "global")]
+	pub project: bool,
+	/// Save to the global config file (the default)
+	#[arg(long, global = true)]
+	pub global: bool,
+}
@@ -0,0 +1,65 @@
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::process;
+
+use super::options::Options;
+use crate::exit::ExitCode;
+use crate::lock::split_descriptor;
+use crate::options;
+use crate::temporary;
+
+/// The package that runs a starter, and the range of it to install
+fn starter_package(starter: &str) -> (String, String) {
+	let (name, range) = split_descriptor(starter);
+	let range = match range {
+		"" => "*".to_string(),
+		range => range.to_string(),
+	};
+
+	let name = match name.split_once('/') {
+		Some((scope, name)) => format!("{}/create-{}", scope, name),
+		None if name.starts_with('@') => format!("{}/create", name),
+		None => format!("create-{}", name),
+	};
+	(name, range)
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo create".bright_magenta().bold());
+	}
+
+	let cwd = env::current_dir()?;
+	let (name, range) = starter_package(&options.starter);
+
+	let dir = env::temp_dir().join(format!("kirbo-create-{}", process::id()));
+	let installed = temporary::install(&dir, &BTreeMap::from([(name.clone(), range)])).await;
+	let status = installed
+		.and_then(|_| temporary::default_bin(&dir, &name))
+		.and_then(|command| {
+			println!("{} {}", "running".dimmed(), command.bold());
+			temporary::run_bin(&dir, &command, &options.args, &cwd)
+		});
+	let _ = fs::remove_dir_all(&dir);
+
+	Ok(ExitCode::check(status?)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn starters() {
+		let starter = |starter: &str| {
+			let (name, range) = starter_package(starter);
+			format!("{}@{}", name, range)
+		};
+		assert_eq!(starter("vite"), "create-vite@*");
+		assert_eq!(starter("vite@^5"), "create-vite@^5");
+		assert_eq!(starter("@org"), "@org/create@*");
+		assert_eq!(starter("@org/app@2.0.0"), "@org/create-app@2.0.0");
+	}
+}
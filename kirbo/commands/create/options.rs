@@ -0,0 +1,16 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Which `create-` package to run, like `vite` for `create-vite`
+	#[arg(value_name = "starter")]
+	pub starter: String,
+	/// Arguments for the starter
+	#[arg(
+		value_name = "args",
+		trailing_var_arg = true,
+		allow_hyphen_values = true
+	)]
+	pub args: Vec<String>,
+}
@@ -0,0 +1,248 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::commands;
+use crate::exit::ExitCode;
+use crate::graph::package_dependencies;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::lock::KirboLockPackage;
+use crate::options;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+/// A descriptor which is moved onto a different version
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Change {
+	descriptor: String,
+	from: String,
+	to: String,
+}
+
+/// Moves every descriptor of each package onto the smallest set of locked versions
+/// which satisfies all of them
+fn dedupe(lock: &mut KirboLock) -> Vec<Change> {
+	let mut by_name = BTreeMap::<&str, Vec<(String, SemverRange)>>::new();
+	for (descriptor, package) in &lock.packages {
+		// Anything that didn't come from the registry can't be swapped for another version
+		if !package.resolved.starts_with("http://") && !package.resolved.starts_with("https://") {
+			continue;
+		}
+		let (name, range) = split_descriptor(descriptor);
+		let Ok(range) = SemverRange::from_str(range) else {
+			continue;
+		};
+		by_name
+			.entry(name)
+			.or_default()
+			.push((descriptor.clone(), range));
+	}
+
+	let mut replacements = vec![];
+	for requests in by_name.into_values() {
+		let mut templates = BTreeMap::<Version, &KirboLockPackage>::new();
+		for (descriptor, _) in &requests {
+			let package = &lock.packages[descriptor];
+			if let Ok(version) = Version::from_str(&package.version) {
+				templates.entry(version).or_insert(package);
+			}
+		}
+		if templates.len() < 2 {
+			continue;
+		}
+
+		// Finding the fewest versions is a set cover problem, so we settle for greedily
+		// taking whichever version satisfies the most remaining ranges, and the newest
+		// of those when there's a tie
+		let mut remaining = requests.iter().map(|(_, range)| range).collect::<Vec<_>>();
+		let mut chosen = vec![];
+		while !remaining.is_empty() {
+			let (best, count) = templates
+				.keys()
+				.map(|version| {
+					let count = remaining
+						.iter()
+						.filter(|range| version.satisfies(range))
+						.count();
+					(version, count)
+				})
+				.max_by_key(|(_, count)| *count)
+				.unwrap();
+			// Ranges which nothing satisfies (because of an override) are left alone
+			if count == 0 {
+				break;
+			}
+			remaining.retain(|range| !best.satisfies(range));
+			chosen.push(best);
+		}
+
+		for (descriptor, range) in &requests {
+			let Some(version) = chosen.iter().filter(|it| it.satisfies(range)).max() else {
+				continue;
+			};
+			let from = &lock.packages[descriptor].version;
+			if version.to_string() != *from {
+				let mut package = templates[*version].clone();
+				package.overrides = lock.packages[descriptor].overrides.clone();
+				replacements.push((
+					Change {
+						descriptor: descriptor.clone(),
+						from: from.clone(),
+						to: version.to_string(),
+					},
+					package,
+				));
+			}
+		}
+	}
+
+	replacements
+		.into_iter()
+		.map(|(change, package)| {
+			lock.packages.insert(change.descriptor.clone(), package);
+			change
+		})
+		.collect()
+}
+
+/// Removes packages which nothing depends on anymore, and returns their descriptors
+fn prune(lock: &mut KirboLock, roots: &BTreeSet<String>) -> Vec<String> {
+	let mut queue = lock
+		.packages
+		.iter()
+		.filter(|(descriptor, package)| {
+			roots.contains(*descriptor) || package.resolved.starts_with("workspace:")
+		})
+		.map(|(descriptor, _)| descriptor.clone())
+		.collect::<Vec<_>>();
+
+	let mut reachable = BTreeSet::new();
+	while let Some(descriptor) = queue.pop() {
+		if !reachable.insert(descriptor.clone()) {
+			continue;
+		}
+		if let Some(package) = lock.packages.get(&descriptor) {
+			queue.extend(
+				package
+					.dependencies
+					.iter()
+					.chain(&package.optional_dependencies)
+					.chain(&package.peer_dependencies)
+					.chain(&package.dev_dependencies)
+					.cloned(),
+			);
+		}
+	}
+
+	let unreachable = lock
+		.packages
+		.keys()
+		.filter(|descriptor| !reachable.contains(*descriptor))
+		.cloned()
+		.collect::<Vec<_>>();
+	lock
+		.packages
+		.retain(|descriptor, _| reachable.contains(descriptor));
+	unreachable
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo dedupe".bright_magenta().bold());
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let mut lock = KirboLock::read(workspace.root())?
+		.ok_or_else(|| anyhow!("no Kirbo.lock found, try running `kirbo install` first"))?;
+
+	let changes = dedupe(&mut lock);
+	if changes.is_empty() {
+		println!("nothing to deduplicate");
+		return Ok(());
+	}
+
+	println!("========================================");
+	for change in &changes {
+		println!(
+			"  {} {} → {}",
+			change.descriptor.bold(),
+			change.from,
+			change.to.green()
+		);
+	}
+	let roots = workspace
+		.packages()
+		.chain([workspace.root_package()])
+		.flat_map(|package_json| package_dependencies(&package_json.1))
+		.map(|(_, descriptor)| descriptor)
+		.collect::<BTreeSet<_>>();
+	for descriptor in prune(&mut lock, &roots) {
+		println!("  {} {}", descriptor.bold(), "removed".red());
+	}
+	println!("========================================");
+
+	if options.check {
+		println!("run `kirbo dedupe` to deduplicate");
+		return Err(ExitCode(1).into());
+	}
+
+	lock.write(workspace.root())?;
+	println!();
+
+	// Installing again keeps the versions we just locked, and updates node_modules to
+	// match them
+	commands::install::main::main(Default::default()).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn package(version: &str) -> KirboLockPackage {
+		KirboLockPackage {
+			version: version.to_string(),
+			resolved: format!("https://registry.npmjs.org/a/-/a-{}.tgz", version),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn dedupes() {
+		let mut lock = KirboLock {
+			lock_version: 1,
+			packages: BTreeMap::from([
+				("a@^1.0.0".to_string(), package("1.2.0")),
+				("a@~1.1.0".to_string(), package("1.1.5")),
+				("a@^1.1.0".to_string(), package("1.2.0")),
+				("a@^2.0.0".to_string(), package("2.0.0")),
+			]),
+			..Default::default()
+		};
+
+		let changes = dedupe(&mut lock);
+		assert_eq!(
+			changes,
+			vec![
+				Change {
+					descriptor: "a@^1.0.0".to_string(),
+					from: "1.2.0".to_string(),
+					to: "1.1.5".to_string(),
+				},
+				Change {
+					descriptor: "a@^1.1.0".to_string(),
+					from: "1.2.0".to_string(),
+					to: "1.1.5".to_string(),
+				},
+			]
+		);
+		assert_eq!(lock.packages["a@^1.0.0"], package("1.1.5"));
+		assert_eq!(lock.packages["a@^2.0.0"], package("2.0.0"));
+		assert!(dedupe(&mut lock).is_empty());
+	}
+}
@@ -0,0 +1,10 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Don't change anything, but exit with a non-zero status if deduplicating would
+	/// change Kirbo.lock
+	#[arg(long)]
+	pub check: bool,
+}
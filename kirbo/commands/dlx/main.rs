@@ -0,0 +1,74 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use sha2::Digest;
+use sha2::Sha512;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+use super::options::Options;
+use crate::config::CONFIG;
+use crate::exit::ExitCode;
+use crate::lock::split_descriptor;
+use crate::lock::LOCK_FILE_NAME;
+use crate::options;
+use crate::resolver::Resolver;
+use crate::temporary;
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo dlx".bright_magenta().bold());
+	}
+
+	let cwd = env::current_dir()?;
+
+	// Installs are cached by the versions they resolve to, so a new release gets a new
+	// install, but otherwise the same one gets used over and over
+	let mut resolver = Resolver::default().quiet();
+	let mut versions = BTreeMap::new();
+	let (packages, command) = options.packages();
+	for package in &packages {
+		let (name, range) = match split_descriptor(package) {
+			(name, "") => (name, "*"),
+			descriptor => descriptor,
+		};
+		let version = resolver
+			.newest_version(name, range)
+			.await
+			.ok_or_else(|| anyhow!("no version of {} matches {}", name, range))?;
+		versions.insert(name.to_string(), version);
+	}
+
+	let key = versions
+		.iter()
+		.map(|(name, version)| format!("{}@{}\n", name, version))
+		.collect::<String>();
+	let hash = Sha512::digest(key.as_bytes())
+		.iter()
+		.take(8)
+		.map(|byte| format!("{:02x}", byte))
+		.collect::<String>();
+	let dir = CONFIG.cache_dir.join("dlx").join(hash);
+
+	// The lock is written last, so an install without one never finished
+	if dir.join(LOCK_FILE_NAME).exists() {
+		println!("{} {}", "using".dimmed(), dir.display());
+	} else {
+		let _ = fs::remove_dir_all(&dir);
+		if let Err(err) = temporary::install(&dir, &versions).await {
+			let _ = fs::remove_dir_all(&dir);
+			return Err(err);
+		}
+	}
+
+	let command = match command {
+		Some(command) => command,
+		None => {
+			let (name, _) = versions.iter().next().unwrap();
+			temporary::default_bin(&dir, name)?
+		}
+	};
+	println!("{} {}", "running".dimmed(), command.bold());
+	let status = temporary::run_bin(&dir, &command, &options.args, &cwd)?;
+	Ok(ExitCode::check(status)?)
+}
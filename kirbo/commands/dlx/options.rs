@@ -0,0 +1,30 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Install a package, and run <command> from any of them instead of the first
+	/// package's executable
+	#[arg(short = 'p', long = "package", value_name = "package")]
+	pub packages: Vec<String>,
+	/// The package to run, as `name[@range]`, or with -p, the executable to run
+	#[arg(value_name = "command")]
+	pub command: String,
+	/// Arguments for the executable
+	#[arg(
+		value_name = "args",
+		trailing_var_arg = true,
+		allow_hyphen_values = true
+	)]
+	pub args: Vec<String>,
+}
+
+impl Options {
+	/// The packages to install, and the executable to run if it was named explicitly
+	pub fn packages(&self) -> (Vec<String>, Option<String>) {
+		match self.packages.is_empty() {
+			true => (vec![self.command.clone()], None),
+			false => (self.packages.clone(), Some(self.command.clone())),
+		}
+	}
+}
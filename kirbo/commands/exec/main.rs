@@ -1,29 +1,40 @@
+use anyhow::anyhow;
 use colored::Colorize;
+use kirbo_workspace::Workspace;
 use std::env;
-use std::ffi::OsStr;
-use std::path::PathBuf;
 use std::process::Command;
 
-use crate::options::Options;
+use super::options::Options;
+use crate::exit::ExitCode;
+use crate::options;
+use crate::scripts;
+use crate::workspace;
 
-pub fn main(options: Options) {
-	println!("{}", "kirbo exec".bright_magenta().bold());
-	let args = options.remaining_args;
+pub fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo exec".bright_magenta().bold());
+	}
 
-	let mut path_env = env::current_dir()
-		.unwrap_or(PathBuf::from("."))
-		.join("node_modules/.bin/")
-		.into_os_string();
-
-	if let Some(path) = env::var_os("PATH") {
-		path_env.extend([OsStr::new(":"), &path]);
+	// Binaries are found in the node_modules of any directory above this one, so that
+	// everything installed at the root of a workspace is available
+	let cwd = env::current_dir()?;
+	let dir = match &options.workspace {
+		Some(name) => {
+			let workspace = Workspace::new(&cwd)?;
+			workspace::target_package(&workspace, Some(name))?
+				.dir()
+				.to_path_buf()
+		}
+		None => cwd,
 	};
 
-	Command::new(&args[0])
-		.args(&args[1..])
-		.env("PATH", path_env)
+	let status = Command::new(&options.command)
+		.args(&options.args)
+		.current_dir(&dir)
+		.env("PATH", scripts::path_env(&dir))
 		.spawn()
-		.unwrap()
-		.wait()
-		.unwrap();
+		.map_err(|err| anyhow!("couldn't run {}: {}", options.command, err))?
+		.wait()?;
+
+	Ok(ExitCode::check(status)?)
 }
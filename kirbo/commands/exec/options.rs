@@ -1 +1,18 @@
+use clap::Args;
 
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+pub struct Options {
+	/// Run the command in this workspace member, instead of the current directory
+	#[arg(short, long, value_name = "name")]
+	pub workspace: Option<String>,
+	/// The command to run
+	#[arg(value_name = "command")]
+	pub command: String,
+	/// Arguments for the command
+	#[arg(
+		value_name = "args",
+		trailing_var_arg = true,
+		allow_hyphen_values = true
+	)]
+	pub args: Vec<String>,
+}
@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Input;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use super::options::Options;
+use crate::config::CONFIG;
+use crate::options;
+
+/// Turns the name of a directory into a valid package name. npm's rules are mostly
+/// about what's safe to put in a URL.
+fn package_name(dir: &Path) -> String {
+	let name = dir
+		.file_name()
+		.map(|name| name.to_string_lossy().to_lowercase())
+		.unwrap_or_default()
+		.chars()
+		.map(|c| match c.is_ascii_alphanumeric() || "-._~".contains(c) {
+			true => c,
+			false => '-',
+		})
+		.collect::<String>();
+
+	match name.trim_start_matches(['.', '_']) {
+		"" => "package".to_string(),
+		name => name.to_string(),
+	}
+}
+
+fn ask(prompt: &str, default: String) -> anyhow::Result<String> {
+	Ok(
+		Input::<String>::with_theme(&ColorfulTheme::default())
+			.with_prompt(prompt)
+			.default(default)
+			.allow_empty(true)
+			.interact_text()?,
+	)
+}
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo init".bright_magenta().bold());
+	}
+
+	let dir = env::current_dir()?;
+	let path = dir.join("package.json");
+	if path.exists() {
+		return Err(anyhow!("{} already exists", path.display()));
+	}
+
+	let mut name = package_name(&dir);
+	let mut version = "0.0.0".to_string();
+	let mut description = String::new();
+	let mut main = "index.js".to_string();
+	let mut license = CONFIG.init_license.clone();
+	if !options.yes {
+		name = ask("name", name)?;
+		version = ask("version", version)?;
+		description = ask("description", description)?;
+		main = ask("entry point", main)?;
+		license = ask("license", license)?;
+	}
+
+	// Written out by hand, so that the fields stay in this order
+	let fields = [
+		("name", Some(name)),
+		("version", Some(version)),
+		("description", Some(description).filter(|it| !it.is_empty())),
+		("main", Some(main).filter(|it| !it.is_empty())),
+		("license", Some(license).filter(|it| !it.is_empty())),
+		(
+			"packageManager",
+			Some(format!("kirbo@{}", env!("CARGO_PKG_VERSION"))),
+		),
+	];
+	let fields = fields
+		.iter()
+		.filter_map(|(key, value)| {
+			Some(format!(
+				"  \"{}\": {}",
+				key,
+				serde_json::to_string(value.as_ref()?).ok()?
+			))
+		})
+		.collect::<Vec<_>>();
+	let text = format!("{{\n{}\n}}\n", fields.join(",\n"));
+
+	fs::write(&path, &text)?;
+	println!("wrote {}", path.display());
+	print!("{}", text);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names() {
+		assert_eq!(package_name(Path::new("/code/my-app")), "my-app");
+		assert_eq!(package_name(Path::new("/code/My App")), "my-app");
+		assert_eq!(package_name(Path::new("/code/.hidden")), "hidden");
+		assert_eq!(package_name(Path::new("/")), "package");
+	}
+}
@@ -0,0 +1,9 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Use the defaults instead of asking
+	#[arg(short, long)]
+	pub yes: bool,
+}
@@ -0,0 +1,339 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::graph::package_dependencies;
+use crate::graph::DependencyGraph;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::options;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+use crate::store;
+use crate::store::format_size;
+use crate::store::Store;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DependencyCost {
+	name: String,
+	range: String,
+	version: String,
+	/// Packages installed because of this one, not counting itself
+	transitive_packages: usize,
+	tarball_size: u64,
+	unpacked_size: u64,
+	/// Packages which count towards this dependency, but which haven't been downloaded,
+	/// so we don't know their size
+	missing_from_store: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateVersion {
+	version: String,
+	ranges: Vec<String>,
+	requested_by: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Suggestion {
+	/// The package.json or package which requests `from`
+	requester: String,
+	from: String,
+	to: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Duplicate {
+	name: String,
+	versions: Vec<DuplicateVersion>,
+	/// A version which is already installed and satisfies every range, if there is one,
+	/// in which case reinstalling is enough to deduplicate
+	satisfied_by: Option<String>,
+	suggestions: Vec<Suggestion>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Report {
+	dependencies: Vec<DependencyCost>,
+	duplicates: Vec<Duplicate>,
+}
+
+fn is_workspace(lock: &KirboLock, descriptor: &str) -> bool {
+	lock
+		.packages
+		.get(descriptor)
+		.map(|package| package.resolved.starts_with("workspace:"))
+		.unwrap_or(false)
+}
+
+fn dependency_costs(
+	lock: &KirboLock,
+	workspace: &Workspace,
+	graph: &DependencyGraph,
+	store: &Store,
+) -> Vec<DependencyCost> {
+	let direct = workspace
+		.packages()
+		.chain([workspace.root_package()])
+		.flat_map(|package_json| package_dependencies(&package_json.1))
+		.filter(|(kind, _)| *kind != "peerDependencies")
+		.map(|(_, descriptor)| descriptor)
+		.filter(|descriptor| lock.packages.contains_key(descriptor) && !is_workspace(lock, descriptor))
+		.collect::<BTreeSet<_>>();
+
+	// Measuring a tarball means reading the whole thing, so only do it once each
+	let mut sizes = BTreeMap::<(String, String), Option<(u64, u64)>>::new();
+	let mut measure = |name: &str, version: &str, resolved: &str, sha512: &str| {
+		*sizes
+			.entry((name.to_string(), version.to_string()))
+			.or_insert_with(|| {
+				// Substitutes are never downloaded, so they only take up as much space as
+				// the files we write for them
+				if resolved.starts_with("firewall:") {
+					let substitution = kirbo_firewall::find(name)?;
+					let unpacked_size = substitution.source.len() + substitution.package_json().len();
+					return Some((0, unpacked_size as u64));
+				}
+
+				// Anything that didn't come from the registry is kept by its commit or hash
+				let tarball = match resolved
+					.strip_prefix("git+")
+					.and_then(|it| it.rsplit_once('#'))
+				{
+					Some((_, commit)) => Some(store.source_path(commit)),
+					None => store
+						.get(name, version)
+						.or_else(|| Some(store.source_path(sha512)).filter(|_| !sha512.is_empty())),
+				}
+				.filter(|path| path.is_file())?;
+				let tarball_size = fs::metadata(&tarball).ok()?.len();
+				let unpacked_size = store::unpacked_size(&tarball).ok()?;
+				Some((tarball_size, unpacked_size))
+			})
+	};
+
+	direct
+		.iter()
+		.map(|descriptor| {
+			let (name, range) = split_descriptor(descriptor);
+			let installed = graph
+				.transitive_dependencies(descriptor)
+				.into_keys()
+				.filter(|it| !is_workspace(lock, it))
+				.map(|it| {
+					let package = &lock.packages[it];
+					(
+						split_descriptor(it).0,
+						package.version.as_str(),
+						package.resolved.as_str(),
+						package.sha512.as_str(),
+					)
+				})
+				.collect::<BTreeSet<_>>();
+
+			let mut cost = DependencyCost {
+				name: name.to_string(),
+				range: range.to_string(),
+				version: lock.packages[descriptor].version.clone(),
+				transitive_packages: installed.len().saturating_sub(1),
+				tarball_size: 0,
+				unpacked_size: 0,
+				missing_from_store: 0,
+			};
+
+			for (name, version, resolved, sha512) in installed {
+				match measure(name, version, resolved, sha512) {
+					Some((tarball_size, unpacked_size)) => {
+						cost.tarball_size += tarball_size;
+						cost.unpacked_size += unpacked_size;
+					}
+					None => cost.missing_from_store += 1,
+				}
+			}
+
+			cost
+		})
+		.collect()
+}
+
+fn duplicates(lock: &KirboLock, graph: &DependencyGraph) -> Vec<Duplicate> {
+	let mut by_name = BTreeMap::<&str, BTreeMap<&str, Vec<&str>>>::new();
+	for (descriptor, package) in &lock.packages {
+		if package.resolved.starts_with("workspace:") {
+			continue;
+		}
+		by_name
+			.entry(split_descriptor(descriptor).0)
+			.or_default()
+			.entry(package.version.as_str())
+			.or_default()
+			.push(descriptor.as_str());
+	}
+
+	by_name
+		.into_iter()
+		.filter(|(_, versions)| versions.len() > 1)
+		.map(|(name, versions)| {
+			let mut versions = versions
+				.into_iter()
+				.map(|(version, descriptors)| DuplicateVersion {
+					version: version.to_string(),
+					ranges: descriptors
+						.iter()
+						.map(|descriptor| split_descriptor(descriptor).1.to_string())
+						.collect(),
+					requested_by: descriptors
+						.iter()
+						.flat_map(|descriptor| graph.requested_by(descriptor))
+						.collect::<BTreeSet<_>>()
+						.into_iter()
+						.collect(),
+				})
+				.collect::<Vec<_>>();
+			versions.sort_by_cached_key(|it| Version::from_str(&it.version).ok());
+
+			let ranges = versions
+				.iter()
+				.flat_map(|it| &it.ranges)
+				.map(|range| SemverRange::from_str(range).ok())
+				.collect::<Vec<_>>();
+			let satisfied_by = versions
+				.iter()
+				.rev()
+				.find(|it| {
+					let Ok(version) = Version::from_str(&it.version) else {
+						return false;
+					};
+					ranges.iter().all(|range| match range {
+						Some(range) => version.satisfies(range),
+						None => false,
+					})
+				})
+				.map(|it| it.version.clone());
+
+			// Otherwise, suggest moving everything onto the newest version
+			let mut suggestions = vec![];
+			if satisfied_by.is_none() {
+				let newest = versions.last().unwrap();
+				let to = format!("^{}", newest.version);
+				for older in &versions[..versions.len() - 1] {
+					for range in &older.ranges {
+						let descriptor = format!("{}@{}", name, range);
+						for requester in graph.requested_by(&descriptor) {
+							suggestions.push(Suggestion {
+								requester,
+								from: range.clone(),
+								to: to.clone(),
+							});
+						}
+					}
+				}
+			}
+
+			Duplicate {
+				name: name.to_string(),
+				versions,
+				satisfied_by,
+				suggestions,
+			}
+		})
+		.collect()
+}
+
+pub fn main(_options: Options) -> anyhow::Result<()> {
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let lock = KirboLock::read(workspace.root())?
+		.ok_or_else(|| anyhow!("no Kirbo.lock found, try running `kirbo install` first"))?;
+	let graph = DependencyGraph::new(&lock, &workspace);
+	let store = Store::default();
+
+	let report = Report {
+		dependencies: dependency_costs(&lock, &workspace, &graph, &store),
+		duplicates: duplicates(&lock, &graph),
+	};
+
+	if options::global().json {
+		println!("{}", serde_json::to_string_pretty(&report)?);
+		return Ok(());
+	}
+
+	if !options::global().silent {
+		println!("{}", "kirbo insight".bright_magenta().bold());
+	}
+	println!("========================================");
+	println!("dependencies:");
+	let width = report
+		.dependencies
+		.iter()
+		.map(|it| it.name.len() + it.version.len() + 1)
+		.max()
+		.unwrap_or(0);
+	for cost in &report.dependencies {
+		let missing = match cost.missing_from_store {
+			0 => "".to_string(),
+			missing => format!(" ({} not downloaded)", missing)
+				.yellow()
+				.to_string(),
+		};
+		println!(
+			"  {:width$}  {:>4} transitive  {:>9} tarball  {:>9} unpacked{}",
+			format!("{}@{}", cost.name, cost.version),
+			cost.transitive_packages,
+			format_size(cost.tarball_size),
+			format_size(cost.unpacked_size),
+			missing,
+			width = width
+		);
+	}
+	println!("========================================");
+
+	if report.duplicates.is_empty() {
+		println!("no packages are installed at more than one version");
+		return Ok(());
+	}
+
+	println!("duplicates:");
+	for duplicate in &report.duplicates {
+		println!("  {}", duplicate.name.bright_magenta().bold());
+		for version in &duplicate.versions {
+			println!(
+				"    {} ← {} {}",
+				version.version.bold(),
+				version.ranges.join(", "),
+				format!("(from {})", version.requested_by.join(", ")).dimmed()
+			);
+		}
+
+		if let Some(version) = &duplicate.satisfied_by {
+			println!(
+				"    {} every range accepts {}, so reinstalling should deduplicate it",
+				"→".green(),
+				version
+			);
+		}
+		for suggestion in &duplicate.suggestions {
+			println!(
+				"    {} bump {}@{} to {} in {}",
+				"→".green(),
+				duplicate.name,
+				suggestion.from,
+				suggestion.to,
+				suggestion.requester
+			);
+		}
+	}
+	println!("========================================");
+
+	Ok(())
+}
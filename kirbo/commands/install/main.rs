@@ -1,50 +1,672 @@
+use anyhow::anyhow;
 use colored::Colorize;
+use kirbo_workspace::Package;
+use kirbo_workspace::Workspace;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
+use super::options::NewPackage;
 use super::options::Options;
-use crate::npm::PackageJson;
+use crate::config;
+use crate::config::ScriptPolicy;
+use crate::config::CONFIG;
+use crate::engines;
+use crate::layout;
+use crate::linker;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::manifest;
 use crate::options;
+use crate::patch;
+use crate::platform::Platform;
+use crate::resolver::PeerConflict;
+use crate::resolver::Resolution;
+use crate::resolver::ResolvedPackage;
+use crate::resolver::ResolvedPackages;
 use crate::resolver::Resolver;
+use crate::sandbox;
+use crate::scripts;
+use crate::specifier::DependencySpecifier;
+use crate::store::sha512_base64;
+use crate::store::Store;
+use crate::workspace;
 
-pub async fn main(options: options::Options) -> anyhow::Result<()> {
-	println!("{}", "kirbo install".bright_magenta().bold());
+/// Passes along the flags which override settings. This has to happen before the
+/// configuration is loaded.
+pub fn set_flags(options: &Options) -> anyhow::Result<()> {
+	for (key, set) in [
+		("strict-peer-deps", options.strict_peer_deps),
+		("engine-strict", options.engine_strict),
+		("ignore-package-manager", options.force),
+	] {
+		if set {
+			config::set_flag(key, "true")?;
+		}
+	}
+	Ok(())
+}
 
-	let options = Options::try_from(&*options.remaining_args)?;
-	let package = serde_json::from_str::<PackageJson>(
-		&fs::read_to_string(env::current_dir().unwrap().join("package.json")).unwrap(),
-	)
-	.unwrap();
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	install(&env::current_dir()?, options).await
+}
 
-	println!("{:?}", &options.packages_to_add);
+/// Installs the dependencies of the workspace containing `dir`
+pub async fn install(dir: &Path, options: Options) -> anyhow::Result<()> {
+	// Nothing but JSON can be printed when installing for a command with JSON output
+	let global = options::global();
+	let quiet = options.quiet || global.silent || global.json;
+	if !quiet {
+		println!("{}", "kirbo install".bright_magenta().bold());
+	}
 
-	println!("========================================");
-	println!(
-		"{}@{}",
-		package.name.as_ref().unwrap(),
-		package.version.as_ref().unwrap()
-	);
-	println!("========================================\n\n\n");
-
-	let joined_dependencies = package
+	let workspace = Workspace::new(dir)?;
+	engines::check_package_manager(&workspace.root_package().1, CONFIG.ignore_package_manager)?;
+
+	// New packages are written to their package.json first, so the workspace has to be
+	// read again afterwards
+	let new_packages = options.packages_to_add();
+	let workspace = match new_packages.is_empty() {
+		true => workspace,
+		false => {
+			add_packages(&workspace, options.workspace.as_deref(), &new_packages).await?;
+			Workspace::new(dir)?
+		}
+	};
+	let package = &workspace.root_package().1;
+
+	if !quiet {
+		println!("========================================");
+		println!(
+			"{}@{}",
+			package.name.as_deref().unwrap_or("(unnamed)"),
+			package.version.as_deref().unwrap_or("0.0.0")
+		);
+		println!("========================================\n\n\n");
+	}
+
+	let mut joined_dependencies = package
 		.dependencies
 		.clone()
 		.into_iter()
 		.chain(package.dev_dependencies.clone())
 		.collect::<HashMap<_, _>>();
+	// Every workspace package gets linked, even if nothing depends on it, unless the
+	// root asks for something else by the same name
+	for name in workspace
+		.members()
+		.filter_map(|package_json| package_json.1.name.clone())
+	{
+		joined_dependencies
+			.entry(name)
+			.or_insert_with(|| "workspace:*".to_string());
+	}
+
+	let current_platform = Platform::current();
+	let platform = Platform {
+		os: options.os.unwrap_or_else(|| current_platform.os.clone()),
+		cpu: options.cpu.unwrap_or_else(|| current_platform.cpu.clone()),
+		libc: options.libc.or_else(|| current_platform.libc.clone()),
+	};
+	if platform != current_platform && !quiet {
+		println!(
+			"installing for {} {}{}",
+			platform.os,
+			platform.cpu,
+			platform
+				.libc
+				.as_ref()
+				.map(|libc| format!(" ({})", libc))
+				.unwrap_or_default()
+		);
+	}
+
+	// The project's own packages need a node that works for them too
+	let mut node_ranges = workspace
+		.members()
+		.chain([workspace.root_package()])
+		.filter_map(|package_json| {
+			let package = &package_json.1;
+			let range = package.engines.get("node")?;
+			let name = package.name.as_deref().unwrap_or("package.json");
+			Some((name.to_string(), range.clone()))
+		})
+		.collect::<BTreeMap<_, _>>();
+
+	let settings = package.kirbo.clone().unwrap_or_default();
+	let root = workspace.root().to_path_buf();
+	let mut resolver = Resolver::new(workspace)?.with_platform(platform.clone());
+	if quiet {
+		resolver = resolver.quiet();
+	}
+	// A lock that can't be read is about to be replaced anyway
+	if let Some(lock) = KirboLock::read(&root).ok().flatten() {
+		resolver = resolver.with_lock(&lock);
+	}
+	let mut resolved = resolver.resolve(&joined_dependencies).await?;
+
+	let peer_conflicts = resolver.peer_conflicts();
+	if !peer_conflicts.is_empty() {
+		print_peer_conflicts(peer_conflicts);
+		if CONFIG.strict_peer_deps {
+			return Err(anyhow!(
+				"unable to satisfy peer dependencies (remove --strict-peer-deps to install anyway)"
+			));
+		}
+	}
+
+	// Failing to download an optional dependency isn't worth failing the install over
+	let required = reachable(&resolved, &joined_dependencies, false);
+	let store = Store::default();
+	let mut tarballs = HashMap::new();
+	let mut failed = vec![];
+	let mut substitutions = vec![];
+
+	// Tarballs are downloaded alongside each other, but each version only once, even
+	// if more than one descriptor resolved to it
+	let mut downloads = HashMap::<(&str, &str), Vec<&String>>::new();
+	let semaphore = Arc::new(Semaphore::new(CONFIG.concurrency));
+	let mut fetches = JoinSet::new();
+	for (descriptor, package) in &resolved {
+		match &package.resolution {
+			Resolution::Registry { tarball, integrity } => {
+				let waiting = downloads
+					.entry((&package.name, &package.version))
+					.or_default();
+				waiting.push(descriptor);
+				if waiting.len() > 1 {
+					continue;
+				}
+
+				let sha512 = integrity
+					.as_deref()
+					.and_then(|integrity| integrity.strip_prefix("sha512-"))
+					.map(str::to_string);
+				let (name, version, tarball) = (
+					package.name.clone(),
+					package.version.clone(),
+					tarball.clone(),
+				);
+				let (store, semaphore) = (store.clone(), semaphore.clone());
+				fetches.spawn(async move {
+					let _permit = semaphore.acquire_owned().await;
+					let fetched = store
+						.fetch(&name, &version, &tarball, sha512.as_deref())
+						.await;
+					(name, version, fetched)
+				});
+			}
+			Resolution::Firewall { substitution } => {
+				if !substitutions.contains(substitution) {
+					substitutions.push(*substitution);
+				}
+			}
+			// These were already packed into the store while resolving
+			Resolution::Git { commit, .. } => {
+				tarballs.insert(descriptor.clone(), store.source_path(commit));
+			}
+			Resolution::Tarball { integrity, .. } => {
+				let sha512 = integrity.strip_prefix("sha512-").unwrap_or(integrity);
+				tarballs.insert(descriptor.clone(), store.source_path(sha512));
+			}
+			Resolution::Workspace { .. } | Resolution::Directory { .. } => (),
+		}
+	}
+
+	while let Some(fetch) = fetches.join_next().await {
+		let (name, version, fetched) = fetch?;
+		let waiting = &downloads[&(name.as_str(), version.as_str())];
+		match fetched {
+			Ok(path) => {
+				for descriptor in waiting {
+					tarballs.insert((*descriptor).clone(), path.clone());
+				}
+			}
+			Err(err) if waiting.iter().all(|it| !required.contains(*it)) => {
+				for descriptor in waiting {
+					println!(
+						"{} skipping optional {}: {}",
+						"warning:".yellow(),
+						descriptor,
+						err
+					);
+					failed.push((*descriptor).clone());
+				}
+			}
+			Err(err) => return Err(err),
+		}
+	}
+
+	if !failed.is_empty() {
+		resolved.retain(|descriptor, _| !failed.contains(descriptor));
+		for package in resolved.values_mut() {
+			package
+				.dependencies
+				.retain(|name, range| !failed.contains(&format!("{}@{}", name, range)));
+		}
+		let installed = reachable(&resolved, &joined_dependencies, true);
+		resolved.retain(|descriptor, _| installed.contains(descriptor));
+	}
+
+	let layout = layout::plan(&resolved, &joined_dependencies, &root, CONFIG.linker);
+	let mut applied_patches = HashMap::new();
+	for (path, descriptor) in &layout {
+		let package = &resolved[descriptor];
+		match &package.resolution {
+			Resolution::Workspace { path: target } | Resolution::Directory { path: target, .. } => {
+				linker::link_package(&root, &root.join(path), target)?
+			}
+			Resolution::Firewall { substitution } => linker::write_package(
+				&root.join(path),
+				&[
+					("package.json", &substitution.package_json()),
+					("index.js", substitution.source),
+				],
+			)?,
+			Resolution::Registry { .. } | Resolution::Git { .. } | Resolution::Tarball { .. } => {
+				if global.verbose && !quiet {
+					println!(
+						"{} {}@{} {} {}",
+						"extracting".dimmed(),
+						package.name,
+						package.version,
+						"into".dimmed(),
+						path.display()
+					);
+				}
+				linker::extract_package(&tarballs[descriptor], &root.join(path))?;
+
+				let patches = &settings.patched_dependencies;
+				if let Some((key, patch_path)) = patch::find_patch(patches, &package.name, &package.version)
+				{
+					let text = fs::read_to_string(root.join(patch_path))
+						.map_err(|err| anyhow!("failed to read {}: {}", patch_path, err))?;
+					patch::apply(&text, &root.join(path)).map_err(|err| {
+						anyhow!(
+							"failed to apply {} to {}@{}: {}\nrun `kirbo patch {}` to update it",
+							patch_path,
+							package.name,
+							package.version,
+							err,
+							package.name
+						)
+					})?;
+					applied_patches.insert(descriptor.clone(), (key, sha512_base64(text.as_bytes())));
+				}
+			}
+		}
+	}
+
+	// A patch that doesn't match anything was probably made for a version we've since
+	// upgraded away from, and quietly dropping it would bring the bug it fixed back
+	for (key, patch_path) in &settings.patched_dependencies {
+		if !applied_patches.values().any(|(applied, _)| applied == key) {
+			return Err(anyhow!(
+				"{} patches {}, but nothing installed matches it\nrun `kirbo patch {}` to make a new patch, and remove the old one from \"kirbo\": {{ \"patchedDependencies\" }}",
+				patch_path,
+				key,
+				split_descriptor(key).0
+			));
+		}
+	}
+
+	for (path, descriptor) in &layout {
+		let package = &resolved[descriptor];
+		if !matches!(
+			package.resolution,
+			Resolution::Registry { .. } | Resolution::Git { .. } | Resolution::Tarball { .. }
+		) {
+			continue;
+		}
+		let package_json = fs::read_to_string(root.join(path).join("package.json"))
+			.ok()
+			.and_then(|text| serde_json::from_str::<Package>(&text).ok());
+		if let Some(range) = package_json.and_then(|it| it.engines.get("node").cloned()) {
+			node_ranges.insert(format!("{}@{}", package.name, package.version), range);
+		}
+	}
+	check_node_ranges(&node_ranges, CONFIG.engine_strict)?;
+
+	// Executables of direct dependencies are linked into `node_modules/.bin/`
+	for name in joined_dependencies.keys() {
+		let dir = root.join("node_modules").join(name);
+		let package_json = fs::read_to_string(dir.join("package.json"))
+			.ok()
+			.and_then(|text| serde_json::from_str::<Package>(&text).ok());
+		if let Some(package_json) = package_json {
+			linker::link_bins(&root, &dir, &package_json)?;
+		}
+	}
+
+	// Dependencies get to run their scripts before their dependents, like they would
+	// with npm, but only if they've been explicitly allowed to.
+	// Scripts for a different platform wouldn't work here anyway.
+	let mut ran = vec![];
+	let mut skipped = vec![];
+	let order = match platform == current_platform {
+		true => dependency_order(&resolved),
+		false => vec![],
+	};
+	for descriptor in order {
+		let package = &resolved[descriptor];
+		if !matches!(
+			package.resolution,
+			Resolution::Registry { .. } | Resolution::Git { .. } | Resolution::Tarball { .. }
+		) {
+			continue;
+		}
+
+		for (path, _) in layout.iter().filter(|(_, it)| *it == descriptor) {
+			let dir = root.join(path);
+			let package_json: Package =
+				serde_json::from_str(&fs::read_to_string(dir.join("package.json"))?)?;
+			let lifecycle = scripts::lifecycle_scripts(&package_json);
+			if lifecycle.is_empty() {
+				continue;
+			}
+
+			let id = format!("{}@{}", package.name, package.version);
+			let events = lifecycle
+				.iter()
+				.map(|(event, _)| *event)
+				.collect::<Vec<_>>()
+				.join(", ");
+			let permission = script_permission(descriptor, package);
+			let allowed = match CONFIG.script_policy {
+				ScriptPolicy::AllowList => settings.allow_scripts.contains(&permission),
+				ScriptPolicy::All => true,
+				ScriptPolicy::None => false,
+			};
+			if !allowed {
+				match permission == package.name {
+					true => skipped.push(format!("{} ({})", id, events)),
+					false => skipped.push(format!("{} ({}) as {:?}", id, events, permission)),
+				}
+				continue;
+			}
+
+			for (event, script) in lifecycle {
+				if !quiet {
+					println!("{} {} {}", id.bold(), event.dimmed(), script);
+				}
+				let mut command = scripts::command(&dir, script, &[]);
+				command
+					.env("npm_lifecycle_event", event)
+					.env("npm_package_name", &package.name)
+					.env("npm_package_version", &package.version)
+					.env("npm_config_registry", &CONFIG.registry);
+				sandbox::confine(&mut command, &dir)?;
 
-	let mut resolver = Resolver::default();
-	let installed_dependencies = resolver
-		.resolve_dependencies(&joined_dependencies, 0)
-		.await?;
+				let status = command.status()?;
+				if !status.success() {
+					return Err(anyhow!("{} script of {} failed ({})", event, id, status));
+				}
+			}
+			ran.push(format!("{} ({})", id, events));
+		}
+	}
+
+	let mut lock = KirboLock::from_resolved(&resolved, &root);
+	for (descriptor, (_, hash)) in applied_patches {
+		if let Some(package) = lock.packages.get_mut(&descriptor) {
+			package.patch = hash;
+		}
+	}
+	lock.write(&root)?;
+
+	if quiet {
+		return Ok(());
+	}
 
 	println!("========================================");
 	println!("summary:");
-	println!("  total dependencies: {}", installed_dependencies.len());
+	println!("  total dependencies: {}", resolved.len());
+	if !ran.is_empty() {
+		println!("  ran lifecycle scripts (sandboxed):");
+		for package in ran {
+			println!("    {}", package);
+		}
+	}
+	if !skipped.is_empty() {
+		match CONFIG.script_policy {
+			ScriptPolicy::None => println!("  skipped lifecycle scripts (script-policy is none):"),
+			_ => println!(
+				"  skipped lifecycle scripts (add to \"kirbo\": {{ \"allowScripts\" }} to run them):"
+			),
+		}
+		for package in skipped {
+			println!("    {}", package.yellow());
+		}
+	}
+	if !substitutions.is_empty() {
+		println!("  substituted by kirbo_firewall:");
+		for substitution in substitutions {
+			println!(
+				"    {}@{} ({})",
+				substitution.name,
+				substitution.version,
+				substitution.reason.as_str()
+			);
+		}
+	}
 	println!("========================================");
 
-	println!("{:#?}", installed_dependencies);
+	Ok(())
+}
+
+/// What a package has to be listed as in `"kirbo": { "allowScripts" }` to run its
+/// scripts. Registry packages go by their real name, so that an `npm:` alias can't
+/// borrow the name of something that's allowed. Anything else has to be allowed by
+/// the exact source it comes from, like `github:org/repo#v1.0.0`.
+fn script_permission(descriptor: &str, package: &ResolvedPackage) -> String {
+	let (name, range) = split_descriptor(descriptor);
+	match (&package.resolution, DependencySpecifier::parse(range)) {
+		(Resolution::Registry { .. }, DependencySpecifier::Alias { name, .. }) => name,
+		(Resolution::Registry { .. }, _) => name.to_string(),
+		_ => range.to_string(),
+	}
+}
+
+/// Adds packages to the package.json of the target package, at the range they were
+/// given with, or otherwise at `^` their latest version. Other members of the
+/// workspace are added as `workspace:*`.
+async fn add_packages(
+	workspace: &Workspace,
+	target: Option<&str>,
+	new_packages: &[NewPackage],
+) -> anyhow::Result<()> {
+	let package_json = workspace::target_package(workspace, target)?;
+	let path = &package_json.0;
+	let mut text = fs::read_to_string(path)?;
+	let mut resolver = Resolver::default().quiet();
+
+	for new_package in new_packages {
+		let (field, descriptor) = match new_package {
+			NewPackage::Dependency(descriptor) => ("dependencies", descriptor),
+			NewPackage::DevDependency(descriptor) => ("devDependencies", descriptor),
+			NewPackage::TestDependency(descriptor) => ("testDependencies", descriptor),
+			NewPackage::OptionalDependency(descriptor) => ("optionalDependencies", descriptor),
+		};
+		let (name, range) = split_descriptor(descriptor);
+		let range = match range {
+			"" if workspace.get(name).is_some() => "workspace:*".to_string(),
+			"" => {
+				let latest = resolver
+					.query_package(name)
+					.await?
+					.dist_tags
+					.get("latest")
+					.cloned()
+					.ok_or_else(|| anyhow!("{} has no latest version", name))?;
+				format!("^{}", latest)
+			}
+			range => range.to_string(),
+		};
+
+		let edit = |text: &str, keys: &[&str], json: &str| {
+			manifest::set_json(text, keys, json)
+				.ok_or_else(|| anyhow!("failed to add {} to {}", name, path.display()))
+		};
+		if manifest::find_value(&text, &[field]).is_none() {
+			text = edit(&text, &[field], "{}")?;
+		}
+		text = edit(&text, &[field, name], &serde_json::to_string(&range)?)?;
+		println!(
+			"{} {}@{} to {} of {}",
+			"adding".dimmed(),
+			name,
+			range,
+			field,
+			path.display()
+		);
+	}
+
+	fs::write(path, text)?;
+	Ok(())
+}
+
+/// Warns about every package whose `engines.node` doesn't include the version of
+/// node we'll be running with, or fails if `strict` is set
+fn check_node_ranges(ranges: &BTreeMap<String, String>, strict: bool) -> anyhow::Result<()> {
+	if ranges.is_empty() {
+		return Ok(());
+	}
+	let Some(node) = engines::node_version() else {
+		println!(
+			"{} couldn't find node, so \"engines\" weren't checked",
+			"warning:".yellow()
+		);
+		return Ok(());
+	};
+
+	let unsupported = ranges
+		.iter()
+		.filter(|(_, range)| !engines::satisfies(&node, range))
+		.collect::<Vec<_>>();
+	if unsupported.is_empty() {
+		return Ok(());
+	}
 
+	println!("{}", format!("unsupported by node {}:", node).yellow());
+	for (package, range) in &unsupported {
+		println!("  {} {} wants node {}", "✗".red(), package.bold(), range);
+	}
+	if strict {
+		return Err(anyhow!(
+			"node {} isn't supported by every package (remove --engine-strict to install anyway)",
+			node
+		));
+	}
 	Ok(())
 }
+
+/// Prints each package whose peer dependencies couldn't be satisfied, along with
+/// everything that provided the wrong version
+fn print_peer_conflicts(conflicts: &[PeerConflict]) {
+	let mut grouped = BTreeMap::<(&str, &str, &str), Vec<&PeerConflict>>::new();
+	for conflict in conflicts {
+		grouped
+			.entry((&conflict.package, &conflict.peer, &conflict.range))
+			.or_default()
+			.push(conflict);
+	}
+
+	println!("{}", "unsatisfied peer dependencies:".yellow());
+	for ((package, peer, range), providers) in grouped {
+		println!(
+			"  {} {} wants {}@{}",
+			"✗".red(),
+			package.bold(),
+			peer,
+			range
+		);
+		for (i, conflict) in providers.iter().enumerate() {
+			let branch = if i + 1 == providers.len() {
+				"└"
+			} else {
+				"├"
+			};
+			println!(
+				"    {} but {} provides {}@{}",
+				branch,
+				conflict.provider,
+				conflict.peer,
+				conflict.provided_version.red()
+			);
+		}
+	}
+}
+
+/// Every resolved descriptor, ordered so that dependencies come before the packages
+/// which depend on them (as far as cycles allow)
+fn dependency_order(resolved: &ResolvedPackages) -> Vec<&str> {
+	fn visit<'a>(
+		resolved: &'a ResolvedPackages,
+		descriptor: &'a str,
+		visited: &mut HashSet<&'a str>,
+		order: &mut Vec<&'a str>,
+	) {
+		let Some((descriptor, package)) = resolved.get_key_value(descriptor) else {
+			return;
+		};
+		if !visited.insert(descriptor) {
+			return;
+		}
+		for (name, range) in package
+			.dependencies
+			.iter()
+			.chain(&package.peer_dependencies)
+		{
+			if let Some((dependency, _)) = resolved.get_key_value(&format!("{}@{}", name, range)) {
+				visit(resolved, dependency, visited, order);
+			}
+		}
+		order.push(descriptor);
+	}
+
+	let mut visited = HashSet::new();
+	let mut order = vec![];
+	for descriptor in resolved.keys() {
+		visit(resolved, descriptor, &mut visited, &mut order);
+	}
+	order
+}
+
+/// Descriptors of every package that can be reached from `roots`, optionally
+/// ignoring optional dependencies
+fn reachable(
+	resolved: &ResolvedPackages,
+	roots: &HashMap<String, String>,
+	include_optional: bool,
+) -> HashSet<String> {
+	let mut found = HashSet::new();
+	let mut queue = roots
+		.iter()
+		.map(|(name, range)| format!("{}@{}", name, range))
+		.collect::<Vec<_>>();
+
+	while let Some(descriptor) = queue.pop() {
+		let Some(package) = resolved.get(&descriptor) else {
+			continue;
+		};
+		if !found.insert(descriptor) {
+			continue;
+		}
+		queue.extend(
+			package
+				.dependencies
+				.iter()
+				.filter(|(name, _)| include_optional || !package.optional_dependencies.contains(*name))
+				.chain(&package.peer_dependencies)
+				.map(|(name, range)| format!("{}@{}", name, range)),
+		);
+	}
+
+	found
+}
@@ -1,14 +1,45 @@
-use std::convert::TryFrom;
-use std::process::exit;
+use clap::Args;
 
-#[derive(Clone, Debug, Default)]
-struct OptionsBuilder {
-	packages_to_add: Vec<NewPackage>,
-}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Args)]
+#[command(after_help = include_str!("./help.txt"))]
 pub struct Options {
-	pub packages_to_add: Vec<NewPackage>,
+	/// Packages to add to `dependencies`, as `name[@range]`
+	#[arg(value_name = "packages")]
+	pub packages: Vec<String>,
+	/// Add a package to `devDependencies`
+	#[arg(short = 'D', long = "dev", value_name = "pkg")]
+	pub dev: Vec<String>,
+	/// Add a package to `testDependencies`
+	#[arg(short = 'T', long = "test", value_name = "pkg")]
+	pub test: Vec<String>,
+	/// Add a package to `optionalDependencies`
+	#[arg(short = 'O', long = "optional", value_name = "pkg")]
+	pub optional: Vec<String>,
+	/// Add packages to this workspace member, instead of the nearest package
+	#[arg(short, long, value_name = "name")]
+	pub workspace: Option<String>,
+	/// Install for a different operating system (linux, darwin, win32...)
+	#[arg(long, value_name = "os")]
+	pub os: Option<String>,
+	/// Install for a different cpu architecture (x64, arm64...)
+	#[arg(long, value_name = "cpu")]
+	pub cpu: Option<String>,
+	/// Install for a different libc (glibc, musl)
+	#[arg(long, value_name = "libc")]
+	pub libc: Option<String>,
+	/// Fail the install if a peer dependency can't be satisfied
+	#[arg(long)]
+	pub strict_peer_deps: bool,
+	/// Fail the install if a package doesn't support the current version of node
+	#[arg(long)]
+	pub engine_strict: bool,
+	/// Install even if `packageManager` says the project belongs to yarn or pnpm
+	#[arg(long)]
+	pub force: bool,
+	/// Don't print any progress, for commands which install on the way to doing
+	/// something else
+	#[arg(skip)]
+	pub quiet: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -19,93 +50,17 @@ pub enum NewPackage {
 	OptionalDependency(String),
 }
 
-impl TryFrom<OptionsBuilder> for Options {
-	type Error = anyhow::Error;
-
-	fn try_from(builder: OptionsBuilder) -> Result<Self, Self::Error> {
-		Ok(Options {
-			// input: builder.input.ok_or(anyhow!("no input provided"))?,
-			// input: env::current_dir()?,
-			packages_to_add: builder.packages_to_add,
-		})
-	}
-}
-
-impl<S> TryFrom<&[S]> for Options
-where
-	S: AsRef<str>,
-{
-	type Error = anyhow::Error;
-
-	fn try_from(args: &[S]) -> Result<Self, Self::Error> {
-		let mut options = OptionsBuilder::default();
-
-		if args.len() == 1 {
-			match args[0].as_ref() {
-				"-h" | "-help" | "--help" | "-?" | "help" => {
-					print!("{}", include_str!("./help.txt"));
-					exit(0);
-				}
-				_ => (),
-			}
-		}
-
-		let mut args = args.into_iter();
-
-		while let Some(arg) = args.next() {
-			let arg = arg.as_ref();
-
-			// `a` should add "a" to `dependencies`
-			if arg.starts_with(|a| char::is_ascii_alphanumeric(&a)) {
-				options
-					.packages_to_add
-					.push(NewPackage::Dependency(arg.to_string()));
-				continue;
-			}
-
-			// `-Da` should add "a" to `devDependencies`
-			if arg.len() >= 3
-				&& arg.starts_with("-D")
-				&& arg.bytes().nth(2).unwrap().is_ascii_alphanumeric()
-			{
-				options
-					.packages_to_add
-					.push(NewPackage::DevDependency(arg[2..].to_string()));
-				continue;
-			}
-
-			// `-Ta` should add "a" to `testDependencies`
-			if arg.len() >= 3
-				&& arg.starts_with("-T")
-				&& arg.bytes().nth(2).unwrap().is_ascii_alphanumeric()
-			{
-				options
-					.packages_to_add
-					.push(NewPackage::TestDependency(arg[2..].to_string()));
-				continue;
-			}
-
-			// `-Oa` should add "a" to `optionalDependencies`
-			if arg.len() >= 3
-				&& arg.starts_with("-O")
-				&& arg.bytes().nth(2).unwrap().is_ascii_alphanumeric()
-			{
-				options
-					.packages_to_add
-					.push(NewPackage::OptionalDependency(arg[2..].to_string()));
-				continue;
-			}
-
-			if (arg.len() >= 2 && arg.starts_with('-')) || (arg.len() >= 3 && arg.starts_with("--")) {
-				match arg {
-					_ => {
-						println!("unrecognized option: {}", arg);
-						exit(1);
-					}
-				}
-			}
-		}
-
-		options.try_into()
+impl Options {
+	/// Every package that should be added before installing
+	pub fn packages_to_add(&self) -> Vec<NewPackage> {
+		let packages = self.packages.iter().cloned().map(NewPackage::Dependency);
+		let dev = self.dev.iter().cloned().map(NewPackage::DevDependency);
+		let test = self.test.iter().cloned().map(NewPackage::TestDependency);
+		let optional = self
+			.optional
+			.iter()
+			.cloned()
+			.map(NewPackage::OptionalDependency);
+		packages.chain(dev).chain(test).chain(optional).collect()
 	}
 }
@@ -0,0 +1,186 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use serde::Serialize;
+use std::env;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::exit::ExitCode;
+use crate::lock::KirboLock;
+use crate::options;
+use crate::resolver::Resolver;
+use crate::semver::Version;
+use crate::specifier::DependencySpecifier;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OutdatedDependency {
+	name: String,
+	range: String,
+	/// The workspace package which depends on it
+	dependent: String,
+	/// The locked version, or `None` if it hasn't been installed
+	current: Option<String>,
+	wanted: Option<String>,
+	latest: Option<String>,
+	/// Whether `latest` is a new major version compared to what's installed
+	major: bool,
+}
+
+fn parse_version(version: &Option<String>) -> Option<Version> {
+	version
+		.as_deref()
+		.and_then(|version| Version::from_str(version).ok())
+}
+
+pub async fn main(_options: Options) -> anyhow::Result<()> {
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let lock = KirboLock::read(workspace.root())?
+		.ok_or_else(|| anyhow!("no Kirbo.lock found, try running `kirbo install` first"))?;
+	let mut resolver = Resolver::default().quiet();
+
+	let mut outdated = vec![];
+	for package_json in workspace.members().chain([workspace.root_package()]) {
+		let dependent = package_json
+			.1
+			.name
+			.clone()
+			.unwrap_or_else(|| "package.json".to_string());
+		let mut dependencies = package_json
+			.1
+			.dependencies
+			.iter()
+			.chain(&package_json.1.dev_dependencies)
+			.collect::<Vec<_>>();
+		dependencies.sort();
+
+		for (name, range) in dependencies {
+			// Only packages from the registry have newer versions to compare against
+			let (registry_name, registry_range) = match DependencySpecifier::parse(range) {
+				DependencySpecifier::Range(range) => (name.clone(), range),
+				DependencySpecifier::Alias { name, range } => (name, range),
+				_ => continue,
+			};
+			if workspace.get(name).is_some() {
+				continue;
+			}
+
+			let current = lock
+				.packages
+				.get(&format!("{}@{}", name, range))
+				.map(|package| package.version.clone());
+			let wanted = resolver
+				.newest_version(&registry_name, &registry_range)
+				.await;
+			let latest = resolver
+				.query_package(&registry_name)
+				.await?
+				.dist_tags
+				.get("latest")
+				.cloned();
+
+			let installed = parse_version(&current);
+			let behind = |version: &Option<String>| match (&installed, parse_version(version)) {
+				(Some(installed), Some(version)) => version > *installed,
+				(None, Some(_)) => true,
+				_ => false,
+			};
+			if !behind(&wanted) && !behind(&latest) {
+				continue;
+			}
+
+			let major = match (
+				installed.or_else(|| parse_version(&wanted)),
+				parse_version(&latest),
+			) {
+				(Some(installed), Some(latest)) => latest.major > installed.major,
+				_ => false,
+			};
+			outdated.push(OutdatedDependency {
+				name: name.clone(),
+				range: range.clone(),
+				dependent: dependent.clone(),
+				current,
+				wanted,
+				latest,
+				major,
+			});
+		}
+	}
+
+	if options::global().json {
+		println!("{}", serde_json::to_string_pretty(&outdated)?);
+	} else {
+		print_table(&outdated);
+	}
+
+	if !outdated.is_empty() {
+		return Err(ExitCode(1).into());
+	}
+	Ok(())
+}
+
+fn print_table(outdated: &[OutdatedDependency]) {
+	if !options::global().silent {
+		println!("{}", "kirbo outdated".bright_magenta().bold());
+	}
+	if outdated.is_empty() {
+		println!("everything is up to date");
+		return;
+	}
+
+	let missing = "missing".to_string();
+	let rows = outdated
+		.iter()
+		.map(|it| {
+			[
+				&it.name,
+				it.current.as_ref().unwrap_or(&missing),
+				it.wanted.as_ref().unwrap_or(&missing),
+				it.latest.as_ref().unwrap_or(&missing),
+				&it.dependent,
+			]
+		})
+		.collect::<Vec<_>>();
+	let header = ["package", "current", "wanted", "latest", "dependent"];
+	let widths = (0..header.len())
+		.map(|column| {
+			rows
+				.iter()
+				.map(|row| row[column].len())
+				.chain([header[column].len()])
+				.max()
+				.unwrap_or(0)
+		})
+		.collect::<Vec<_>>();
+
+	println!("========================================");
+	let cells = header
+		.iter()
+		.zip(&widths)
+		.map(|(cell, width)| format!("{:width$}", cell, width = width))
+		.collect::<Vec<_>>();
+	println!("  {}", cells.join("  ").bold());
+
+	for (row, dependency) in rows.iter().zip(outdated) {
+		let cells = row
+			.iter()
+			.zip(&widths)
+			.map(|(cell, width)| format!("{:width$}", cell, width = width))
+			.collect::<Vec<_>>();
+		let wanted = match dependency.wanted != dependency.current {
+			true => cells[2].green(),
+			false => cells[2].normal(),
+		};
+		let latest = match dependency.major {
+			true => cells[3].red().bold(),
+			false => cells[3].yellow(),
+		};
+		println!(
+			"  {}  {}  {}  {}  {}",
+			cells[0], cells[1], wanted, latest, cells[4]
+		);
+	}
+	println!("========================================");
+}
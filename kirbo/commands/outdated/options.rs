@@ -0,0 +1,5 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {}
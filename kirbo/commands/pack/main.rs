@@ -0,0 +1,68 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Package;
+use kirbo_workspace::Workspace;
+use std::env;
+use std::fs;
+
+use super::options::Options;
+use crate::options;
+use crate::pack;
+use crate::pack::Packed;
+use crate::scripts;
+use crate::store::format_size;
+
+/// Lists the contents of a tarball, along with its size and integrity
+pub fn print_packed(packed: &Packed) {
+	println!("========================================");
+	println!("{}@{}", packed.name.bold(), packed.version);
+	for file in &packed.files {
+		println!("  {:>8}  {}", format_size(file.size), file.path);
+	}
+	println!("========================================");
+	println!("filename:      {}", packed.filename());
+	println!(
+		"package size:  {}",
+		format_size(packed.tarball.len() as u64)
+	);
+	println!("unpacked size: {}", format_size(packed.unpacked_size()));
+	println!("shasum:        {}", packed.shasum);
+	println!("integrity:     {}", packed.integrity);
+	println!("total files:   {}", packed.files.len());
+}
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo pack".bright_magenta().bold());
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let dir = workspace.current_package().dir().to_path_buf();
+	let path = dir.join("package.json");
+	let read = || -> anyhow::Result<(String, Package)> {
+		let text = fs::read_to_string(&path)
+			.map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+		let package = serde_json::from_str(&text)?;
+		Ok((text, package))
+	};
+
+	let (_, package) = read()?;
+	if !options.dry_run {
+		scripts::run_event(&dir, &package, "prepack")?;
+	}
+
+	// prepack scripts usually build things, so the package has to be read again
+	let (text, package) = read()?;
+	let packed = pack::pack(&dir, &text)?;
+
+	print_packed(&packed);
+
+	if options.dry_run {
+		return Ok(());
+	}
+
+	fs::write(dir.join(packed.filename()), &packed.tarball)?;
+	scripts::run_event(&dir, &package, "postpack")?;
+
+	Ok(())
+}
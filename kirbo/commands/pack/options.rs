@@ -0,0 +1,9 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// List what would be packed, without running scripts or writing a tarball
+	#[arg(long)]
+	pub dry_run: bool,
+}
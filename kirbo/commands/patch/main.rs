@@ -0,0 +1,66 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use std::env;
+use std::fs;
+
+use super::options::Options;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::lock::LOCK_FILE_NAME;
+use crate::options;
+use crate::patch;
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo patch".bright_magenta().bold());
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let root = workspace.root();
+	let lock = KirboLock::read(root)?.ok_or_else(|| {
+		anyhow!(
+			"there's no {} yet, so run `kirbo install` first",
+			LOCK_FILE_NAME
+		)
+	})?;
+
+	let (name, version) = split_descriptor(&options.package);
+	let package = patch::locked_package(&lock, name, version)?;
+	let id = format!("{}@{}", name, package.version);
+
+	let dir = patch::edit_dir(&id);
+	if dir.exists() {
+		return Err(anyhow!(
+			"{} already exists, so either finish with `kirbo patch-commit {}` or remove it",
+			dir.display(),
+			dir.display()
+		));
+	}
+	patch::extract_locked(name, package, &dir).await?;
+
+	// Start from the existing patch, so that new changes get added to it instead of
+	// replacing it
+	let settings = workspace.root_package().1.kirbo.clone().unwrap_or_default();
+	if let Some((_, path)) = patch::find_patch(&settings.patched_dependencies, name, &package.version)
+	{
+		let applied = fs::read_to_string(root.join(path))
+			.map_err(anyhow::Error::from)
+			.and_then(|text| patch::apply(&text, &dir));
+		if let Err(err) = applied {
+			println!(
+				"{} {} doesn't apply anymore, so starting over: {}",
+				"warning:".yellow(),
+				path,
+				err
+			);
+		}
+	}
+
+	println!("extracted {} into {}", id.bold(), dir.display());
+	println!(
+		"make your changes there, and then run `kirbo patch-commit {}`",
+		dir.display()
+	);
+	Ok(())
+}
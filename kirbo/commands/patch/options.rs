@@ -0,0 +1,9 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// The package to patch, as `name` or `name@version`
+	#[arg(value_name = "package")]
+	pub package: String,
+}
@@ -0,0 +1,116 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Package;
+use kirbo_workspace::Workspace;
+use std::env;
+use std::fs;
+use std::process;
+
+use super::options::Options;
+use crate::commands::install;
+use crate::lock::KirboLock;
+use crate::lock::LOCK_FILE_NAME;
+use crate::manifest;
+use crate::options;
+use crate::patch;
+
+/// Adds `key` to `"kirbo": { "patchedDependencies" }` in the text of a package.json,
+/// creating either object if it doesn't exist yet
+fn register_patch(text: &str, key: &str, path: &str) -> Option<String> {
+	let mut text = text.to_string();
+	if manifest::find_value(&text, &["kirbo"]).is_none() {
+		text = manifest::set_json(&text, &["kirbo"], "{}")?;
+	}
+	if manifest::find_value(&text, &["kirbo", "patchedDependencies"]).is_none() {
+		text = manifest::set_json(&text, &["kirbo", "patchedDependencies"], "{}")?;
+	}
+	manifest::set_json(
+		&text,
+		&["kirbo", "patchedDependencies", key],
+		&serde_json::to_string(path).ok()?,
+	)
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo patch-commit".bright_magenta().bold());
+	}
+
+	let cwd = env::current_dir()?;
+	let dir = cwd.join(&options.dir);
+	let edited = fs::read_to_string(dir.join("package.json"))
+		.map_err(|err| anyhow!("failed to read package.json in {}: {}", dir.display(), err))?;
+	let edited = serde_json::from_str::<Package>(&edited)?;
+	let (Some(name), Some(version)) = (edited.name, edited.version) else {
+		return Err(anyhow!(
+			"package.json in {} needs a name and version",
+			dir.display()
+		));
+	};
+
+	let workspace = Workspace::new(&cwd)?;
+	let root = workspace.root().to_path_buf();
+	let lock = KirboLock::read(&root)?.ok_or_else(|| {
+		anyhow!(
+			"there's no {} yet, so run `kirbo install` first",
+			LOCK_FILE_NAME
+		)
+	})?;
+	let package = patch::locked_package(&lock, &name, &version)?;
+
+	let original = env::temp_dir().join(format!("kirbo-patch-original-{}", process::id()));
+	let diff = patch::extract_locked(&name, package, &original)
+		.await
+		.and_then(|_| patch::diff(&original, &dir));
+	let _ = fs::remove_dir_all(&original);
+	let diff = diff?;
+	if diff.is_empty() {
+		return Err(anyhow!(
+			"nothing in {} was changed from {}@{}",
+			dir.display(),
+			name,
+			version
+		));
+	}
+
+	let id = format!("{}@{}", name, version);
+	let path = patch::patch_path(&name, &version);
+	fs::create_dir_all(root.join(&path).parent().unwrap())?;
+	fs::write(root.join(&path), diff)?;
+
+	let package_json = &workspace.root_package().0;
+	let text = fs::read_to_string(package_json)?;
+	let text = register_patch(&text, &id, &path)
+		.ok_or_else(|| anyhow!("failed to add {} to {}", path, package_json.display()))?;
+	fs::write(package_json, text)?;
+	println!("{} {} ({})", "+".green(), id.bold(), path);
+
+	// Only clean up after `kirbo patch`. Any other directory could be something the
+	// user still wants, like the installed copy in node_modules.
+	let extracted = patch::edit_dir(&id);
+	if fs::canonicalize(&dir)? == fs::canonicalize(&extracted).unwrap_or_default() {
+		fs::remove_dir_all(&extracted)?;
+	}
+
+	let options = install::options::Options::default();
+	install::main::install(&root, options).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn registers_patches() {
+		let text = "{\n  \"name\": \"app\"\n}\n";
+		let text = register_patch(text, "left@1.0.0", "patches/left@1.0.0.patch").unwrap();
+		assert_eq!(
+			text,
+			"{\n  \"name\": \"app\",\n  \"kirbo\": {\n    \"patchedDependencies\": {\n      \"left@1.0.0\": \"patches/left@1.0.0.patch\"\n    }\n  }\n}\n"
+		);
+		let text = register_patch(&text, "shared", "patches/shared.patch").unwrap();
+		assert!(text.contains(
+			"\"left@1.0.0\": \"patches/left@1.0.0.patch\",\n      \"shared\": \"patches/shared.patch\"\n"
+		));
+	}
+}
@@ -0,0 +1,10 @@
+use clap::Args;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// The directory that `kirbo patch` extracted the package into
+	#[arg(value_name = "dir")]
+	pub dir: PathBuf,
+}
@@ -0,0 +1,297 @@
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use colored::Colorize;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Input;
+use kirbo_workspace::Package;
+use kirbo_workspace::Workspace;
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::commands::pack::main::print_packed;
+use crate::config::CONFIG;
+use crate::manifest;
+use crate::npm;
+use crate::options;
+use crate::pack;
+use crate::pack::Packed;
+use crate::scripts;
+use crate::semver::SemverRange;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+const DEPENDENCY_FIELDS: &[&str] = &[
+	"dependencies",
+	"devDependencies",
+	"optionalDependencies",
+	"peerDependencies",
+];
+
+/// Replaces every `workspace:` specifier in the text of a package.json with a range
+/// that people installing from the registry can actually use. `version_of` looks up
+/// the current version of a workspace package.
+fn rewrite_workspace_specifiers(
+	text: &str,
+	version_of: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<String> {
+	let json = serde_json::from_str::<serde_json::Value>(text)?;
+	let mut text = text.to_string();
+
+	for field in DEPENDENCY_FIELDS {
+		let Some(dependencies) = json.get(field).and_then(|it| it.as_object()) else {
+			continue;
+		};
+		for (name, specifier) in dependencies {
+			let Some(rest) = specifier
+				.as_str()
+				.and_then(|it| it.strip_prefix("workspace:"))
+			else {
+				continue;
+			};
+			let version = version_of(name)
+				.ok_or_else(|| anyhow!("no workspace package named {} has a version", name))?;
+
+			let range = match rest {
+				"*" | "" => version,
+				"^" | "~" => format!("{}{}", rest, version),
+				range if SemverRange::from_str(range).is_ok() => range.to_string(),
+				// Paths, like `workspace:../core`
+				_ => version,
+			};
+			text = manifest::set_string(&text, &[field, name], &range)
+				.ok_or_else(|| anyhow!("failed to rewrite {} in {}", name, field))?;
+		}
+	}
+
+	Ok(text)
+}
+
+/// The document npm sends to publish a version, with the tarball attached to it
+fn document(
+	packed: &Packed,
+	manifest: &str,
+	options: &Options,
+) -> anyhow::Result<serde_json::Value> {
+	let name = &packed.name;
+	let basename = name.rsplit('/').next().unwrap_or(name);
+
+	let mut version = serde_json::from_str::<serde_json::Value>(manifest)?;
+	version["_id"] = format!("{}@{}", name, packed.version).into();
+	version["dist"] = serde_json::json!({
+		"shasum": packed.shasum,
+		"integrity": packed.integrity,
+		"tarball": format!("{}{}/-/{}-{}.tgz", CONFIG.registry, name, basename, packed.version),
+	});
+
+	Ok(serde_json::json!({
+		"_id": name,
+		"name": name,
+		"description": version.get("description"),
+		"dist-tags": { &options.tag: packed.version },
+		"versions": { &packed.version: version },
+		"access": options.access,
+		"_attachments": {
+			format!("{}-{}.tgz", name, packed.version): {
+				"content_type": "application/octet-stream",
+				"data": BASE64.encode(&packed.tarball),
+				"length": packed.tarball.len(),
+			},
+		},
+	}))
+}
+
+async fn put(
+	name: &str,
+	document: &serde_json::Value,
+	otp: Option<&str>,
+) -> anyhow::Result<reqwest::Response> {
+	let mut request = CLIENT.put(npm::registry_doc_url(name)).json(document);
+	if let Some(token) = &CONFIG.auth_token {
+		request = request.bearer_auth(token);
+	}
+	if let Some(otp) = otp {
+		request = request.header("npm-otp", otp);
+	}
+	Ok(request.send().await?)
+}
+
+/// Registries ask for a one-time password by responding with a `401` and a
+/// `WWW-Authenticate: OTP` header
+fn wants_otp(response: &reqwest::Response) -> bool {
+	response.status() == StatusCode::UNAUTHORIZED
+		&& response
+			.headers()
+			.get("www-authenticate")
+			.and_then(|it| it.to_str().ok())
+			.is_some_and(|it| it.to_ascii_lowercase().contains("otp"))
+}
+
+/// Whether this exact version of a package is already in the registry
+async fn is_published(name: &str, version: &str) -> anyhow::Result<bool> {
+	let response = CLIENT.get(npm::registry_doc_url(name)).send().await?;
+	if response.status() == StatusCode::NOT_FOUND {
+		return Ok(false);
+	}
+	let doc = response
+		.error_for_status()?
+		.json::<npm::RegistryDoc>()
+		.await?;
+	Ok(doc.versions.contains_key(version))
+}
+
+/// Packs and publishes the package in `dir`
+async fn publish(dir: &Path, workspace: &Workspace, options: &Options) -> anyhow::Result<()> {
+	let path = dir.join("package.json");
+	let read = || -> anyhow::Result<(String, Package)> {
+		let text = fs::read_to_string(&path)
+			.map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+		let package = serde_json::from_str(&text)?;
+		Ok((text, package))
+	};
+
+	let (_, package) = read()?;
+	if package.private == Some(true) {
+		return Err(anyhow!(
+			"{} is private, so it can't be published",
+			package.name.as_deref().unwrap_or("package.json")
+		));
+	}
+	if !options.dry_run {
+		scripts::run_event(dir, &package, "prepublishOnly")?;
+		scripts::run_event(dir, &package, "prepack")?;
+	}
+
+	let (text, package) = read()?;
+	let manifest = rewrite_workspace_specifiers(&text, |name| {
+		workspace
+			.get(name)
+			.and_then(|package_json| package_json.1.version.clone())
+	})?;
+	let packed = pack::pack(dir, &manifest)?;
+	print_packed(&packed);
+	if options.dry_run {
+		println!(
+			"{} {}@{} (dry run)",
+			"+".green(),
+			packed.name.bold(),
+			packed.version
+		);
+		return Ok(());
+	}
+	scripts::run_event(dir, &package, "postpack")?;
+
+	let document = document(&packed, &manifest, options)?;
+	let mut response = put(&packed.name, &document, options.otp.as_deref()).await?;
+	if wants_otp(&response) && options.otp.is_none() {
+		let otp = Input::<String>::with_theme(&ColorfulTheme::default())
+			.with_prompt("one-time password")
+			.interact_text()?;
+		response = put(&packed.name, &document, Some(&otp)).await?;
+	}
+
+	let status = response.status();
+	if !status.is_success() {
+		let body = response.text().await.unwrap_or_default();
+		return Err(anyhow!(
+			"failed to publish {}@{} ({}): {}",
+			packed.name,
+			packed.version,
+			status,
+			body.trim()
+		));
+	}
+	println!(
+		"{} {}@{} ({})",
+		"+".green(),
+		packed.name.bold(),
+		packed.version,
+		options.tag
+	);
+
+	scripts::run_event(dir, &package, "publish")?;
+	scripts::run_event(dir, &package, "postpublish")?;
+	Ok(())
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo publish".bright_magenta().bold());
+	}
+
+	if let (None, Some((_, origin))) = (&CONFIG.auth_token, CONFIG.values.get("auth-token")) {
+		println!(
+			"{} not sending auth-token ({}) to {}, since the registry was chosen by the project",
+			"warning:".yellow(),
+			origin,
+			CONFIG.registry
+		);
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+
+	if !options.recursive {
+		return publish(workspace.current_package().dir(), &workspace, &options).await;
+	}
+
+	// Dependencies are published before their dependents, so that nothing ever
+	// depends on a version which isn't in the registry yet
+	let root = workspace.root_package().0.clone();
+	for package_json in workspace.topological_order()? {
+		let package = &package_json.1;
+		let (Some(name), Some(version)) = (&package.name, &package.version) else {
+			continue;
+		};
+		if package_json.0 == root {
+			continue;
+		}
+		if package.private == Some(true) {
+			println!("{} {} (private)", "skipping".dimmed(), name);
+			continue;
+		}
+		if is_published(name, version).await? {
+			println!(
+				"{} {}@{} (already published)",
+				"skipping".dimmed(),
+				name,
+				version
+			);
+			continue;
+		}
+
+		publish(package_json.dir(), &workspace, &options).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rewrites_workspace_specifiers() {
+		let text = r#"{
+  "dependencies": { "core": "workspace:^", "left": "^1.0.0" },
+  "devDependencies": { "core": "workspace:*" },
+  "peerDependencies": { "core": "workspace:>=1.0.0" },
+  "optionalDependencies": { "core": "workspace:../core" }
+}"#;
+		assert_eq!(
+			rewrite_workspace_specifiers(text, |name| (name == "core").then(|| "1.2.3".to_string()))
+				.unwrap(),
+			r#"{
+  "dependencies": { "core": "^1.2.3", "left": "^1.0.0" },
+  "devDependencies": { "core": "1.2.3" },
+  "peerDependencies": { "core": ">=1.0.0" },
+  "optionalDependencies": { "core": "1.2.3" }
+}"#
+		);
+		assert!(rewrite_workspace_specifiers(text, |_| None).is_err());
+	}
+}
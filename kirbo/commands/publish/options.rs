@@ -0,0 +1,22 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// The dist-tag to point at the published version
+	#[arg(long, value_name = "tag", default_value = "latest")]
+	pub tag: String,
+	/// `public` or `restricted`, for scoped packages
+	#[arg(long, value_name = "access", value_parser = ["public", "restricted"])]
+	pub access: Option<String>,
+	/// A one-time password, for accounts which use two-factor authentication
+	#[arg(long, value_name = "code")]
+	pub otp: Option<String>,
+	/// Publish every workspace member which hasn't been published yet, in dependency
+	/// order
+	#[arg(short, long)]
+	pub recursive: bool,
+	/// Pack everything, but don't send anything to the registry
+	#[arg(long)]
+	pub dry_run: bool,
+}
@@ -1,72 +1,65 @@
+use anyhow::anyhow;
 use colored::Colorize;
-use std::borrow::Cow;
+use kirbo_workspace::Workspace;
 use std::env;
-use std::ffi::OsStr;
-use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
 
-use crate::npm;
-use crate::options::Options;
+use super::options::Options;
+use crate::commands::workspaces;
+use crate::commands::workspaces::options::ForeachFlags;
+use crate::config::CONFIG;
+use crate::engines;
+use crate::exit::ExitCode;
+use crate::options;
+use crate::scripts;
+use crate::workspace;
 
-pub fn main(options: Options) {
-	println!("{}", "kirbo run".bright_magenta().bold());
-	let args = options.remaining_args;
+pub fn main(options: Options) -> anyhow::Result<()> {
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	engines::check_package_manager(&workspace.root_package().1, CONFIG.ignore_package_manager)?;
 
-	let package = serde_json::from_str::<npm::PackageJson>(
-		&fs::read_to_string(env::current_dir().unwrap().join("package.json")).unwrap(),
-	)
-	.unwrap();
+	// `kirbo run -r build` is shorthand for `kirbo workspaces foreach build`
+	if options.recursive {
+		let script = options
+			.script
+			.ok_or_else(|| anyhow!("--recursive needs the name of a script to run"))?;
+		return workspaces::foreach::main(workspaces::options::ForeachOptions {
+			flags: options.foreach,
+			script,
+			args: options.args,
+		});
+	}
+	if options.foreach != ForeachFlags::default() {
+		return Err(anyhow!(
+			"--parallel, --include, --exclude and --since only apply with --recursive"
+		));
+	}
 
-	if args.len() == 0 {
-		println!("Available scripts:");
-		for (name, script) in package.scripts {
-			println!("\n  - {}\n    {}\n", name.bold(), script);
-		}
-		return;
+	if !options::global().silent {
+		println!("{}", "kirbo run".bright_magenta().bold());
 	}
 
-	let mut path_env = env::current_dir()
-		.unwrap_or(PathBuf::from("."))
-		.join("node_modules/.bin/")
-		.into_os_string();
+	let package_json = workspace::target_package(&workspace, options.workspace.as_deref())?;
+	let package = &package_json.1;
 
-	if let Some(path) = env::var_os("PATH") {
-		// TODO: Maybe use `env::join_paths`?
-		#[cfg(target_os = "win")]
-		path_env.extend([OsStr::new(";"), &path]);
-		#[cfg(not(target_os = "win"))]
-		path_env.extend([OsStr::new(":"), &path]);
+	let Some(name) = &options.script else {
+		println!("Available scripts:");
+		for (name, script) in &package.scripts {
+			println!("\n  - {}\n    {}\n", name.bold(), script);
+		}
+		return Ok(());
 	};
 
-	let mut script = Cow::from(package.scripts[&args[0]].clone());
-	// Pass in additional arguments. I always thought this npm/yarn behavior felt hacky,
-	// but I like it even less after seeing what it takes to implement it.
-	for it in 1..args.len() {
-		script.to_mut().extend([format!(" ${}", it - 1)]);
-	}
-
-	#[cfg(not(windows))]
-	let mut cmd = {
-		let mut cmd = Command::new("sh");
-		cmd
-			.arg("-c")
-			.arg(&*script)
-			.args(&args[1..])
-			.env("PATH", path_env);
-		cmd
-	};
+	let script = package.scripts.get(name).ok_or_else(|| {
+		anyhow!(
+			"no script named \"{}\" in {}",
+			name,
+			package_json.0.display()
+		)
+	})?;
 
-	#[cfg(windows)]
-	let mut cmd = {
-		let mut cmd = Command::new("powershell");
-		cmd
-			.arg("-Command")
-			.arg(&*script)
-			.args(&args[1..])
-			.env("PATH", path_env);
-		cmd
-	};
+	let status = scripts::command(package_json.dir(), script, &options.args)
+		.spawn()?
+		.wait()?;
 
-	cmd.spawn().unwrap().wait().unwrap();
+	Ok(ExitCode::check(status)?)
 }
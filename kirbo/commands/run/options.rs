@@ -1 +1,39 @@
+use clap::Args;
 
+use crate::commands::workspaces::options::ForeachFlags;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Run the script in every workspace member which has it, like
+	/// `kirbo workspaces foreach`
+	#[arg(short, long)]
+	pub recursive: bool,
+	#[command(flatten)]
+	pub foreach: ForeachFlags,
+	/// Run the script from this workspace member, instead of the nearest package
+	#[arg(short, long, value_name = "name", conflicts_with = "recursive")]
+	pub workspace: Option<String>,
+	/// The script to run. If it's left out, every script is listed.
+	#[arg(value_name = "script")]
+	pub script: Option<String>,
+	/// Arguments for the script
+	#[arg(
+		value_name = "args",
+		trailing_var_arg = true,
+		allow_hyphen_values = true
+	)]
+	pub args: Vec<String>,
+}
+
+impl From<Vec<String>> for Options {
+	/// The options for `kirbo <script> [args...]`
+	fn from(args: Vec<String>) -> Self {
+		let mut args = args.into_iter();
+		Options {
+			script: args.next(),
+			args: args.collect(),
+			..Default::default()
+		}
+	}
+}
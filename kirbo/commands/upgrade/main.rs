@@ -0,0 +1,274 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::MultiSelect;
+use kirbo_workspace::Workspace;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::commands;
+use crate::manifest;
+use crate::options;
+use crate::resolver::Resolver;
+use crate::semver::Version;
+use crate::specifier::DependencySpecifier;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Kind {
+	Patch,
+	Minor,
+	Major,
+}
+
+impl fmt::Display for Kind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Kind::Patch => write!(f, "patch"),
+			Kind::Minor => write!(f, "minor"),
+			Kind::Major => write!(f, "major"),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+struct Upgrade {
+	/// The package.json to edit
+	path: PathBuf,
+	dependent: String,
+	/// `dependencies` or `devDependencies`
+	field: &'static str,
+	name: String,
+	from: String,
+	to: String,
+	kind: Kind,
+}
+
+/// Rewrites a dependency specifier to allow `version`, keeping its operator and any
+/// `npm:` alias, or returns `None` if it isn't newer than what's already allowed
+fn bump_specifier(specifier: &str, version: &Version) -> Option<(String, Kind)> {
+	let specifier = DependencySpecifier::parse(specifier);
+	let floor = specifier.floor()?;
+	if *version <= floor {
+		return None;
+	}
+
+	let kind = if version.major != floor.major {
+		Kind::Major
+	} else if version.minor != floor.minor {
+		Kind::Minor
+	} else {
+		Kind::Patch
+	};
+	Some((specifier.with_version(version)?, kind))
+}
+
+pub async fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo upgrade".bright_magenta().bold());
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let mut resolver = Resolver::default().quiet();
+
+	for name in &options.packages {
+		let depended_on = workspace
+			.members()
+			.chain([workspace.root_package()])
+			.any(|package_json| {
+				package_json.1.dependencies.contains_key(name)
+					|| package_json.1.dev_dependencies.contains_key(name)
+			});
+		if !depended_on {
+			return Err(anyhow!("nothing in the workspace depends on {}", name));
+		}
+	}
+
+	let mut upgrades = vec![];
+	for package_json in workspace.members().chain([workspace.root_package()]) {
+		let dependent = package_json
+			.1
+			.name
+			.clone()
+			.unwrap_or_else(|| "package.json".to_string());
+		for (field, dependencies) in [
+			("dependencies", &package_json.1.dependencies),
+			("devDependencies", &package_json.1.dev_dependencies),
+		] {
+			let mut dependencies = dependencies.iter().collect::<Vec<_>>();
+			dependencies.sort();
+
+			for (name, specifier) in dependencies {
+				if !options.packages.is_empty() && !options.packages.contains(name) {
+					continue;
+				}
+				let parsed = DependencySpecifier::parse(specifier);
+				if workspace.get(name).is_some() || parsed.floor().is_none() {
+					continue;
+				}
+				let (registry_name, range) = match parsed {
+					DependencySpecifier::Range(range) => (name.clone(), range),
+					DependencySpecifier::Alias { name, range } => (name, range),
+					_ => continue,
+				};
+
+				let target = match options.latest {
+					true => resolver
+						.query_package(&registry_name)
+						.await?
+						.dist_tags
+						.get("latest")
+						.cloned(),
+					false => resolver.newest_version(&registry_name, &range).await,
+				};
+				let Some(target) = target.and_then(|it| Version::from_str(&it).ok()) else {
+					continue;
+				};
+
+				if let Some((to, kind)) = bump_specifier(specifier, &target) {
+					upgrades.push(Upgrade {
+						path: package_json.0.clone(),
+						dependent: dependent.clone(),
+						field,
+						name: name.clone(),
+						from: specifier.clone(),
+						to,
+						kind,
+					});
+				}
+			}
+		}
+	}
+
+	if options.interactive && !upgrades.is_empty() {
+		upgrades = choose(upgrades)?;
+	}
+	if upgrades.is_empty() {
+		println!("everything is already up to date");
+		return Ok(());
+	}
+
+	println!("========================================");
+	let mut edits = BTreeMap::<&PathBuf, Vec<&Upgrade>>::new();
+	for upgrade in &upgrades {
+		println!(
+			"  {} {} → {} {}",
+			upgrade.name.bold(),
+			upgrade.from,
+			match upgrade.kind {
+				Kind::Major => upgrade.to.red().bold(),
+				Kind::Minor => upgrade.to.yellow(),
+				Kind::Patch => upgrade.to.green(),
+			},
+			format!("({})", upgrade.dependent).dimmed()
+		);
+		edits.entry(&upgrade.path).or_default().push(upgrade);
+	}
+	println!("========================================\n");
+
+	for (path, upgrades) in edits {
+		let mut text = fs::read_to_string(path)?;
+		for upgrade in upgrades {
+			text = manifest::set_string(&text, &[upgrade.field, &upgrade.name], &upgrade.to).ok_or_else(
+				|| {
+					anyhow!(
+						"failed to find {} in {} of {}",
+						upgrade.name,
+						upgrade.field,
+						path.display()
+					)
+				},
+			)?;
+		}
+		fs::write(path, text)?;
+	}
+
+	// Resolve everything again, so that Kirbo.lock and node_modules match the new ranges
+	commands::install::main::main(Default::default()).await
+}
+
+/// Lets the user pick which upgrades to apply, a group at a time. Patch and minor
+/// upgrades start out selected, but major upgrades have to be opted into.
+fn choose(upgrades: Vec<Upgrade>) -> anyhow::Result<Vec<Upgrade>> {
+	let theme = ColorfulTheme::default();
+	let mut chosen = vec![];
+
+	for kind in [Kind::Patch, Kind::Minor, Kind::Major] {
+		let group = upgrades
+			.iter()
+			.filter(|upgrade| upgrade.kind == kind)
+			.collect::<Vec<_>>();
+		if group.is_empty() {
+			continue;
+		}
+
+		let width = group.iter().map(|it| it.name.len()).max().unwrap_or(0);
+		let items = group
+			.iter()
+			.map(|upgrade| {
+				(
+					format!(
+						"{:width$}  {} → {}  ({})",
+						upgrade.name,
+						upgrade.from,
+						upgrade.to,
+						upgrade.dependent,
+						width = width
+					),
+					kind != Kind::Major,
+				)
+			})
+			.collect::<Vec<_>>();
+		let selected = MultiSelect::with_theme(&theme)
+			.with_prompt(format!("{} upgrades", kind))
+			.items_checked(&items)
+			.interact()
+			.map_err(|err| anyhow!("failed to show upgrades: {}", err))?;
+		chosen.extend(selected.into_iter().map(|i| group[i].clone()));
+	}
+
+	Ok(chosen)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn bump(specifier: &str, version: &str) -> Option<(String, Kind)> {
+		bump_specifier(specifier, &Version::from_str(version).unwrap())
+	}
+
+	#[test]
+	fn bumps_keep_operators() {
+		assert_eq!(
+			bump("^1.0.0", "1.1.0"),
+			Some(("^1.1.0".to_string(), Kind::Minor))
+		);
+		assert_eq!(
+			bump("~1.0.0", "1.0.3"),
+			Some(("~1.0.3".to_string(), Kind::Patch))
+		);
+		assert_eq!(
+			bump("1.0.0", "2.0.0"),
+			Some(("2.0.0".to_string(), Kind::Major))
+		);
+		assert_eq!(
+			bump("^1", "2.0.0"),
+			Some(("^2.0.0".to_string(), Kind::Major))
+		);
+		assert_eq!(
+			bump("npm:real@^1.0.0", "1.2.0"),
+			Some(("npm:real@^1.2.0".to_string(), Kind::Minor))
+		);
+		assert_eq!(bump("^1.1.0", "1.1.0"), None);
+		assert_eq!(bump("^1.1.0", "1.0.0"), None);
+		assert_eq!(bump(">=1.0.0 <2.0.0", "1.1.0"), None);
+		assert_eq!(bump("*", "1.1.0"), None);
+		assert_eq!(bump("1.x", "1.1.0"), None);
+		assert_eq!(bump("workspace:*", "1.1.0"), None);
+	}
+}
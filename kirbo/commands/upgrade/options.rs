@@ -0,0 +1,15 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// Only upgrade these packages, or everything if left out
+	#[arg(value_name = "packages")]
+	pub packages: Vec<String>,
+	/// Upgrade to the latest version, even if it's a new major version
+	#[arg(short = 'L', long)]
+	pub latest: bool,
+	/// Choose which upgrades to apply from a list
+	#[arg(short, long)]
+	pub interactive: bool,
+}
@@ -0,0 +1,255 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Package;
+use kirbo_workspace::PackageJson;
+use kirbo_workspace::Workspace;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::commands::workspaces::foreach::matches_filter;
+use crate::git;
+use crate::lock::LOCK_FILE_NAME;
+use crate::manifest;
+use crate::options;
+use crate::scripts;
+use crate::semver::Bump;
+use crate::semver::Version;
+use crate::specifier::DependencySpecifier;
+
+const DEPENDENCY_FIELDS: &[&str] = &[
+	"dependencies",
+	"devDependencies",
+	"optionalDependencies",
+	"peerDependencies",
+];
+
+/// A package whose version is being bumped
+#[derive(Clone, Debug)]
+struct Bumped {
+	name: Option<String>,
+	path: PathBuf,
+	from: String,
+	to: Version,
+}
+
+impl Bumped {
+	fn dir(&self) -> &Path {
+		self.path.parent().unwrap_or_else(|| Path::new("."))
+	}
+
+	fn read(&self) -> anyhow::Result<Package> {
+		Ok(serde_json::from_str(&fs::read_to_string(&self.path)?)?)
+	}
+}
+
+fn next_version(package_json: &PackageJson, options: &Options) -> anyhow::Result<Bumped> {
+	let package = &package_json.1;
+	let label = package.name.as_deref().unwrap_or("package.json");
+	let from = package
+		.version
+		.clone()
+		.ok_or_else(|| anyhow!("{} doesn't have a version", label))?;
+
+	let to = match Bump::from_str(&options.version) {
+		Ok(bump) => Version::from_str(&from)
+			.map_err(|err| anyhow!("{} has an invalid version: {}", label, err))?
+			.bump(bump, options.preid.as_deref()),
+		Err(_) => Version::from_str(options.version.trim_start_matches('v')).map_err(|_| {
+			anyhow!(
+				"\"{}\" isn't a version, or one of major, minor, patch, premajor, preminor, prepatch, or prerelease",
+				options.version
+			)
+		})?,
+	};
+	if to.to_string() == from {
+		return Err(anyhow!("{} is already at version {}", label, from));
+	}
+
+	Ok(Bumped {
+		name: package.name.clone(),
+		path: package_json.0.clone(),
+		from,
+		to,
+	})
+}
+
+/// Moves the ranges of every dependency on a bumped package up to its new version,
+/// and returns a description of each change
+fn update_dependents(
+	workspace: &Workspace,
+	bumped: &[Bumped],
+	edits: &mut BTreeMap<PathBuf, String>,
+) -> anyhow::Result<Vec<String>> {
+	let versions = bumped
+		.iter()
+		.filter_map(|it| Some((it.name.as_deref()?, &it.to)))
+		.collect::<BTreeMap<_, _>>();
+
+	let mut changes = vec![];
+	let mut paths = workspace
+		.packages()
+		.chain([workspace.root_package()])
+		.map(|package_json| package_json.0.clone())
+		.collect::<Vec<_>>();
+	paths.sort();
+	paths.dedup();
+
+	for path in paths {
+		let mut text = match edits.get(&path) {
+			Some(text) => text.clone(),
+			None => fs::read_to_string(&path)?,
+		};
+		let json = serde_json::from_str::<serde_json::Value>(&text)?;
+		let dependent = json["name"].as_str().unwrap_or("package.json");
+		let mut changed = false;
+
+		for field in DEPENDENCY_FIELDS {
+			let Some(dependencies) = json.get(field).and_then(|it| it.as_object()) else {
+				continue;
+			};
+			for (name, specifier) in dependencies {
+				let (Some(version), Some(specifier)) = (versions.get(name.as_str()), specifier.as_str())
+				else {
+					continue;
+				};
+				// Aliases might have the same name as a workspace package, but they aren't it
+				let parsed = DependencySpecifier::parse(specifier);
+				if !matches!(parsed, DependencySpecifier::Range(_)) {
+					continue;
+				}
+				let Some(to) = parsed.with_version(version) else {
+					continue;
+				};
+				if to == specifier {
+					continue;
+				}
+
+				text = manifest::set_string(&text, &[field, name], &to)
+					.ok_or_else(|| anyhow!("failed to update {} in {}", name, path.display()))?;
+				changed = true;
+				changes.push(format!("{} {} {} → {}", dependent, name, specifier, to));
+			}
+		}
+
+		if changed {
+			edits.insert(path, text);
+		}
+	}
+
+	Ok(changes)
+}
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo version".bright_magenta().bold());
+	}
+
+	let dir = env::current_dir()?;
+	let workspace = Workspace::new(&dir)?;
+
+	let selected = match options.recursive || !options.include.is_empty() {
+		true => workspace
+			.members()
+			.filter(|package_json| {
+				options.include.is_empty()
+					|| options
+						.include
+						.iter()
+						.any(|pattern| matches_filter(&workspace, package_json, pattern))
+			})
+			.collect::<Vec<_>>(),
+		// Otherwise we want whichever package we're in
+		false => workspace
+			.packages()
+			.chain([workspace.root_package()])
+			.filter(|package_json| dir.starts_with(package_json.dir()))
+			.max_by_key(|package_json| package_json.dir().components().count())
+			.into_iter()
+			.collect(),
+	};
+	if selected.is_empty() {
+		return Err(anyhow!("no workspace packages matched"));
+	}
+
+	let bumped = selected
+		.iter()
+		.map(|package_json| next_version(package_json, &options))
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	for package in &bumped {
+		scripts::run_event(package.dir(), &package.read()?, "preversion")?;
+	}
+
+	let mut edits = BTreeMap::<PathBuf, String>::new();
+	println!("========================================");
+	for package in &bumped {
+		let text = fs::read_to_string(&package.path)?;
+		let text = manifest::set_string(&text, &["version"], &package.to.to_string())
+			.ok_or_else(|| anyhow!("failed to update the version in {}", package.path.display()))?;
+		edits.insert(package.path.clone(), text);
+		println!(
+			"  {} {} → {}",
+			package.name.as_deref().unwrap_or("package.json").bold(),
+			package.from,
+			package.to.to_string().green()
+		);
+	}
+	for change in update_dependents(&workspace, &bumped, &mut edits)? {
+		println!("  {}", change);
+	}
+	println!("========================================");
+
+	for (path, text) in &edits {
+		fs::write(path, text)?;
+	}
+	for package in &bumped {
+		scripts::run_event(package.dir(), &package.read()?, "version")?;
+	}
+
+	if options.git_tag_version && git::toplevel(workspace.root()).is_ok() {
+		// A lone package gets a `v1.2.3` tag, but a workspace needs to know which of its
+		// packages each tag belongs to
+		let tags = match &*bumped {
+			[package] if package.path == workspace.root_package().0 => {
+				vec![format!("v{}", package.to)]
+			}
+			_ => bumped
+				.iter()
+				.map(|package| {
+					format!(
+						"{}@{}",
+						package.name.as_deref().unwrap_or("package.json"),
+						package.to
+					)
+				})
+				.collect(),
+		};
+		let message = match (&options.message, &*bumped) {
+			(Some(message), [package]) => message.replace("%s", &package.to.to_string()),
+			(Some(message), _) => message.replace("%s", &tags.join(", ")),
+			(None, [package]) if tags[0].starts_with('v') => package.to.to_string(),
+			(None, _) => tags.join(", "),
+		};
+
+		let paths = edits.keys().cloned().collect::<Vec<_>>();
+		git::commit(workspace.root(), &message, &paths)?;
+		for tag in &tags {
+			git::tag(workspace.root(), tag)?;
+			println!("tagged {}", tag.bold());
+		}
+	}
+
+	for package in &bumped {
+		scripts::run_event(package.dir(), &package.read()?, "postversion")?;
+	}
+
+	if workspace.root().join(LOCK_FILE_NAME).exists() {
+		println!("run `kirbo install` to update {}", LOCK_FILE_NAME);
+	}
+	Ok(())
+}
@@ -0,0 +1,26 @@
+use clap::ArgAction;
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// `major`, `minor`, `patch`, `premajor`, `preminor`, `prepatch`, `prerelease`, or
+	/// an exact version like 1.2.3
+	#[arg(value_name = "version")]
+	pub version: String,
+	/// The identifier for prerelease versions, like `beta` in `1.0.0-beta.0`
+	#[arg(long, value_name = "id")]
+	pub preid: Option<String>,
+	/// Bump every workspace member
+	#[arg(short, long)]
+	pub recursive: bool,
+	/// Bump workspace members matching a name glob or path
+	#[arg(long, value_name = "pattern")]
+	pub include: Vec<String>,
+	/// Don't commit or tag anything
+	#[arg(long = "no-git-tag-version", action = ArgAction::SetFalse)]
+	pub git_tag_version: bool,
+	/// The commit message, where `%s` is replaced with the new version
+	#[arg(short, long, value_name = "message")]
+	pub message: Option<String>,
+}
@@ -0,0 +1,103 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Workspace;
+use serde::Serialize;
+use std::env;
+use std::str::FromStr;
+
+use super::options::Options;
+use crate::exit::ExitCode;
+use crate::graph::Chain;
+use crate::graph::DependencyGraph;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::options;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+#[derive(Clone, Debug, Serialize)]
+struct Explanation {
+	name: String,
+	version: String,
+	chains: Vec<Chain>,
+}
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let lock = KirboLock::read(workspace.root())?
+		.ok_or_else(|| anyhow!("no Kirbo.lock found, try running `kirbo install` first"))?;
+	let graph = DependencyGraph::new(&lock, &workspace);
+
+	let (name, range) = split_descriptor(&options.package);
+	let range = match range {
+		"" => None,
+		range => Some(SemverRange::from_str(range)?),
+	};
+
+	let mut explanations = graph
+		.descriptors_named(name)
+		.into_iter()
+		.map(|descriptor| (descriptor, &lock.packages[descriptor]))
+		.filter(|(_, package)| match &range {
+			Some(range) => Version::from_str(&package.version)
+				.map(|version| version.satisfies(range))
+				.unwrap_or(false),
+			None => true,
+		})
+		.map(|(descriptor, package)| Explanation {
+			name: name.to_string(),
+			version: package.version.clone(),
+			chains: graph.chains_to(descriptor),
+		})
+		.collect::<Vec<_>>();
+
+	// Several ranges can resolve to the same version, so merge them together
+	explanations.sort_by(|a, b| a.version.cmp(&b.version));
+	explanations.dedup_by(|next, previous| {
+		let same = next.version == previous.version;
+		if same {
+			previous.chains.append(&mut next.chains);
+			previous
+				.chains
+				.sort_by(|a, b| a.links.len().cmp(&b.links.len()).then(a.cmp(b)));
+		}
+		same
+	});
+
+	if options::global().json {
+		println!("{}", serde_json::to_string_pretty(&explanations)?);
+		if explanations.is_empty() {
+			return Err(ExitCode(1).into());
+		}
+		return Ok(());
+	}
+
+	if explanations.is_empty() {
+		return Err(anyhow!("{} is not installed", options.package));
+	}
+
+	for explanation in &explanations {
+		println!(
+			"{}@{}",
+			explanation.name.bright_magenta().bold(),
+			explanation.version.bold()
+		);
+
+		for chain in &explanation.chains {
+			let links = chain
+				.links
+				.iter()
+				.map(|link| format!("{}@{}", link.name, link.range))
+				.collect::<Vec<_>>()
+				.join(" → ");
+			let kind = match chain.root.kind {
+				"dependencies" => "".to_string(),
+				kind => format!(" ({})", kind).dimmed().to_string(),
+			};
+			println!("  {}{} → {}", chain.root.name.bold(), kind, links);
+		}
+		println!();
+	}
+
+	Ok(())
+}
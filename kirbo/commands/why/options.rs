@@ -0,0 +1,9 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+#[command(after_help = include_str!("./help.txt"))]
+pub struct Options {
+	/// The package to explain, as `name` or `name@range`
+	#[arg(value_name = "package")]
+	pub package: String,
+}
@@ -0,0 +1,85 @@
+use kirbo_workspace::Workspace;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::git;
+use crate::lock::KirboLock;
+use crate::lock::LOCK_FILE_NAME;
+
+/// Finds the workspace package that a file belongs to. Packages can be nested inside
+/// each other, so the deepest one wins.
+fn owner<'a>(members: &'a [(String, PathBuf)], file: &Path) -> Option<&'a str> {
+	members
+		.iter()
+		.filter(|(_, dir)| file.starts_with(dir))
+		.max_by_key(|(_, dir)| dir.components().count())
+		.map(|(name, _)| name.as_str())
+}
+
+/// Names of every workspace member affected by changes since `rev`. A member is
+/// affected if any of its files have changed, if the lock entries of any of its
+/// dependencies have changed, or if it depends on another affected member.
+pub fn affected_packages(workspace: &Workspace, rev: &str) -> anyhow::Result<BTreeSet<String>> {
+	let root = fs::canonicalize(workspace.root())?;
+	let members = workspace
+		.members()
+		.filter_map(|package_json| {
+			let name = package_json.1.name.clone()?;
+			let dir = fs::canonicalize(package_json.dir()).ok()?;
+			Some((name, dir))
+		})
+		.collect::<Vec<_>>();
+
+	let lock_path = root.join(LOCK_FILE_NAME);
+	let mut affected = BTreeSet::new();
+	let mut lock_changed = false;
+
+	for file in git::changed_files(&root, rev)? {
+		if file == lock_path {
+			lock_changed = true;
+		} else if let Some(name) = owner(&members, &file) {
+			affected.insert(name.to_string());
+		}
+	}
+
+	if lock_changed {
+		let parse = |text: Option<String>| {
+			text
+				.and_then(|text| serde_yaml::from_str::<KirboLock>(&text).ok())
+				.unwrap_or_default()
+		};
+		let before = parse(git::show_file(&root, rev, &lock_path)?);
+		let after = parse(fs::read_to_string(&lock_path).ok());
+		let changed = before.changed_packages(&after);
+
+		for package_json in workspace.members() {
+			let package = &package_json.1;
+			let depends_on_changed = package
+				.dependencies
+				.keys()
+				.chain(package.dev_dependencies.keys())
+				.chain(package.peer_dependencies.keys())
+				.any(|dependency| changed.contains(dependency));
+
+			if depends_on_changed {
+				affected.extend(package.name.clone());
+			}
+		}
+	}
+
+	let dependents = affected
+		.iter()
+		.flat_map(|name| workspace.transitive_dependents_of(name))
+		.map(str::to_string)
+		.collect::<Vec<_>>();
+	affected.extend(dependents);
+
+	// The root isn't a member, so it never needs to be run
+	if let Some(name) = &workspace.root_package().1.name {
+		affected.remove(name);
+	}
+
+	Ok(affected)
+}
@@ -0,0 +1,288 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::glob;
+use kirbo_workspace::PackageJson;
+use kirbo_workspace::Workspace;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+
+use super::changed;
+use super::options::ForeachOptions;
+use crate::options;
+use crate::scripts;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+	Succeeded,
+	Failed(Option<i32>),
+	/// Skipped because a dependency (named here) failed or was skipped
+	Skipped(String),
+}
+
+/// A script waiting to be run in a workspace package
+#[derive(Clone, Debug)]
+pub struct Task {
+	pub name: String,
+	pub dir: PathBuf,
+	pub script: String,
+	/// Names of other tasks which must succeed before this one can start
+	pub waiting_on: BTreeSet<String>,
+}
+
+/// Checks if a package matches an `--include` or `--exclude` pattern. Patterns that
+/// look like paths are matched against the package's location relative to the root
+/// of the workspace, and anything else is matched against its name.
+pub fn matches_filter(workspace: &Workspace, package_json: &PackageJson, pattern: &str) -> bool {
+	if pattern.starts_with('.') || pattern.contains('/') && !pattern.starts_with('@') {
+		let dir = package_json.dir();
+		let relative = dir.strip_prefix(workspace.root()).unwrap_or(dir);
+		return glob::matches_path(pattern, relative);
+	}
+
+	package_json
+		.1
+		.name
+		.as_deref()
+		.map(|name| glob::matches(pattern, name))
+		.unwrap_or(false)
+}
+
+/// Builds the list of tasks for every selected workspace package which defines
+/// `options.script`, each waiting on the selected packages it depends on.
+pub fn plan(workspace: &Workspace, options: &ForeachOptions) -> anyhow::Result<Vec<Task>> {
+	let affected = match &options.flags.since {
+		Some(rev) => Some(changed::affected_packages(workspace, rev)?),
+		None => None,
+	};
+
+	let selected = workspace
+		.members()
+		.filter(|package_json| package_json.1.scripts.contains_key(&options.script))
+		.filter(|package_json| match (&affected, &package_json.1.name) {
+			(Some(affected), Some(name)) => affected.contains(name),
+			_ => true,
+		})
+		.filter(|package_json| {
+			options.flags.include.is_empty()
+				|| options
+					.flags
+					.include
+					.iter()
+					.any(|pattern| matches_filter(workspace, package_json, pattern))
+		})
+		.filter(|package_json| {
+			!options
+				.flags
+				.exclude
+				.iter()
+				.any(|pattern| matches_filter(workspace, package_json, pattern))
+		})
+		.filter_map(|package_json| Some((package_json.1.name.clone()?, package_json)))
+		.collect::<BTreeMap<_, _>>();
+
+	Ok(
+		selected
+			.iter()
+			.map(|(name, package_json)| Task {
+				name: name.clone(),
+				dir: package_json.dir().to_path_buf(),
+				script: package_json.1.scripts[&options.script].clone(),
+				// Dependencies which we aren't running the script in still count, so that
+				// we wait on the packages "behind" them.
+				waiting_on: workspace
+					.transitive_dependencies_of(name)
+					.into_iter()
+					.filter(|dependency| selected.contains_key(*dependency))
+					.map(str::to_string)
+					.collect(),
+			})
+			.collect(),
+	)
+}
+
+fn print_prefixed<R: Read>(output: R, prefix: &str, to_stderr: bool) {
+	for line in BufReader::new(output).lines() {
+		let Ok(line) = line else {
+			break;
+		};
+
+		if to_stderr {
+			eprintln!("{} {}", prefix, line);
+		} else {
+			println!("{} {}", prefix, line);
+		}
+	}
+}
+
+fn run_task(task: &Task, args: &[String], prefix: &str) -> io::Result<Outcome> {
+	let mut child = scripts::command(&task.dir, &task.script, args)
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()?;
+
+	let stdout = child.stdout.take().unwrap();
+	let stderr = child.stderr.take().unwrap();
+
+	thread::scope(|scope| {
+		scope.spawn(|| print_prefixed(stdout, prefix, false));
+		scope.spawn(|| print_prefixed(stderr, prefix, true));
+	});
+
+	let status = child.wait()?;
+	Ok(match status.success() {
+		true => Outcome::Succeeded,
+		false => Outcome::Failed(status.code()),
+	})
+}
+
+/// Runs every task, starting each one as soon as everything it's waiting on has
+/// succeeded, with at most `parallel` running at once.
+pub fn run(
+	tasks: Vec<Task>,
+	args: &[String],
+	parallel: usize,
+) -> anyhow::Result<BTreeMap<String, Outcome>> {
+	let width = tasks.iter().map(|task| task.name.len()).max().unwrap_or(0);
+	let mut pending = tasks
+		.into_iter()
+		.map(|task| (task.name.clone(), task))
+		.collect::<BTreeMap<_, _>>();
+	let mut outcomes = BTreeMap::new();
+	let mut running = 0;
+	let (sender, receiver) = mpsc::channel();
+
+	while !pending.is_empty() || running > 0 {
+		// Anything waiting on a failure is never going to be able to run
+		loop {
+			let skipped = pending.values().find_map(|task| {
+				task
+					.waiting_on
+					.iter()
+					.find_map(|dependency| match outcomes.get(dependency) {
+						Some(Outcome::Failed(_) | Outcome::Skipped(_)) => {
+							Some((task.name.clone(), dependency.clone()))
+						}
+						_ => None,
+					})
+			});
+
+			let Some((name, dependency)) = skipped else {
+				break;
+			};
+			pending.remove(&name);
+			outcomes.insert(name, Outcome::Skipped(dependency));
+		}
+
+		let ready = pending
+			.values()
+			.filter(|task| {
+				task
+					.waiting_on
+					.iter()
+					.all(|dependency| outcomes.get(dependency) == Some(&Outcome::Succeeded))
+			})
+			.map(|task| task.name.clone())
+			.take(parallel - running)
+			.collect::<Vec<_>>();
+
+		for name in ready {
+			let task = pending.remove(&name).unwrap();
+			let prefix = format!("{:width$}", task.name, width = width)
+				.bright_magenta()
+				.bold()
+				.to_string();
+			let args = args.to_vec();
+			let sender = sender.clone();
+
+			running += 1;
+			thread::spawn(move || {
+				let outcome = run_task(&task, &args, &prefix).unwrap_or_else(|err| {
+					eprintln!("{} failed to start script: {}", prefix, err);
+					Outcome::Failed(None)
+				});
+				sender.send((task.name, outcome)).unwrap();
+			});
+		}
+
+		if running == 0 {
+			if pending.is_empty() {
+				break;
+			}
+			return Err(anyhow!(
+				"workspace packages have a circular dependency: {}",
+				pending.keys().cloned().collect::<Vec<_>>().join(", ")
+			));
+		}
+
+		let (name, outcome) = receiver.recv()?;
+		running -= 1;
+		outcomes.insert(name, outcome);
+	}
+
+	Ok(outcomes)
+}
+
+pub fn main(options: ForeachOptions) -> anyhow::Result<()> {
+	if !options::global().silent {
+		println!("{}", "kirbo workspaces foreach".bright_magenta().bold());
+	}
+
+	let workspace = Workspace::new(&env::current_dir()?)?;
+	let tasks = plan(&workspace, &options)?;
+
+	if tasks.is_empty() {
+		match &options.flags.since {
+			Some(rev) => println!(
+				"no workspace packages with a \"{}\" script have changed since {}",
+				options.script, rev
+			),
+			None => println!("no workspace packages have a \"{}\" script", options.script),
+		}
+		return Ok(());
+	}
+
+	let outcomes = run(tasks, &options.args, options.flags.parallel)?;
+
+	println!("========================================");
+	println!("summary:");
+	for (name, outcome) in &outcomes {
+		match outcome {
+			Outcome::Succeeded => println!("  {} {}", "✓".green(), name),
+			Outcome::Failed(Some(code)) => {
+				println!("  {} {} (exited with {})", "✗".red(), name, code)
+			}
+			Outcome::Failed(None) => println!("  {} {}", "✗".red(), name),
+			Outcome::Skipped(dependency) => println!(
+				"  {} {} (skipped because {} failed)",
+				"-".yellow(),
+				name,
+				dependency
+			),
+		}
+	}
+	println!("========================================");
+
+	let failed = outcomes
+		.values()
+		.filter(|outcome| **outcome != Outcome::Succeeded)
+		.count();
+	if failed > 0 {
+		return Err(anyhow!(
+			"\"{}\" did not succeed in {} of {} packages",
+			options.script,
+			failed,
+			outcomes.len()
+		));
+	}
+
+	Ok(())
+}
@@ -0,0 +1,9 @@
+use super::foreach;
+use super::options::Action;
+use super::options::Options;
+
+pub fn main(options: Options) -> anyhow::Result<()> {
+	match options.action {
+		Action::Foreach(options) => foreach::main(options),
+	}
+}
@@ -0,0 +1,116 @@
+use clap::builder::RangedU64ValueParser;
+use clap::Args;
+use clap::Subcommand;
+
+#[derive(Clone, Debug, Args)]
+pub struct Options {
+	#[command(subcommand)]
+	pub action: Action,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Action {
+	/// Run a script in every workspace member which has it
+	#[command(after_help = include_str!("./help.txt"))]
+	Foreach(ForeachOptions),
+}
+
+/// Picks which packages a script runs in, and how many run at once
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+pub struct ForeachFlags {
+	/// How many scripts can be running at once
+	#[arg(
+		short,
+		long,
+		value_name = "n",
+		default_value_t = 1,
+		value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+	)]
+	pub parallel: usize,
+	/// Only run in packages matching a name glob or path. If left out, every package
+	/// will be included.
+	#[arg(long, value_name = "pattern")]
+	pub include: Vec<String>,
+	/// Skip packages matching a name glob or path
+	#[arg(long, value_name = "pattern")]
+	pub exclude: Vec<String>,
+	/// Only run in packages affected by changes made since a git revision
+	#[arg(long, value_name = "ref")]
+	pub since: Option<String>,
+}
+
+impl Default for ForeachFlags {
+	fn default() -> Self {
+		ForeachFlags {
+			parallel: 1,
+			include: vec![],
+			exclude: vec![],
+			since: None,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Args)]
+pub struct ForeachOptions {
+	#[command(flatten)]
+	pub flags: ForeachFlags,
+	/// The script to run
+	#[arg(value_name = "script")]
+	pub script: String,
+	/// Arguments for the script
+	#[arg(
+		value_name = "args",
+		trailing_var_arg = true,
+		allow_hyphen_values = true
+	)]
+	pub args: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use clap::Parser;
+
+	#[derive(Parser)]
+	struct Foreach {
+		#[command(flatten)]
+		options: ForeachOptions,
+	}
+
+	fn parse(args: &[&str]) -> Result<ForeachOptions, clap::Error> {
+		Foreach::try_parse_from([&"foreach"].into_iter().chain(args)).map(|it| it.options)
+	}
+
+	#[test]
+	fn parse_foreach() {
+		let options = parse(&[
+			"--parallel=4",
+			"--include",
+			"@kirbo/*",
+			"--exclude",
+			"packages/legacy",
+			"--since",
+			"origin/main",
+			"test",
+			"--watch",
+		])
+		.unwrap();
+
+		assert_eq!(
+			options,
+			ForeachOptions {
+				flags: ForeachFlags {
+					parallel: 4,
+					include: vec!["@kirbo/*".to_string()],
+					exclude: vec!["packages/legacy".to_string()],
+					since: Some("origin/main".to_string()),
+				},
+				script: "test".to_string(),
+				args: vec!["--watch".to_string()],
+			}
+		);
+
+		assert!(parse(&["--parallel", "0", "test"]).is_err());
+		assert!(parse(&["--include", "poyo"]).is_err());
+	}
+}
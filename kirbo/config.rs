@@ -1,12 +1,608 @@
+use anyhow::anyhow;
+use kirbo_workspace::Workspace;
 use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+use std::env;
 use std::env::current_exe;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use toml_edit::DocumentMut;
 
-pub struct Env {
-	execpath: PathBuf,
+/// The name of the project config file, which lives at the root of a workspace
+pub const PROJECT_CONFIG_FILE_NAME: &str = ".kirborc";
+
+/// What kind of value a setting takes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+	String,
+	Bool,
+	Number,
+	OneOf(&'static [&'static str]),
+}
+
+/// A setting that can be configured, along with a description for `kirbo config list`
+pub struct Setting {
+	pub key: &'static str,
+	kind: Kind,
+	pub description: &'static str,
+	/// Secret settings never have their values printed, and can't be set by a project
+	pub secret: bool,
+}
+
+const fn setting(key: &'static str, kind: Kind, description: &'static str) -> Setting {
+	Setting {
+		key,
+		kind,
+		description,
+		secret: false,
+	}
+}
+
+pub const SETTINGS: &[Setting] = &[
+	setting(
+		"registry",
+		Kind::String,
+		"base URL of the registry to fetch packages from",
+	),
+	setting(
+		"cache-dir",
+		Kind::String,
+		"where downloaded tarballs are kept",
+	),
+	Setting {
+		secret: true,
+		..setting(
+			"auth-token",
+			Kind::String,
+			"sent as a bearer token when publishing",
+		)
+	},
+	setting(
+		"linker",
+		Kind::OneOf(&["hoisted", "nested"]),
+		"how node_modules is laid out",
+	),
+	setting(
+		"concurrency",
+		Kind::Number,
+		"how many tarballs to download at once",
+	),
+	setting(
+		"script-policy",
+		Kind::OneOf(&["allow-list", "all", "none"]),
+		"which dependencies get to run their install scripts",
+	),
+	setting(
+		"strict-peer-deps",
+		Kind::Bool,
+		"fail installs when a peer dependency can't be satisfied",
+	),
+	setting(
+		"engine-strict",
+		Kind::Bool,
+		"fail installs when a package doesn't support the current version of node",
+	),
+	setting(
+		"ignore-package-manager",
+		Kind::Bool,
+		"keep going in projects whose packageManager is yarn or pnpm",
+	),
+	setting(
+		"init-license",
+		Kind::String,
+		"the license that `kirbo init` gives new packages",
+	),
+];
+
+impl Setting {
+	/// Formats a value the way it would be written in a config file
+	pub fn format_value(&self, value: &str) -> String {
+		match self.kind {
+			Kind::Bool | Kind::Number => value.to_string(),
+			Kind::String | Kind::OneOf(_) => {
+				serde_json::to_string(value).unwrap_or_else(|_| value.to_string())
+			}
+		}
+	}
+}
+
+/// Looks up a setting by its key
+pub fn find_setting(key: &str) -> anyhow::Result<&'static Setting> {
+	SETTINGS
+		.iter()
+		.find(|setting| setting.key == key)
+		.ok_or_else(|| anyhow!("there's no setting named \"{}\"", key))
+}
+
+/// Where the value of a setting came from. Each one overrides the ones before it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+	Default,
+	/// The global config file, or a project's `.kirborc`
+	File(PathBuf),
+	/// The `"kirbo"` object of a project's package.json
+	PackageJson(PathBuf),
+	/// A `KIRBO_*` environment variable
+	Env(String),
+	/// A command line flag
+	Flag,
+}
+
+impl Origin {
+	/// Whether the value came from the project, which means whoever made the project
+	/// chose it, rather than whoever is running kirbo
+	pub fn is_project(&self) -> bool {
+		match self {
+			Origin::PackageJson(_) => true,
+			Origin::File(path) => path.file_name() == Some(PROJECT_CONFIG_FILE_NAME.as_ref()),
+			_ => false,
+		}
+	}
+}
+
+impl Display for Origin {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Origin::Default => write!(f, "default"),
+			Origin::File(path) => write!(f, "{}", path.display()),
+			Origin::PackageJson(path) => write!(f, "{} (\"kirbo\")", path.display()),
+			Origin::Env(name) => write!(f, "${}", name),
+			Origin::Flag => write!(f, "command line"),
+		}
+	}
+}
+
+/// How packages are arranged in `node_modules/`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LinkerMode {
+	/// Hoist as much as possible to the top, like npm and yarn do
+	#[default]
+	Hoisted,
+	/// Only direct dependencies go at the top, and everything else is nested beneath
+	/// whatever depends on it
+	Nested,
+}
+
+/// Which dependencies are allowed to run `preinstall`, `install`, and `postinstall`
+/// scripts
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ScriptPolicy {
+	/// Only the ones listed in `"kirbo": { "allowScripts" }`
+	#[default]
+	AllowList,
+	All,
+	None,
+}
+
+pub struct Config {
+	pub execpath: PathBuf,
+	/// Base URL of the registry to fetch packages from, always ending with a `/`
+	pub registry: String,
+	/// Where downloaded tarballs are kept
+	pub cache_dir: PathBuf,
+	/// Sent as a bearer token when publishing, unless the registry was chosen by the
+	/// project, since then the project could send it anywhere
+	pub auth_token: Option<String>,
+	pub linker: LinkerMode,
+	/// How many tarballs to download at once
+	pub concurrency: usize,
+	pub script_policy: ScriptPolicy,
+	/// Fail installs when a peer dependency can't be satisfied
+	pub strict_peer_deps: bool,
+	/// Fail installs when a package doesn't support the current version of node
+	pub engine_strict: bool,
+	/// Keep going in projects whose `packageManager` is yarn or pnpm
+	pub ignore_package_manager: bool,
+	/// The license that `kirbo init` gives new packages
+	pub init_license: String,
+	/// The value of every setting that has one, and where it came from
+	pub values: BTreeMap<&'static str, (String, Origin)>,
+}
+
+/// Values set by command line flags, which have to be added before the configuration
+/// is loaded
+static FLAGS: Mutex<Vec<(&'static str, String)>> = Mutex::new(vec![]);
+
+static LOADED: OnceCell<Config> = OnceCell::new();
+
+/// Sets a value from a command line flag, which overrides every other source
+pub fn set_flag(key: &str, value: &str) -> anyhow::Result<()> {
+	let setting = find_setting(key)?;
+	if LOADED.get().is_some() {
+		return Err(anyhow!(
+			"{} was set after the configuration was already loaded",
+			key
+		));
+	}
+	FLAGS.lock().unwrap().push((setting.key, value.to_string()));
+	Ok(())
+}
+
+/// Loads the configuration for the current directory, along with any flags set so
+/// far. Only the first call does anything, and it should happen before `CONFIG` is
+/// used, so that a configuration which isn't valid can be reported like any other
+/// error.
+pub fn load() -> anyhow::Result<&'static Config> {
+	LOADED.get_or_try_init(|| {
+		let dir = env::current_dir()?;
+		let flags = FLAGS.lock().unwrap().clone();
+		Config::load(&dir, &flags)
+	})
+}
+
+pub static CONFIG: Lazy<&'static Config> =
+	Lazy::new(|| load().expect("the configuration should be loaded before it's used"));
+
+/// The key of a setting as it's written in package.json, like `cacheDir`
+fn camel_case(key: &str) -> String {
+	let mut parts = key.split('-');
+	let first = parts.next().unwrap_or_default().to_string();
+	parts.fold(first, |mut name, part| {
+		let mut chars = part.chars();
+		if let Some(first) = chars.next() {
+			name.extend(first.to_uppercase());
+			name.push_str(chars.as_str());
+		}
+		name
+	})
+}
+
+/// The environment variable that sets a setting, like `KIRBO_CACHE_DIR`
+pub fn env_var(key: &str) -> String {
+	format!("KIRBO_{}", key.to_uppercase().replace('-', "_"))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+	match value {
+		"true" | "1" | "yes" => Some(true),
+		"false" | "0" | "no" | "" => Some(false),
+		_ => None,
+	}
+}
+
+/// Checks that a value makes sense for a setting
+pub fn validate(key: &str, value: &str) -> anyhow::Result<()> {
+	let setting = find_setting(key)?;
+	let expected = match setting.kind {
+		Kind::String => return Ok(()),
+		Kind::Bool => parse_bool(value)
+			.is_none()
+			.then(|| "true or false".to_string()),
+		Kind::Number => {
+			(!usize::from_str(value).is_ok_and(|it| it > 0)).then(|| "a number above 0".to_string())
+		}
+		Kind::OneOf(choices) => {
+			(!choices.contains(&value)).then(|| format!("one of {}", choices.join(", ")))
+		}
+	};
+	let Some(expected) = expected else {
+		return Ok(());
+	};
+
+	Err(anyhow!(
+		"\"{}\" isn't a valid value for {} (expected {})",
+		value,
+		key,
+		expected
+	))
+}
+
+/// Reads the settings from the text of a TOML config file, as strings
+fn parse_file(text: &str) -> anyhow::Result<Vec<(String, String)>> {
+	let document = text.parse::<DocumentMut>()?;
+	document
+		.iter()
+		.map(|(key, item)| {
+			let value = item
+				.as_value()
+				.ok_or_else(|| anyhow!("{} isn't a value", key))?;
+			let value = match value {
+				toml_edit::Value::String(it) => it.value().clone(),
+				toml_edit::Value::Boolean(it) => it.value().to_string(),
+				toml_edit::Value::Integer(it) => it.value().to_string(),
+				_ => return Err(anyhow!("{} should be a string, number, or boolean", key)),
+			};
+			Ok((key.to_string(), value))
+		})
+		.collect()
+}
+
+/// Picks out the settings from the `"kirbo"` object of a package.json. It also holds
+/// things like `allowScripts`, which aren't settings, so anything unknown is skipped.
+fn parse_package_json(kirbo: &serde_json::Value) -> Vec<(String, String)> {
+	SETTINGS
+		.iter()
+		.filter_map(|setting| {
+			let value = match kirbo.get(camel_case(setting.key))? {
+				serde_json::Value::String(it) => it.clone(),
+				serde_json::Value::Bool(it) => it.to_string(),
+				serde_json::Value::Number(it) => it.to_string(),
+				_ => return None,
+			};
+			Some((setting.key.to_string(), value))
+		})
+		.collect()
+}
+
+fn home_dir() -> Option<PathBuf> {
+	#[cfg(windows)]
+	let home = env::var_os("USERPROFILE");
+	#[cfg(not(windows))]
+	let home = env::var_os("HOME");
+
+	home.filter(|it| !it.is_empty()).map(PathBuf::from)
+}
+
+/// Where the global config file is, which is `~/.config/kirbo/config.toml` unless
+/// `XDG_CONFIG_HOME` says otherwise
+pub fn global_config_path() -> PathBuf {
+	env::var_os("XDG_CONFIG_HOME")
+		.filter(|it| !it.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| home_dir().map(|home| home.join(".config")))
+		.unwrap_or_else(env::temp_dir)
+		.join("kirbo")
+		.join("config.toml")
 }
 
-pub static ENV: Lazy<Env> = Lazy::new(|| {
-	let execpath = current_exe().unwrap();
-	Env { execpath }
-});
+/// The directory whose `.kirborc` applies to `dir`, which is the root of its
+/// workspace, or `dir` itself when it isn't in one
+pub fn project_dir(dir: &Path) -> PathBuf {
+	Workspace::new(dir)
+		.map(|workspace| workspace.root().to_path_buf())
+		.unwrap_or_else(|_| dir.to_path_buf())
+}
+
+fn default_value(key: &str) -> Option<String> {
+	let value = match key {
+		"registry" => "https://registry.npmjs.org/".to_string(),
+		"cache-dir" => env::var_os("XDG_CACHE_HOME")
+			.map(PathBuf::from)
+			.or_else(|| home_dir().map(|home| home.join(".cache")))
+			.map(|cache| cache.join("kirbo"))
+			.unwrap_or_else(|| env::temp_dir().join("kirbo"))
+			.to_string_lossy()
+			.to_string(),
+		"linker" => "hoisted".to_string(),
+		"concurrency" => "16".to_string(),
+		"script-policy" => "allow-list".to_string(),
+		"strict-peer-deps" | "engine-strict" | "ignore-package-manager" => "false".to_string(),
+		"init-license" => "MIT".to_string(),
+		_ => return None,
+	};
+	Some(value)
+}
+
+fn read_file(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+	match fs::read_to_string(path) {
+		Ok(text) => {
+			parse_file(&text).map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))
+		}
+		Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+		Err(err) => Err(anyhow!("failed to read {}: {}", path.display(), err)),
+	}
+}
+
+impl Config {
+	/// Loads every layer of configuration that applies to `dir`, from lowest to
+	/// highest priority: the defaults, the global config file, the project's
+	/// package.json and `.kirborc`, `KIRBO_*` environment variables, and `flags`
+	pub fn load(dir: &Path, flags: &[(&'static str, String)]) -> anyhow::Result<Config> {
+		let mut layers = Vec::<(Vec<(String, String)>, Origin)>::new();
+
+		let global = global_config_path();
+		layers.push((read_file(&global)?, Origin::File(global)));
+
+		let project = project_dir(dir);
+		let package_json = project.join("package.json");
+		let kirbo = fs::read_to_string(&package_json)
+			.ok()
+			.and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+			.and_then(|json| json.get("kirbo").cloned());
+		if let Some(kirbo) = kirbo {
+			layers.push((
+				parse_package_json(&kirbo),
+				Origin::PackageJson(package_json),
+			));
+		}
+		let rc = project.join(PROJECT_CONFIG_FILE_NAME);
+		layers.push((read_file(&rc)?, Origin::File(rc)));
+
+		let mut values = BTreeMap::new();
+		for setting in SETTINGS {
+			if let Some(value) = default_value(setting.key) {
+				values.insert(setting.key, (value, Origin::Default));
+			}
+		}
+		for (settings, origin) in layers {
+			for (key, value) in settings {
+				let setting = find_setting(&key).map_err(|err| anyhow!("{} in {}", err, origin))?;
+				if setting.secret && origin.is_project() {
+					return Err(anyhow!(
+						"{} can't be set in {}, since everyone with the project could read it",
+						key,
+						origin
+					));
+				}
+				values.insert(setting.key, (value, origin.clone()));
+			}
+		}
+		for setting in SETTINGS {
+			let name = env_var(setting.key);
+			if let Some(value) = env::var(&name).ok().filter(|it| !it.is_empty()) {
+				values.insert(setting.key, (value, Origin::Env(name)));
+			}
+		}
+		for (key, value) in flags {
+			values.insert(key, (value.clone(), Origin::Flag));
+		}
+
+		for (key, (value, origin)) in &values {
+			validate(key, value).map_err(|err| anyhow!("{} (from {})", err, origin))?;
+		}
+		Ok(Config::from_values(values))
+	}
+
+	/// Builds a config out of values which have all been validated already
+	fn from_values(values: BTreeMap<&'static str, (String, Origin)>) -> Config {
+		let get = |key: &str| values.get(key).map(|(value, _)| value.as_str());
+		let from_project = |key: &str| {
+			values
+				.get(key)
+				.is_some_and(|(_, origin)| origin.is_project())
+		};
+		let flag = |key: &str| get(key).and_then(parse_bool).unwrap_or_default();
+
+		let mut registry = get("registry").unwrap_or_default().to_string();
+		if !registry.ends_with('/') {
+			registry.push('/');
+		}
+
+		Config {
+			execpath: current_exe().unwrap(),
+			registry,
+			cache_dir: PathBuf::from(get("cache-dir").unwrap_or_default()),
+			auth_token: get("auth-token")
+				.filter(|_| !from_project("registry"))
+				.map(str::to_string),
+			linker: match get("linker") {
+				Some("nested") => LinkerMode::Nested,
+				_ => LinkerMode::Hoisted,
+			},
+			concurrency: get("concurrency")
+				.and_then(|it| usize::from_str(it).ok())
+				.unwrap_or(16),
+			script_policy: match get("script-policy") {
+				Some("all") => ScriptPolicy::All,
+				Some("none") => ScriptPolicy::None,
+				_ => ScriptPolicy::AllowList,
+			},
+			strict_peer_deps: flag("strict-peer-deps"),
+			engine_strict: flag("engine-strict"),
+			ignore_package_manager: flag("ignore-package-manager"),
+			init_license: get("init-license").unwrap_or("MIT").to_string(),
+			values,
+		}
+	}
+}
+
+/// Sets a value in the text of a TOML config file, keeping everything else about
+/// the file (like comments) the way it was
+pub fn set_in_file(text: &str, key: &str, value: &str) -> anyhow::Result<String> {
+	let setting = find_setting(key)?;
+	validate(key, value)?;
+
+	let mut document = text.parse::<DocumentMut>()?;
+	document[setting.key] = match setting.kind {
+		Kind::Bool => toml_edit::value(parse_bool(value).unwrap_or_default()),
+		Kind::Number => toml_edit::value(i64::from_str(value)?),
+		Kind::String | Kind::OneOf(_) => toml_edit::value(value),
+	};
+	Ok(document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names() {
+		assert_eq!(camel_case("registry"), "registry");
+		assert_eq!(camel_case("strict-peer-deps"), "strictPeerDeps");
+		assert_eq!(env_var("cache-dir"), "KIRBO_CACHE_DIR");
+	}
+
+	#[test]
+	fn validates() {
+		assert!(validate("linker", "nested").is_ok());
+		assert!(validate("linker", "isolated").is_err());
+		assert!(validate("concurrency", "8").is_ok());
+		assert!(validate("concurrency", "0").is_err());
+		assert!(validate("engine-strict", "1").is_ok());
+		assert!(validate("engine-strict", "maybe").is_err());
+		assert!(validate("nope", "").is_err());
+	}
+
+	#[test]
+	fn reads_layers() {
+		assert_eq!(
+			parse_file("# comment\nlinker = \"nested\"\nconcurrency = 4\nengine-strict = true\n")
+				.unwrap(),
+			vec![
+				("linker".to_string(), "nested".to_string()),
+				("concurrency".to_string(), "4".to_string()),
+				("engine-strict".to_string(), "true".to_string()),
+			]
+		);
+		assert!(parse_file("[table]\nkey = 1\n").is_err());
+
+		let kirbo = serde_json::json!({
+			"allowScripts": ["esbuild"],
+			"scriptPolicy": "none",
+			"strictPeerDeps": true,
+		});
+		assert_eq!(
+			parse_package_json(&kirbo),
+			vec![
+				("script-policy".to_string(), "none".to_string()),
+				("strict-peer-deps".to_string(), "true".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn edits_files() {
+		let text = "# keep me\nlinker = \"nested\"\n";
+		assert_eq!(
+			set_in_file(text, "concurrency", "4").unwrap(),
+			"# keep me\nlinker = \"nested\"\nconcurrency = 4\n"
+		);
+		assert_eq!(
+			set_in_file(text, "linker", "hoisted").unwrap(),
+			"# keep me\nlinker = \"hoisted\"\n"
+		);
+		assert!(set_in_file(text, "linker", "isolated").is_err());
+	}
+
+	#[test]
+	fn typed_values() {
+		let values = BTreeMap::from([
+			("registry", ("http://localhost".to_string(), Origin::Flag)),
+			("script-policy", ("none".to_string(), Origin::Default)),
+			("engine-strict", ("yes".to_string(), Origin::Default)),
+		]);
+		let config = Config::from_values(values);
+		assert_eq!(config.registry, "http://localhost/");
+		assert_eq!(config.script_policy, ScriptPolicy::None);
+		assert!(config.engine_strict);
+		assert_eq!(config.linker, LinkerMode::Hoisted);
+	}
+
+	#[test]
+	fn project_registries() {
+		let global = Origin::File(PathBuf::from("/home/kirbo/.config/kirbo/config.toml"));
+		let rc = Origin::File(PathBuf::from("/project/.kirborc"));
+		assert!(!global.is_project());
+		assert!(rc.is_project());
+		assert!(Origin::PackageJson(PathBuf::from("/project/package.json")).is_project());
+
+		let values = |registry: Origin| {
+			BTreeMap::from([
+				("registry", ("http://localhost".to_string(), registry)),
+				("auth-token", ("token".to_string(), global.clone())),
+			])
+		};
+		// The token stays with the registry that whoever owns it chose
+		let config = Config::from_values(values(Origin::Env("KIRBO_REGISTRY".to_string())));
+		assert_eq!(config.auth_token.as_deref(), Some("token"));
+		let config = Config::from_values(values(rc.clone()));
+		assert_eq!(config.auth_token, None);
+	}
+}
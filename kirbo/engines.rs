@@ -0,0 +1,73 @@
+use anyhow::anyhow;
+use kirbo_workspace::Package;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::lock::split_descriptor;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+/// Package managers which keep their own lock files and layouts, and would be
+/// confused by whatever kirbo leaves behind
+const OTHER_PACKAGE_MANAGERS: &[&str] = &["yarn", "pnpm"];
+
+/// The version of `node` on the `PATH`, if there is one
+pub fn node_version() -> Option<Version> {
+	let output = Command::new("node").arg("--version").output().ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	let version = String::from_utf8(output.stdout).ok()?;
+	Version::from_str(version.trim().trim_start_matches('v')).ok()
+}
+
+/// Whether `version` is within the `engines` range of a package. Like npm, a range
+/// that can't be parsed isn't satisfied by anything.
+pub fn satisfies(version: &Version, range: &str) -> bool {
+	SemverRange::from_str(range).is_ok_and(|range| version.satisfies(&range))
+}
+
+/// Refuses to go any further in a project that says it should be managed by yarn or
+/// pnpm, unless `force` is set
+pub fn check_package_manager(package: &Package, force: bool) -> anyhow::Result<()> {
+	let Some(package_manager) = &package.package_manager else {
+		return Ok(());
+	};
+	// The version can be followed by a hash, like `yarn@3.6.0+sha256.abc...`
+	let (name, _) = split_descriptor(package_manager);
+	if force || !OTHER_PACKAGE_MANAGERS.contains(&name) {
+		return Ok(());
+	}
+
+	Err(anyhow!(
+		"this project uses {} (from \"packageManager\" in package.json), so use that instead, or pass --force (or set KIRBO_IGNORE_PACKAGE_MANAGER=1) to use kirbo anyway",
+		package_manager
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn engines() {
+		let version = Version::from_str("20.11.0").unwrap();
+		assert!(satisfies(&version, ">=18"));
+		assert!(satisfies(&version, "^18.0.0 || ^20.0.0"));
+		assert!(!satisfies(&version, "<20"));
+		assert!(!satisfies(&version, "not a range"));
+	}
+
+	#[test]
+	fn package_managers() {
+		let package = |package_manager: &str| {
+			serde_json::from_str::<Package>(&format!(r#"{{ "packageManager": "{}" }}"#, package_manager))
+				.unwrap()
+		};
+		assert!(check_package_manager(&package("kirbo@0.0.2"), false).is_ok());
+		assert!(check_package_manager(&package("npm@10.0.0"), false).is_ok());
+		assert!(check_package_manager(&package("yarn@3.6.0+sha256.abc"), false).is_err());
+		assert!(check_package_manager(&package("pnpm@8.0.0"), false).is_err());
+		assert!(check_package_manager(&package("pnpm@8.0.0"), true).is_ok());
+	}
+}
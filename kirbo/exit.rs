@@ -0,0 +1,27 @@
+use std::fmt;
+use std::process::ExitStatus;
+
+/// An error for when a command has already said everything it needs to, and kirbo
+/// only has to exit with a particular code. It's turned into the exit code of the
+/// process by `program.rs`, so that everything is still cleaned up on the way out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExitCode(pub i32);
+
+impl ExitCode {
+	/// Passes the exit code of a child process along if it failed. Processes which
+	/// were killed by a signal don't have a code, so they become 1.
+	pub fn check(status: ExitStatus) -> Result<(), ExitCode> {
+		match status.success() {
+			true => Ok(()),
+			false => Err(ExitCode(status.code().unwrap_or(1))),
+		}
+	}
+}
+
+impl fmt::Display for ExitCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "exited with code {}", self.0)
+	}
+}
+
+impl std::error::Error for ExitCode {}
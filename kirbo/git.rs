@@ -0,0 +1,155 @@
+use anyhow::anyhow;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Runs git in `dir`, and returns its stdout if it succeeds
+fn git<S: AsRef<str>>(dir: &Path, args: &[S]) -> anyhow::Result<String> {
+	let output = Command::new("git")
+		.current_dir(dir)
+		.args(args.iter().map(AsRef::as_ref))
+		.output()
+		.map_err(|err| anyhow!("failed to run git: {}", err))?;
+
+	if !output.status.success() {
+		return Err(anyhow!(
+			"git {} failed: {}",
+			args.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(" "),
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The root of the git repository containing `dir`
+pub fn toplevel(dir: &Path) -> anyhow::Result<PathBuf> {
+	Ok(PathBuf::from(
+		git(dir, &["rev-parse", "--show-toplevel"])?.trim(),
+	))
+}
+
+/// Absolute paths of every file that has changed since `rev`, including changes that
+/// haven't been committed yet.
+pub fn changed_files(dir: &Path, rev: &str) -> anyhow::Result<Vec<PathBuf>> {
+	let toplevel = toplevel(dir)?;
+	let changed = git(&toplevel, &["diff", "--name-only", rev, "--"])?;
+	let untracked = git(&toplevel, &["ls-files", "--others", "--exclude-standard"])?;
+
+	Ok(
+		changed
+			.lines()
+			.chain(untracked.lines())
+			.filter(|line| !line.is_empty())
+			.map(|line| toplevel.join(line))
+			.collect(),
+	)
+}
+
+/// The contents of `path` as of `rev`, or `None` if it didn't exist yet
+pub fn show_file(dir: &Path, rev: &str, path: &Path) -> anyhow::Result<Option<String>> {
+	let toplevel = toplevel(dir)?;
+	let path = path.strip_prefix(&toplevel).unwrap_or(path);
+	let spec = format!("{}:{}", rev, path.to_string_lossy().replace('\\', "/"));
+
+	// Make sure the revision itself is valid, so that we only treat a missing file as
+	// a missing file.
+	git(
+		&toplevel,
+		&["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+	)?;
+	Ok(git(&toplevel, &["show", &spec]).ok())
+}
+
+/// Makes a bare clone of a repository at `dir`
+pub fn clone_bare(url: &str, dir: &Path) -> anyhow::Result<()> {
+	// Urls come from package.json files we don't control, so make sure git can never
+	// mistake one for an option
+	if url.starts_with('-') {
+		return Err(anyhow!("invalid git url {}", url));
+	}
+
+	let parent = dir.parent().unwrap_or_else(|| Path::new("."));
+	git(
+		parent,
+		&[
+			"clone",
+			"--bare",
+			"--quiet",
+			"--",
+			url,
+			&dir.to_string_lossy(),
+		],
+	)?;
+	Ok(())
+}
+
+/// The full sha of the commit that `rev` refers to
+pub fn resolve_commit(dir: &Path, rev: &str) -> anyhow::Result<String> {
+	Ok(
+		git(
+			dir,
+			&["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+		)?
+		.trim()
+		.to_string(),
+	)
+}
+
+/// Commits just the changes to `paths`, leaving anything else that's staged alone
+pub fn commit(dir: &Path, message: &str, paths: &[PathBuf]) -> anyhow::Result<()> {
+	let paths = paths
+		.iter()
+		.map(|path| path.to_string_lossy().to_string())
+		.collect::<Vec<_>>();
+
+	let mut add = vec!["add".to_string(), "--".to_string()];
+	add.extend(paths.iter().cloned());
+	git(dir, &add)?;
+
+	let mut commit = ["commit", "--quiet", "-m", message, "--"]
+		.map(str::to_string)
+		.to_vec();
+	commit.extend(paths);
+	git(dir, &commit)?;
+	Ok(())
+}
+
+/// Creates an annotated tag at `HEAD`, with its name as its message
+pub fn tag(dir: &Path, name: &str) -> anyhow::Result<()> {
+	git(dir, &["tag", "-a", name, "-m", name])?;
+	Ok(())
+}
+
+/// The name of every tag in a repository
+pub fn tags(dir: &Path) -> anyhow::Result<Vec<String>> {
+	Ok(
+		git(dir, &["tag", "--list"])?
+			.lines()
+			.filter(|line| !line.is_empty())
+			.map(str::to_string)
+			.collect(),
+	)
+}
+
+/// The contents of `path` as of `commit`, in a repository which might be bare
+pub fn read_file(dir: &Path, commit: &str, path: &str) -> anyhow::Result<String> {
+	git(dir, &["show", &format!("{}:{}", commit, path)])
+}
+
+/// Writes every file from `commit` into an uncompressed tarball at `output`,
+/// beneath a `package/` directory like npm does
+pub fn archive(dir: &Path, commit: &str, output: &Path) -> anyhow::Result<()> {
+	git(
+		dir,
+		&[
+			"archive",
+			"--format=tar",
+			"--prefix=package/",
+			"-o",
+			&output.to_string_lossy(),
+			commit,
+		],
+	)?;
+	Ok(())
+}
@@ -0,0 +1,314 @@
+use kirbo_workspace::Package;
+use kirbo_workspace::Workspace;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+
+/// A package.json in the workspace which depends on something in the lock
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Root {
+	/// The name of the workspace package, or its path relative to the workspace root
+	/// if it doesn't have one
+	pub name: String,
+	/// Which field of package.json the dependency is listed in
+	#[serde(rename = "type")]
+	pub kind: &'static str,
+}
+
+/// One step along a chain of dependencies
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Link {
+	pub name: String,
+	/// The range requested by the previous step of the chain
+	pub range: String,
+	/// The version that range was resolved to
+	pub version: String,
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Chain {
+	pub root: Root,
+	pub links: Vec<Link>,
+}
+
+/// The direct dependencies listed in a package.json, by descriptor
+pub fn package_dependencies(package: &Package) -> Vec<(&'static str, String)> {
+	let fields = [
+		("dependencies", &package.dependencies),
+		("devDependencies", &package.dev_dependencies),
+		("peerDependencies", &package.peer_dependencies),
+	];
+
+	fields
+		.into_iter()
+		.flat_map(|(kind, dependencies)| {
+			dependencies
+				.iter()
+				.map(move |(name, range)| (kind, format!("{}@{}", name, range)))
+		})
+		.collect()
+}
+
+/// The graph of packages in a lock, which can be walked backwards to find out how a
+/// package ended up being installed.
+pub struct DependencyGraph<'a> {
+	lock: &'a KirboLock,
+	/// Workspace packages which directly depend on each descriptor
+	roots: HashMap<String, Vec<Root>>,
+	/// Locked packages which directly depend on each descriptor
+	dependents: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+	pub fn new(lock: &'a KirboLock, workspace: &Workspace) -> Self {
+		let mut roots = HashMap::<String, Vec<Root>>::new();
+		for package_json in workspace.packages().chain([workspace.root_package()]) {
+			let name = package_json.1.name.clone().unwrap_or_else(|| {
+				let dir = package_json.dir();
+				let relative = dir.strip_prefix(workspace.root()).unwrap_or(dir);
+				format!("{}/package.json", relative.display())
+					.trim_start_matches('/')
+					.to_string()
+			});
+
+			for (kind, descriptor) in package_dependencies(&package_json.1) {
+				let root = Root {
+					name: name.clone(),
+					kind,
+				};
+				let entry = roots.entry(descriptor).or_default();
+				if !entry.contains(&root) {
+					entry.push(root);
+				}
+			}
+		}
+
+		let mut dependents = HashMap::<&str, Vec<&str>>::new();
+		for (descriptor, package) in &lock.packages {
+			// Workspace packages are roots in their own right, so we stop there rather
+			// than walking through them.
+			if package.resolved.starts_with("workspace:") {
+				continue;
+			}
+
+			for dependency in package
+				.dependencies
+				.iter()
+				.chain(&package.peer_dependencies)
+			{
+				dependents
+					.entry(dependency.as_str())
+					.or_default()
+					.push(descriptor.as_str());
+			}
+		}
+
+		DependencyGraph {
+			lock,
+			roots,
+			dependents,
+		}
+	}
+
+	/// Descriptors of every locked package with the given name
+	pub fn descriptors_named(&self, name: &str) -> Vec<&'a str> {
+		self
+			.lock
+			.packages
+			.keys()
+			.filter(|descriptor| split_descriptor(descriptor).0 == name)
+			.map(String::as_str)
+			.collect()
+	}
+
+	fn link(&self, descriptor: &str) -> Link {
+		let (name, range) = split_descriptor(descriptor);
+		Link {
+			name: name.to_string(),
+			range: range.to_string(),
+			version: self
+				.lock
+				.packages
+				.get(descriptor)
+				.map(|package| package.version.clone())
+				.unwrap_or_default(),
+		}
+	}
+
+	/// Every chain of dependencies leading from a workspace package to `descriptor`,
+	/// shortest first.
+	pub fn chains_to(&self, descriptor: &str) -> Vec<Chain> {
+		let mut chains = vec![];
+		let mut path = vec![descriptor];
+		self.walk(&mut path, &mut chains);
+		chains.sort_by(|a, b| a.links.len().cmp(&b.links.len()).then(a.cmp(b)));
+		chains.dedup();
+		chains
+	}
+
+	fn walk<'b>(&'b self, path: &mut Vec<&'b str>, chains: &mut Vec<Chain>) {
+		let node = *path.last().unwrap();
+
+		for root in self.roots.get(node).into_iter().flatten() {
+			chains.push(Chain {
+				root: root.clone(),
+				links: path.iter().rev().map(|it| self.link(it)).collect(),
+			});
+		}
+
+		for &parent in self.dependents.get(node).into_iter().flatten() {
+			if path.contains(&parent) {
+				continue;
+			}
+			path.push(parent);
+			self.walk(path, chains);
+			path.pop();
+		}
+	}
+
+	/// Everything which directly requests `descriptor`. Workspace packages are listed
+	/// by name, and locked packages as `name@version`.
+	pub fn requested_by(&self, descriptor: &str) -> Vec<String> {
+		let mut requesters = self
+			.roots
+			.get(descriptor)
+			.into_iter()
+			.flatten()
+			.map(|root| root.name.clone())
+			.chain(
+				self
+					.dependents
+					.get(descriptor)
+					.into_iter()
+					.flatten()
+					.map(|parent| {
+						let link = self.link(parent);
+						format!("{}@{}", link.name, link.version)
+					}),
+			)
+			.collect::<Vec<_>>();
+		requesters.sort();
+		requesters.dedup();
+		requesters
+	}
+
+	/// Descriptors of every locked package reachable from `descriptor`, including
+	/// itself.
+	pub fn transitive_dependencies(&self, descriptor: &str) -> BTreeMap<&'a str, usize> {
+		let mut found = BTreeMap::new();
+		let mut queue = vec![(descriptor.to_string(), 0)];
+
+		while let Some((next, depth)) = queue.pop() {
+			let Some((descriptor, package)) = self.lock.packages.get_key_value(&next) else {
+				continue;
+			};
+			if found.contains_key(descriptor.as_str()) {
+				continue;
+			}
+			found.insert(descriptor.as_str(), depth);
+			queue.extend(
+				package
+					.dependencies
+					.iter()
+					.map(|dependency| (dependency.clone(), depth + 1)),
+			);
+		}
+
+		found
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lock::KirboLockPackage;
+	use std::path::Path;
+
+	fn package(version: &str, dependencies: &[&str]) -> KirboLockPackage {
+		KirboLockPackage {
+			version: version.to_string(),
+			resolved: format!("https://registry.npmjs.org/-/{}.tgz", version),
+			dependencies: dependencies.iter().map(|it| it.to_string()).collect(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn chains() {
+		let workspace = Workspace::new(Path::new("./tests/testdata/43-why/")).unwrap();
+		let lock = KirboLock {
+			lock_version: 1,
+			binaries: BTreeMap::new(),
+			packages: BTreeMap::from([
+				(
+					"react@^18.2.0".to_string(),
+					package("18.2.0", &["loose-envify@^1.1.0"]),
+				),
+				(
+					"react-dom@^18.2.0".to_string(),
+					package(
+						"18.2.0",
+						&["loose-envify@^1.1.0", "scheduler@^0.23.0", "react@^18.2.0"],
+					),
+				),
+				(
+					"scheduler@^0.23.0".to_string(),
+					package("0.23.0", &["loose-envify@^1.1.0"]),
+				),
+				(
+					"loose-envify@^1.1.0".to_string(),
+					package("1.4.0", &["js-tokens@^3.0.0 || ^4.0.0"]),
+				),
+				(
+					"js-tokens@^3.0.0 || ^4.0.0".to_string(),
+					package("4.0.0", &[]),
+				),
+			]),
+		};
+		let graph = DependencyGraph::new(&lock, &workspace);
+
+		assert_eq!(graph.descriptors_named("react"), vec!["react@^18.2.0"]);
+
+		let chains = graph.chains_to("scheduler@^0.23.0");
+		assert_eq!(
+			chains,
+			vec![Chain {
+				root: Root {
+					name: "app".to_string(),
+					kind: "dependencies",
+				},
+				links: vec![
+					Link {
+						name: "react-dom".to_string(),
+						range: "^18.2.0".to_string(),
+						version: "18.2.0".to_string(),
+					},
+					Link {
+						name: "scheduler".to_string(),
+						range: "^0.23.0".to_string(),
+						version: "0.23.0".to_string(),
+					},
+				],
+			}]
+		);
+
+		// app -> react, app -> react-dom, app -> react-dom -> react, and the same
+		// through scheduler, plus the dev dependency from the workspace root
+		let chains = graph.chains_to("loose-envify@^1.1.0");
+		assert_eq!(chains.len(), 5);
+		assert_eq!(chains[0].root.name, "app");
+		assert_eq!(chains[0].links.len(), 2);
+		assert!(chains
+			.iter()
+			.any(|chain| chain.root.kind == "devDependencies" && chain.root.name == "package.json"));
+
+		assert_eq!(graph.transitive_dependencies("react@^18.2.0").len(), 3);
+		assert_eq!(
+			graph.requested_by("react@^18.2.0"),
+			vec!["app", "package.json", "react-dom@18.2.0"]
+		);
+	}
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::LinkerMode;
+use crate::resolver::Resolution;
+use crate::resolver::ResolvedPackages;
+use crate::semver::Version;
+
+/// Where each package should be installed, relative to the root of the workspace,
+/// mapped to its descriptor
+pub type Layout = BTreeMap<PathBuf, String>;
+
+/// The package that `name` resolves to from a package installed at `dir`, following
+/// the same lookup that node does.
+fn lookup<'a>(layout: &'a Layout, dir: &Path, name: &str) -> Option<&'a String> {
+	dir
+		.ancestors()
+		.find_map(|ancestor| layout.get(&ancestor.join("node_modules").join(name)))
+}
+
+/// Decides where every resolved package should go in `node_modules/`. When hoisting,
+/// as much as possible goes at the top, and otherwise only the root's own
+/// dependencies do. Any package which needs a different version than the one it
+/// would find gets its own copy nested beneath it.
+pub fn plan(
+	resolved: &ResolvedPackages,
+	root_dependencies: &HashMap<String, String>,
+	root: &Path,
+	mode: LinkerMode,
+) -> Layout {
+	let version_of = |descriptor: &str| {
+		resolved
+			.get(descriptor)
+			.map(|package| package.version.as_str())
+	};
+
+	// Anything the root asks for directly gets first pick of the top level, followed
+	// by workspace packages (and other linked packages), and otherwise we pick
+	// whichever version is depended on the most.
+	let mut hoisted = BTreeMap::<&str, String>::new();
+	let mut root_dependencies = root_dependencies.iter().collect::<Vec<_>>();
+	root_dependencies.sort();
+	for (name, range) in root_dependencies {
+		let descriptor = format!("{}@{}", name, range);
+		if resolved.contains_key(&descriptor) {
+			hoisted.insert(name, descriptor);
+		}
+	}
+	for (descriptor, package) in resolved {
+		if matches!(
+			package.resolution,
+			Resolution::Workspace { .. } | Resolution::Directory { .. }
+		) {
+			hoisted
+				.entry(&package.name)
+				.or_insert_with(|| descriptor.clone());
+		}
+	}
+
+	if mode == LinkerMode::Hoisted {
+		let mut popularity = HashMap::<&str, usize>::new();
+		for package in resolved.values() {
+			for (name, range) in &package.dependencies {
+				if let Some((descriptor, _)) = resolved.get_key_value(&format!("{}@{}", name, range)) {
+					*popularity.entry(descriptor.as_str()).or_default() += 1;
+				}
+			}
+		}
+		let mut candidates = resolved.iter().collect::<Vec<_>>();
+		candidates.sort_by_cached_key(|(descriptor, package)| {
+			(
+				std::cmp::Reverse(popularity.get(descriptor.as_str()).copied().unwrap_or(0)),
+				std::cmp::Reverse(Version::from_str(&package.version).ok()),
+			)
+		});
+		for (descriptor, package) in candidates {
+			hoisted
+				.entry(&package.name)
+				.or_insert_with(|| descriptor.clone());
+		}
+	}
+
+	let mut layout = hoisted
+		.into_iter()
+		.map(|(name, descriptor)| (Path::new("node_modules").join(name), descriptor))
+		.collect::<Layout>();
+
+	// Walk down from everything at the top, nesting anything that doesn't find the
+	// version it asked for.
+	let mut queue = layout
+		.iter()
+		.map(|(path, descriptor)| {
+			let dir = match &resolved[descriptor].resolution {
+				Resolution::Workspace { path } | Resolution::Directory { path, .. } => {
+					path.strip_prefix(root).unwrap_or(path).to_path_buf()
+				}
+				_ => path.clone(),
+			};
+			(dir, descriptor.clone())
+		})
+		.collect::<Vec<_>>();
+
+	while let Some((dir, descriptor)) = queue.pop() {
+		for (name, range) in &resolved[&descriptor].dependencies {
+			let dependency = format!("{}@{}", name, range);
+			let Some(version) = version_of(&dependency) else {
+				continue;
+			};
+
+			let found = lookup(&layout, &dir, name).and_then(|it| version_of(it));
+			if found == Some(version) {
+				continue;
+			}
+
+			let path = dir.join("node_modules").join(name);
+			layout.insert(path.clone(), dependency.clone());
+			queue.push((path, dependency));
+		}
+
+		// Peers have to be found from wherever the package itself is installed, so any
+		// that aren't visible yet get placed alongside it
+		for (name, range) in &resolved[&descriptor].peer_dependencies {
+			let peer = format!("{}@{}", name, range);
+			if version_of(&peer).is_none() || lookup(&layout, &dir, name).is_some() {
+				continue;
+			}
+
+			let node_modules = dir
+				.ancestors()
+				.find(|ancestor| ancestor.file_name() == Some("node_modules".as_ref()))
+				.map(Path::to_path_buf)
+				.unwrap_or_else(|| dir.join("node_modules"));
+			let path = node_modules.join(name);
+			layout.insert(path.clone(), peer.clone());
+			queue.push((path, peer));
+		}
+	}
+
+	layout
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::resolver::ResolvedPackage;
+
+	fn package(name: &str, version: &str, dependencies: &[(&str, &str)]) -> ResolvedPackage {
+		ResolvedPackage {
+			name: name.to_string(),
+			version: version.to_string(),
+			resolution: Resolution::Registry {
+				tarball: String::new(),
+				integrity: None,
+			},
+			dependencies: dependencies
+				.iter()
+				.map(|(name, range)| (name.to_string(), range.to_string()))
+				.collect(),
+			optional_dependencies: Default::default(),
+			peer_dependencies: Default::default(),
+			overrides: Default::default(),
+		}
+	}
+
+	#[test]
+	fn nests_conflicting_versions() {
+		let resolved = ResolvedPackages::from([
+			(
+				"left@^1.0.0".to_string(),
+				package("left", "1.0.0", &[("shared", "^1.0.0")]),
+			),
+			(
+				"right@^1.0.0".to_string(),
+				package("right", "1.0.0", &[("shared", "^2.0.0")]),
+			),
+			(
+				"middle@^1.0.0".to_string(),
+				package("middle", "1.0.0", &[("shared", "^2.0.0")]),
+			),
+			("shared@^1.0.0".to_string(), package("shared", "1.1.0", &[])),
+			("shared@^2.0.0".to_string(), package("shared", "2.0.0", &[])),
+		]);
+		let root_dependencies = HashMap::from([
+			("left".to_string(), "^1.0.0".to_string()),
+			("middle".to_string(), "^1.0.0".to_string()),
+			("right".to_string(), "^1.0.0".to_string()),
+		]);
+
+		let layout = plan(
+			&resolved,
+			&root_dependencies,
+			Path::new("/"),
+			LinkerMode::Hoisted,
+		);
+		assert_eq!(
+			layout,
+			Layout::from([
+				(
+					PathBuf::from("node_modules/left"),
+					"left@^1.0.0".to_string()
+				),
+				(
+					PathBuf::from("node_modules/left/node_modules/shared"),
+					"shared@^1.0.0".to_string()
+				),
+				(
+					PathBuf::from("node_modules/middle"),
+					"middle@^1.0.0".to_string()
+				),
+				(
+					PathBuf::from("node_modules/right"),
+					"right@^1.0.0".to_string()
+				),
+				(
+					PathBuf::from("node_modules/shared"),
+					"shared@^2.0.0".to_string()
+				),
+			])
+		);
+	}
+
+	#[test]
+	fn nests_everything() {
+		let resolved = ResolvedPackages::from([
+			(
+				"left@^1.0.0".to_string(),
+				package("left", "1.0.0", &[("shared", "^1.0.0")]),
+			),
+			("shared@^1.0.0".to_string(), package("shared", "1.1.0", &[])),
+		]);
+		let root_dependencies = HashMap::from([("left".to_string(), "^1.0.0".to_string())]);
+
+		let layout = plan(
+			&resolved,
+			&root_dependencies,
+			Path::new("/"),
+			LinkerMode::Nested,
+		);
+		assert_eq!(
+			layout,
+			Layout::from([
+				(
+					PathBuf::from("node_modules/left"),
+					"left@^1.0.0".to_string()
+				),
+				(
+					PathBuf::from("node_modules/left/node_modules/shared"),
+					"shared@^1.0.0".to_string()
+				),
+			])
+		);
+	}
+}
@@ -0,0 +1,26 @@
+pub mod advisories;
+pub mod commands;
+pub mod config;
+pub mod engines;
+pub mod exit;
+pub mod git;
+pub mod graph;
+pub mod layout;
+pub mod linker;
+pub mod lock;
+pub mod manifest;
+pub mod npm;
+pub mod options;
+pub mod overrides;
+pub mod pack;
+pub mod patch;
+pub mod platform;
+pub mod resolver;
+pub mod sandbox;
+pub mod scripts;
+pub mod semver;
+pub mod sources;
+pub mod specifier;
+pub mod store;
+pub mod temporary;
+pub mod workspace;
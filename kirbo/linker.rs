@@ -0,0 +1,206 @@
+use flate2::read::GzDecoder;
+use kirbo_workspace::Package;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use tar::EntryType;
+
+/// Builds a path to `target` relative to the directory `from`. Both paths must be
+/// relative to the same base (or both absolute).
+fn relative_path(from: &Path, target: &Path) -> PathBuf {
+	let from = from.components().collect::<Vec<_>>();
+	let target = target.components().collect::<Vec<_>>();
+	let common = from.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+	let mut path = PathBuf::new();
+	for _ in common..from.len() {
+		path.push("..");
+	}
+	for component in &target[common..] {
+		path.push(component);
+	}
+	path
+}
+
+/// Removes whatever is at `path`, whether it's a directory, a file, or a symlink
+fn remove_existing(path: &Path) -> io::Result<()> {
+	match fs::symlink_metadata(path) {
+		Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+		Ok(_) => fs::remove_file(path),
+		Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+		Err(err) => Err(err),
+	}
+}
+
+/// Creates a link at `link` (somewhere inside of `root`) so that it resolves to the
+/// package at `target`, replacing anything that was already there.
+pub fn link_package(root: &Path, link: &Path, target: &Path) -> io::Result<()> {
+	let parent = link.parent().unwrap();
+	fs::create_dir_all(parent)?;
+
+	remove_existing(link)?;
+
+	// Anything outside of the root has to be linked to by its absolute path
+	let target = match target.strip_prefix(root) {
+		Ok(target) => relative_path(parent.strip_prefix(root).unwrap_or(parent), target),
+		Err(_) => target.to_path_buf(),
+	};
+
+	#[cfg(unix)]
+	std::os::unix::fs::symlink(target, link)?;
+	#[cfg(windows)]
+	std::os::windows::fs::symlink_dir(target, link)?;
+
+	Ok(())
+}
+
+/// Where an executable listed in a package's `bin` actually is. Paths come from
+/// strangers too, so anything that would point outside of the package is refused.
+fn bin_target(dir: &Path, path: &str) -> Option<PathBuf> {
+	let path = Path::new(path);
+	let inside = path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+	let target = dir.join(
+		path
+			.components()
+			.filter(|it| *it != Component::CurDir)
+			.collect::<PathBuf>(),
+	);
+	(inside && target != dir).then_some(target)
+}
+
+/// Links each executable of the package in `dir` into `node_modules/.bin/`, and
+/// makes sure that it can actually be executed. `dir` must be inside of `root`.
+pub fn link_bins(root: &Path, dir: &Path, package: &Package) -> io::Result<()> {
+	let Some(bin) = &package.bin else {
+		return Ok(());
+	};
+	let bin_dir = root.join("node_modules/.bin");
+	fs::create_dir_all(&bin_dir)?;
+
+	for (name, path) in bin.commands(package.name.as_deref().unwrap_or_default()) {
+		// Names come from strangers, so they don't get to point outside of `.bin/`
+		if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+			continue;
+		}
+		let Some(target) = bin_target(dir, path) else {
+			continue;
+		};
+		let link = bin_dir.join(name);
+		remove_existing(&link)?;
+
+		let relative = relative_path(
+			Path::new("node_modules/.bin"),
+			target.strip_prefix(root).unwrap_or(&target),
+		);
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			if let Ok(metadata) = fs::metadata(&target) {
+				let mut permissions = metadata.permissions();
+				permissions.set_mode(permissions.mode() | 0o111);
+				fs::set_permissions(&target, permissions)?;
+			}
+			std::os::unix::fs::symlink(relative, link)?;
+		}
+		// Windows can't run a script through a symlink, so it gets a shim instead
+		#[cfg(windows)]
+		fs::write(
+			link.with_extension("cmd"),
+			format!("@node \"%~dp0\\{}\" %*\r\n", relative.display()),
+		)?;
+	}
+
+	Ok(())
+}
+
+/// Writes a package into `dir` from a list of files, replacing anything that was
+/// already there.
+pub fn write_package(dir: &Path, files: &[(&str, &str)]) -> io::Result<()> {
+	remove_existing(dir)?;
+	fs::create_dir_all(dir)?;
+	for (file, contents) in files {
+		fs::write(dir.join(file), contents)?;
+	}
+
+	Ok(())
+}
+
+/// Extracts a package tarball into `dir`, replacing anything that was already there.
+/// Tarballs from the registry put everything inside of a top level folder (usually
+/// `package/`), which is stripped off.
+pub fn extract_package(tarball: &Path, dir: &Path) -> io::Result<()> {
+	remove_existing(dir)?;
+	fs::create_dir_all(dir)?;
+
+	let mut archive = tar::Archive::new(GzDecoder::new(File::open(tarball)?));
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let path = entry.path()?.into_owned();
+
+		// Refuse anything that would end up outside of `dir`
+		let mut components = path.components();
+		components.next();
+		if !components
+			.clone()
+			.all(|component| matches!(component, Component::Normal(_)))
+		{
+			continue;
+		}
+		let target = dir.join(components.as_path());
+		if target == dir {
+			continue;
+		}
+
+		match entry.header().entry_type() {
+			EntryType::Directory => fs::create_dir_all(&target)?,
+			EntryType::Regular | EntryType::Continuous => {
+				fs::create_dir_all(target.parent().unwrap())?;
+				entry.unpack(&target)?;
+			}
+			// Links and devices have no business being in a package
+			_ => (),
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn relative_paths() {
+		assert_eq!(
+			relative_path(Path::new("node_modules"), Path::new("packages/ui")),
+			Path::new("../packages/ui")
+		);
+		assert_eq!(
+			relative_path(Path::new("node_modules/@kirbo"), Path::new("packages/ui")),
+			Path::new("../../packages/ui")
+		);
+	}
+
+	#[test]
+	fn bin_targets() {
+		let dir = Path::new("node_modules/left");
+		assert_eq!(
+			bin_target(dir, "./bin/left.js"),
+			Some(PathBuf::from("node_modules/left/bin/left.js"))
+		);
+		assert_eq!(
+			bin_target(dir, "cli.js"),
+			Some(PathBuf::from("node_modules/left/cli.js"))
+		);
+		assert_eq!(bin_target(dir, "../../../../home/u/.bashrc"), None);
+		assert_eq!(bin_target(dir, "bin/../../right/cli.js"), None);
+		assert_eq!(bin_target(dir, "/etc/passwd"), None);
+		assert_eq!(bin_target(dir, "."), None);
+	}
+}
@@ -2,29 +2,214 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::resolver::Resolution;
+use crate::resolver::ResolvedPackages;
+use crate::store::sha512_base64;
+
+pub const LOCK_FILE_NAME: &str = "Kirbo.lock";
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct KirboLock {
-	lock_version: usize,
+	pub lock_version: usize,
 	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
-	binaries: BTreeMap<String, String>,
+	pub binaries: BTreeMap<String, String>,
 	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
-	packages: BTreeMap<String, KirboLockPackage>,
+	pub packages: BTreeMap<String, KirboLockPackage>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct KirboLockPackage {
-	// version: String,
-	resolved: String,
-	sha512: String,
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub version: String,
+	pub resolved: String,
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub sha512: String,
+	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+	pub dependencies: BTreeSet<String>,
+	/// The subset of `dependencies` which are optional. Lock files only include the
+	/// optional dependencies that could be installed on the platform they were created
+	/// for.
 	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
-	dependencies: BTreeSet<String>,
+	pub optional_dependencies: BTreeSet<String>,
 	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
-	peer_dependencies: BTreeSet<String>,
+	pub peer_dependencies: BTreeSet<String>,
 	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
-	dev_dependencies: BTreeSet<String>,
+	pub dev_dependencies: BTreeSet<String>,
+	/// Descriptors which this package was installed in place of because of an override
+	/// or resolution in the root package.json
+	#[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+	pub overrides: BTreeSet<String>,
+	/// The sha512 of the patch from `patchedDependencies` which was applied to this
+	/// package after it was extracted
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub patch: String,
+}
+
+/// Splits a descriptor like `@scope/name@^1.0.0` into its name and range
+pub fn split_descriptor(descriptor: &str) -> (&str, &str) {
+	match descriptor[1.min(descriptor.len())..].find('@') {
+		Some(at) => (&descriptor[..at + 1], &descriptor[at + 2..]),
+		None => (descriptor, ""),
+	}
+}
+
+impl KirboLock {
+	/// Builds a lock from a resolved dependency graph. Paths of workspace packages are
+	/// recorded relative to `root`.
+	pub fn from_resolved(resolved: &ResolvedPackages, root: &Path) -> Self {
+		let packages = resolved
+			.iter()
+			.map(|(descriptor, package)| {
+				let (resolved, sha512) = match &package.resolution {
+					Resolution::Registry { tarball, integrity } => (
+						tarball.clone(),
+						integrity
+							.as_deref()
+							.and_then(|integrity| integrity.strip_prefix("sha512-"))
+							.unwrap_or_default()
+							.to_string(),
+					),
+					Resolution::Firewall { substitution } => (
+						format!("firewall:{}@{}", substitution.name, substitution.version),
+						sha512_base64(substitution.source.as_bytes()),
+					),
+					Resolution::Git {
+						url,
+						commit,
+						integrity,
+					} => (
+						format!("git+{}#{}", url, commit),
+						integrity
+							.strip_prefix("sha512-")
+							.unwrap_or_default()
+							.to_string(),
+					),
+					Resolution::Tarball { url, integrity } => (
+						url.clone(),
+						integrity
+							.strip_prefix("sha512-")
+							.unwrap_or_default()
+							.to_string(),
+					),
+					Resolution::Directory { path, link } => {
+						let path = path.strip_prefix(root).unwrap_or(path);
+						(
+							format!(
+								"{}:{}",
+								if *link { "link" } else { "file" },
+								path.to_string_lossy().replace('\\', "/")
+							),
+							String::new(),
+						)
+					}
+					Resolution::Workspace { path } => {
+						let path = path.strip_prefix(root).unwrap_or(path);
+						(
+							format!("workspace:{}", path.to_string_lossy().replace('\\', "/")),
+							String::new(),
+						)
+					}
+				};
+
+				let lock_package = KirboLockPackage {
+					version: package.version.clone(),
+					resolved,
+					sha512,
+					dependencies: package
+						.dependencies
+						.iter()
+						.map(|(name, range)| format!("{}@{}", name, range))
+						.collect(),
+					optional_dependencies: package
+						.dependencies
+						.iter()
+						.filter(|(name, _)| package.optional_dependencies.contains(*name))
+						.map(|(name, range)| format!("{}@{}", name, range))
+						.collect(),
+					peer_dependencies: package
+						.peer_dependencies
+						.iter()
+						.map(|(name, range)| format!("{}@{}", name, range))
+						.collect(),
+					overrides: package.overrides.clone(),
+					..Default::default()
+				};
+
+				(descriptor.clone(), lock_package)
+			})
+			.collect();
+
+		KirboLock {
+			lock_version: 1,
+			binaries: BTreeMap::new(),
+			packages,
+		}
+	}
+
+	/// Reads the lock from the root of a workspace, if there is one
+	pub fn read(root: &Path) -> anyhow::Result<Option<Self>> {
+		match fs::read_to_string(root.join(LOCK_FILE_NAME)) {
+			Ok(text) => Ok(Some(serde_yaml::from_str(&text)?)),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(err.into()),
+		}
+	}
+
+	pub fn write(&self, root: &Path) -> anyhow::Result<()> {
+		fs::write(root.join(LOCK_FILE_NAME), serde_yaml::to_string(self)?)?;
+		Ok(())
+	}
+
+	/// Names of every package whose entry differs between `self` and `other`, along
+	/// with every package that depends on one of them (directly or not).
+	pub fn changed_packages(&self, other: &KirboLock) -> BTreeSet<String> {
+		let mut changed = self
+			.packages
+			.iter()
+			.filter(|(descriptor, package)| other.packages.get(*descriptor) != Some(package))
+			.chain(
+				other
+					.packages
+					.iter()
+					.filter(|(descriptor, _)| !self.packages.contains_key(*descriptor)),
+			)
+			.map(|(descriptor, _)| descriptor.clone())
+			.collect::<BTreeSet<_>>();
+
+		// Keep marking dependents until we stop finding new ones
+		loop {
+			let dependents = self
+				.packages
+				.iter()
+				.chain(&other.packages)
+				.filter(|(descriptor, package)| {
+					!changed.contains(*descriptor)
+						&& package
+							.dependencies
+							.iter()
+							.chain(&package.peer_dependencies)
+							.any(|dependency| changed.contains(dependency))
+				})
+				.map(|(descriptor, _)| descriptor.clone())
+				.collect::<Vec<_>>();
+
+			if dependents.is_empty() {
+				break;
+			}
+			changed.extend(dependents);
+		}
+
+		changed
+			.iter()
+			.map(|descriptor| split_descriptor(descriptor).0.to_string())
+			.collect()
+	}
 }
 
 #[cfg(test)]
@@ -50,7 +235,7 @@ mod tests {
 
 		assert_eq!(serde_yaml::to_string(&lock_object).unwrap(), lock_snapshot);
 		assert_eq!(
-			serde_yaml::from_str::<KirboLock>(&lock_snapshot).unwrap(),
+			serde_yaml::from_str::<KirboLock>(lock_snapshot).unwrap(),
 			lock_object
 		);
 	}
@@ -74,8 +259,57 @@ mod tests {
 
 		assert_eq!(serde_yaml::to_string(&lock_object).unwrap(), lock_snapshot);
 		assert_eq!(
-			serde_yaml::from_str::<KirboLock>(&lock_snapshot).unwrap(),
+			serde_yaml::from_str::<KirboLock>(lock_snapshot).unwrap(),
 			lock_object
 		);
 	}
+
+	#[test]
+	fn descriptors() {
+		assert_eq!(
+			split_descriptor("succulent@^0.20.0"),
+			("succulent", "^0.20.0")
+		);
+		assert_eq!(
+			split_descriptor("@mckayla/prettier@^1.0.3"),
+			("@mckayla/prettier", "^1.0.3")
+		);
+		assert_eq!(split_descriptor("prettier"), ("prettier", ""));
+	}
+
+	#[test]
+	fn changed_packages() {
+		let package = |resolved: &str, dependencies: &[&str]| KirboLockPackage {
+			resolved: resolved.to_string(),
+			sha512: "abcdefghijklmnopqrstuvwxyz".to_string(),
+			dependencies: dependencies.iter().map(|it| it.to_string()).collect(),
+			..Default::default()
+		};
+
+		let before = KirboLock {
+			lock_version: 1,
+			binaries: BTreeMap::new(),
+			packages: BTreeMap::from([
+				(
+					"a@^1.0.0".to_string(),
+					package("a-1.0.0.tgz", &["b@^1.0.0"]),
+				),
+				("b@^1.0.0".to_string(), package("b-1.0.0.tgz", &[])),
+				("c@^1.0.0".to_string(), package("c-1.0.0.tgz", &[])),
+			]),
+		};
+		let mut after = before.clone();
+		after
+			.packages
+			.insert("b@^1.0.0".to_string(), package("b-1.0.1.tgz", &[]));
+		after
+			.packages
+			.insert("d@^1.0.0".to_string(), package("d-1.0.0.tgz", &[]));
+
+		assert_eq!(
+			before.changed_packages(&after),
+			BTreeSet::from(["a".to_string(), "b".to_string(), "d".to_string()])
+		);
+		assert!(before.changed_packages(&before).is_empty());
+	}
 }
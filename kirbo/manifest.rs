@@ -0,0 +1,286 @@
+use std::ops::Range;
+
+/// Finds the span of the value at `path` in the text of a JSON document, where each
+/// element of the path is a key of a nested object. Working with spans rather than
+/// parsing and reserializing means that an edit can't change anything besides the
+/// value itself, so indentation, key order, and trailing newlines all survive.
+pub fn find_value(text: &str, path: &[&str]) -> Option<Range<usize>> {
+	let mut scanner = Scanner {
+		text: text.as_bytes(),
+		position: 0,
+	};
+	let mut value = scanner.value()?;
+
+	for key in path {
+		let mut scanner = Scanner {
+			text: text.as_bytes(),
+			position: value.start,
+		};
+		value = scanner.member(key)?;
+	}
+
+	Some(value)
+}
+
+/// Replaces the value at `path` with a string, if there already is a value there
+pub fn set_string(text: &str, path: &[&str], value: &str) -> Option<String> {
+	let span = find_value(text, path)?;
+	Some(splice(text, span, &serde_json::to_string(value).ok()?))
+}
+
+/// Sets the value at `path` to some already serialized JSON, adding it to the end of
+/// its object if it isn't there yet. The object itself has to exist already. New
+/// members are indented like the ones around them.
+pub fn set_json(text: &str, path: &[&str], json: &str) -> Option<String> {
+	if let Some(span) = find_value(text, path) {
+		return Some(splice(text, span, json));
+	}
+
+	let (key, parent) = path.split_last()?;
+	let object = find_value(text, parent)?;
+	let members = Scanner {
+		text: text.as_bytes(),
+		position: object.start,
+	}
+	.members()?;
+	let key = serde_json::to_string(key).ok()?;
+
+	match (members.first(), members.last()) {
+		(Some((first_key, first_value)), Some((_, last_value))) => {
+			let indent = &text[object.start + 1..first_key.start];
+			// Keys are sometimes padded to line up their values, which we don't bother to do
+			let colon = match text[first_key.end..first_value.start].ends_with(' ') {
+				true => ": ",
+				false => ":",
+			};
+			Some(splice(
+				text,
+				last_value.end..last_value.end,
+				&format!(",{}{}{}{}", indent, key, colon, json),
+			))
+		}
+		_ => {
+			let line = &text[text[..object.start].rfind('\n').map_or(0, |i| i + 1)..];
+			let base = &line[..line.len() - line.trim_start().len()];
+			Some(splice(
+				text,
+				object.start + 1..object.end - 1,
+				&format!("\n{}{}{}: {}\n{}", base, indent_unit(text), key, json, base),
+			))
+		}
+	}
+}
+
+fn splice(text: &str, span: Range<usize>, replacement: &str) -> String {
+	let mut edited = String::with_capacity(text.len() + replacement.len());
+	edited.push_str(&text[..span.start]);
+	edited.push_str(replacement);
+	edited.push_str(&text[span.end..]);
+	edited
+}
+
+/// How much each level of the document is indented by, judging by its second line
+fn indent_unit(text: &str) -> &str {
+	let line = text.lines().nth(1).unwrap_or_default();
+	match &line[..line.len() - line.trim_start().len()] {
+		"" => "  ",
+		indent => indent,
+	}
+}
+
+struct Scanner<'a> {
+	text: &'a [u8],
+	position: usize,
+}
+
+impl Scanner<'_> {
+	fn peek(&self) -> Option<u8> {
+		self.text.get(self.position).copied()
+	}
+
+	fn skip_whitespace(&mut self) {
+		while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+			self.position += 1;
+		}
+	}
+
+	fn expect(&mut self, byte: u8) -> Option<()> {
+		self.skip_whitespace();
+		(self.peek()? == byte).then(|| self.position += 1)
+	}
+
+	/// Skips over a value, and returns where it was
+	fn value(&mut self) -> Option<Range<usize>> {
+		self.skip_whitespace();
+		let start = self.position;
+		match self.peek()? {
+			b'"' => {
+				self.string()?;
+			}
+			b'{' => {
+				self.position += 1;
+				self.skip_whitespace();
+				if self.peek()? != b'}' {
+					loop {
+						self.skip_whitespace();
+						self.string()?;
+						self.expect(b':')?;
+						self.value()?;
+						self.skip_whitespace();
+						match self.peek()? {
+							b',' => self.position += 1,
+							b'}' => break,
+							_ => return None,
+						}
+					}
+				}
+				self.position += 1;
+			}
+			b'[' => {
+				self.position += 1;
+				self.skip_whitespace();
+				if self.peek()? != b']' {
+					loop {
+						self.value()?;
+						self.skip_whitespace();
+						match self.peek()? {
+							b',' => self.position += 1,
+							b']' => break,
+							_ => return None,
+						}
+					}
+				}
+				self.position += 1;
+			}
+			_ => {
+				while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || b"+-.".contains(&c)) {
+					self.position += 1;
+				}
+				if self.position == start {
+					return None;
+				}
+			}
+		}
+		Some(start..self.position)
+	}
+
+	/// Skips over a string, and returns its decoded contents
+	fn string(&mut self) -> Option<String> {
+		let start = self.position;
+		if self.peek()? != b'"' {
+			return None;
+		}
+		self.position += 1;
+		loop {
+			match self.peek()? {
+				b'\\' => self.position += 2,
+				b'"' => break,
+				_ => self.position += 1,
+			}
+		}
+		self.position += 1;
+		serde_json::from_slice(&self.text[start..self.position]).ok()
+	}
+
+	/// Finds the value of `key` in the object that starts at the current position
+	fn member(&mut self, key: &str) -> Option<Range<usize>> {
+		let text = self.text;
+		self
+			.members()?
+			.into_iter()
+			.find(|(name, _)| {
+				serde_json::from_slice::<String>(&text[name.clone()])
+					.ok()
+					.as_deref()
+					== Some(key)
+			})
+			.map(|(_, value)| value)
+	}
+
+	/// The spans of the keys and values of the object that starts at the current
+	/// position
+	fn members(&mut self) -> Option<Vec<(Range<usize>, Range<usize>)>> {
+		self.expect(b'{')?;
+		self.skip_whitespace();
+		let mut members = vec![];
+		if self.peek()? == b'}' {
+			return Some(members);
+		}
+
+		loop {
+			self.skip_whitespace();
+			let start = self.position;
+			self.string()?;
+			let name = start..self.position;
+			self.expect(b':')?;
+			members.push((name, self.value()?));
+			self.skip_whitespace();
+			match self.peek()? {
+				b',' => self.position += 1,
+				b'}' => return Some(members),
+				_ => return None,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PACKAGE: &str = r#"{
+    "name": "example",
+    "scripts": { "test": "echo \"}\"" },
+    "files": ["a", { "b": [1, 2.5e3, true, null] }],
+    "dependencies": {
+        "left":   "~1.0.0",
+        "shared": "^1.0.0"
+    }
+}
+"#;
+
+	#[test]
+	fn finds_values() {
+		let span = find_value(PACKAGE, &["dependencies", "left"]).unwrap();
+		assert_eq!(&PACKAGE[span], "\"~1.0.0\"");
+		let span = find_value(PACKAGE, &["scripts"]).unwrap();
+		assert_eq!(&PACKAGE[span], r#"{ "test": "echo \"}\"" }"#);
+		assert_eq!(find_value(PACKAGE, &["dependencies", "right"]), None);
+		assert_eq!(find_value(PACKAGE, &["name", "nope"]), None);
+		assert_eq!(find_value("{ oops", &["name"]), None);
+	}
+
+	#[test]
+	fn edits_in_place() {
+		assert_eq!(
+			set_string(PACKAGE, &["dependencies", "shared"], "^1.1.0").unwrap(),
+			PACKAGE.replace("\"^1.0.0\"", "\"^1.1.0\"")
+		);
+		assert_eq!(
+			set_string(PACKAGE, &["devDependencies", "left"], "1.0.0"),
+			None
+		);
+	}
+
+	#[test]
+	fn adds_members() {
+		assert_eq!(
+			set_json(PACKAGE, &["dependencies", "right"], "\"^2.0.0\"").unwrap(),
+			PACKAGE.replace(
+				"\"^1.0.0\"\n",
+				"\"^1.0.0\",\n        \"right\": \"^2.0.0\"\n"
+			)
+		);
+		assert_eq!(
+			set_json(PACKAGE, &["scripts", "build"], "\"tsc\"").unwrap(),
+			PACKAGE.replace("\\\"\" }", "\\\"\", \"build\": \"tsc\" }")
+		);
+
+		let added = set_json(PACKAGE, &["overrides"], "{}").unwrap();
+		let added = set_json(&added, &["overrides", "bar@1.0.0"], "\"1.0.1\"").unwrap();
+		assert!(
+			added.ends_with("    },\n    \"overrides\": {\n        \"bar@1.0.0\": \"1.0.1\"\n    }\n}\n")
+		);
+		assert_eq!(set_json(PACKAGE, &["nope", "bar"], "1"), None);
+	}
+}
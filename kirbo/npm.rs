@@ -1,11 +1,7 @@
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct Workspace {
-	pub workspaces: HashMap<PathBuf, PackageJson>,
-}
+use crate::config::CONFIG;
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -19,11 +15,87 @@ pub struct RegistryDocVersion {
 	pub version: String,
 	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
 	pub dependencies: HashMap<String, String>,
+	#[serde(default, rename = "optionalDependencies")]
+	pub optional_dependencies: HashMap<String, String>,
+	#[serde(default, rename = "peerDependencies")]
+	pub peer_dependencies: HashMap<String, String>,
+	#[serde(default, rename = "peerDependenciesMeta")]
+	pub peer_dependencies_meta: HashMap<String, PeerDependencyMeta>,
+	#[serde(default)]
+	pub os: Vec<String>,
+	#[serde(default)]
+	pub cpu: Vec<String>,
+	#[serde(default)]
+	pub libc: Vec<String>,
 	pub dist: PackageDist,
 }
 
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PeerDependencyMeta {
+	#[serde(default)]
+	pub optional: bool,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct PackageDist {
 	pub integrity: Option<String>,
 	pub tarball: String,
 }
+
+/// The URL of a package's document on the configured registry. Scoped packages
+/// need their `/` escaped.
+pub fn registry_doc_url(package: &str) -> String {
+	format!("{}{}", CONFIG.registry, package.replace('/', "%2f"))
+}
+
+/// Whether a dependency could be the name of a package in the registry, which is
+/// either `name` or `@scope/name`, using only characters that don't need escaping in
+/// a url. Names end up as paths in `node_modules/` and the store, so anything else
+/// (like `../../.ssh`) could point outside of them.
+pub fn is_valid_name(name: &str) -> bool {
+	let parts = match name.strip_prefix('@') {
+		Some(scoped) => match scoped.split_once('/') {
+			Some((scope, name)) => vec![scope, name],
+			None => return false,
+		},
+		None => vec![name],
+	};
+
+	name.len() <= 214
+		&& parts.iter().all(|part| {
+			!part.is_empty()
+				&& !part.starts_with('.')
+				&& part
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || "-._~!*'()".contains(c))
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names() {
+		for name in ["left-pad", "@types/node", "JSONStream", "lodash.merge", "a"] {
+			assert!(is_valid_name(name), "{}", name);
+		}
+		for name in [
+			"",
+			"..",
+			".bin",
+			"../../../.ssh",
+			"/etc",
+			"a/b",
+			"@scope",
+			"@scope/",
+			"@scope/../x",
+			"@scope/a/b",
+			"a\\b",
+			"C:",
+			"with space",
+		] {
+			assert!(!is_valid_name(name), "{}", name);
+		}
+	}
+}
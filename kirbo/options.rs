@@ -1,31 +1,140 @@
-use std::process::exit;
+use clap::ArgAction;
+use clap::Args;
+use clap::Parser;
+use clap::Subcommand;
+use once_cell::sync::OnceCell;
+use std::path::PathBuf;
 
-use colored::Colorize;
+use crate::commands::audit;
+use crate::commands::completions;
+use crate::commands::config;
+use crate::commands::create;
+use crate::commands::dedupe;
+use crate::commands::dlx;
+use crate::commands::exec;
+use crate::commands::init;
+use crate::commands::insight;
+use crate::commands::install;
+use crate::commands::outdated;
+use crate::commands::pack;
+use crate::commands::patch;
+use crate::commands::patch_commit;
+use crate::commands::publish;
+use crate::commands::run;
+use crate::commands::upgrade;
+use crate::commands::version;
+use crate::commands::why;
+use crate::commands::workspaces;
 
-#[derive(Clone, Debug, Default)]
-struct OptionsBuilder {
+static GLOBAL: OnceCell<GlobalOptions> = OnceCell::new();
+
+#[derive(Clone, Debug, Parser)]
+#[command(
+	name = "kirbo",
+	version,
+	about = "A secure and fast npm/yarn alternative",
+	disable_version_flag = true,
+	allow_external_subcommands = true,
+	after_help = include_str!("./help.txt")
+)]
+pub struct Options {
+	#[command(flatten)]
+	pub global: GlobalOptions,
+	/// Show version information
+	#[arg(short = 'v', short_alias = 'V', long, action = ArgAction::Version)]
+	version: Option<bool>,
+	#[command(subcommand)]
 	command: Option<Command>,
-	remaining_args: Vec<String>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Options {
-	pub command: Command,
-	pub remaining_args: Vec<String>,
+/// Flags which every command accepts, before or after its name
+#[derive(Clone, Debug, Default, Args)]
+pub struct GlobalOptions {
+	/// Run as if kirbo was started in this directory
+	#[arg(
+		long,
+		global = true,
+		value_name = "dir",
+		help_heading = "Global options"
+	)]
+	pub cwd: Option<PathBuf>,
+	/// Only print errors, and whatever scripts print
+	#[arg(
+		long,
+		global = true,
+		conflicts_with = "verbose",
+		help_heading = "Global options"
+	)]
+	pub silent: bool,
+	/// Print more about what's going on
+	#[arg(long, global = true, help_heading = "Global options")]
+	pub verbose: bool,
+	/// Print results as JSON, for commands which support it
+	#[arg(long, global = true, help_heading = "Global options")]
+	pub json: bool,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Subcommand)]
 pub enum Command {
-	Install,
-	Run,
-	Exec,
+	/// Install every dependency, after adding any new ones
+	#[command(visible_alias = "add", alias = "i")]
+	Install(install::options::Options),
+	/// Run a script from package.json
+	#[command(alias = "run-script")]
+	Run(run::options::Options),
+	/// Run a command with node_modules/.bin on the PATH
+	#[command(visible_alias = "x")]
+	Exec(exec::options::Options),
+	/// Do something in each member of the workspace
+	#[command(alias = "workspace")]
+	Workspaces(workspaces::options::Options),
+	/// Explain why a package is installed
+	Why(why::options::Options),
+	/// Summarize what's installed, and how much space it takes up
+	Insight(insight::options::Options),
+	/// List dependencies with newer versions available
+	Outdated(outdated::options::Options),
+	/// Upgrade dependencies, and the ranges they're listed with
+	#[command(visible_alias = "up")]
+	Upgrade(upgrade::options::Options),
+	/// Remove duplicate versions of packages from Kirbo.lock
+	#[command(visible_alias = "ddp")]
+	Dedupe(dedupe::options::Options),
+	/// Check installed packages against known security advisories
+	Audit(audit::options::Options),
+	/// Pack the package into a tarball, exactly as it would be published
+	Pack(pack::options::Options),
+	/// Publish the package to the registry
+	Publish(publish::options::Options),
+	/// Bump the version of the package
+	Version(version::options::Options),
+	/// Create a package.json
+	Init(init::options::Options),
+	/// Start a new project from a `create-` package
+	Create(create::options::Options),
+	/// Run an executable from a package, without adding it to the project
+	Dlx(dlx::options::Options),
+	/// Extract a dependency so that it can be edited and patched
+	Patch(patch::options::Options),
+	/// Save the changes made to a package extracted by `kirbo patch`
+	PatchCommit(patch_commit::options::Options),
+	/// Read and change settings
+	Config(config::options::Options),
+	/// Print a completion script for a shell
+	Completions(completions::options::Options),
+	/// `kirbo <script>` is shorthand for `kirbo run <script>`
+	#[command(external_subcommand)]
+	Script(Vec<String>),
 }
 
-impl From<OptionsBuilder> for Options {
-	fn from(builder: OptionsBuilder) -> Self {
-		Options {
-			command: builder.command.unwrap_or(Command::Install),
-			remaining_args: builder.remaining_args,
+impl Options {
+	/// The command to run. `kirbo` on its own installs, and `kirbo <script>` runs a
+	/// script.
+	pub fn into_command(self) -> Command {
+		match self.command {
+			Some(Command::Script(args)) => Command::Run(run::options::Options::from(args)),
+			Some(command) => command,
+			None => Command::Install(Default::default()),
 		}
 	}
 }
@@ -34,7 +143,7 @@ impl<S, const N: usize> TryFrom<&[S; N]> for Options
 where
 	S: AsRef<str>,
 {
-	type Error = anyhow::Error;
+	type Error = clap::Error;
 
 	fn try_from(args: &[S; N]) -> Result<Self, Self::Error> {
 		Options::try_from(&args[..])
@@ -45,61 +154,83 @@ impl<S> TryFrom<&[S]> for Options
 where
 	S: AsRef<str>,
 {
-	type Error = anyhow::Error;
+	type Error = clap::Error;
 
 	fn try_from(args: &[S]) -> Result<Self, Self::Error> {
-		if args.len() == 1 {
-			match args[0].as_ref() {
-				"-h" | "-help" | "--help" | "-?" | "help" => {
-					print!("{}", include_str!("./help.txt"));
-					exit(0);
-				}
-				"-v" | "-V" | "-version" | "--version" | "version" => {
-					println!(
-						"{} {}",
-						env!("CARGO_PKG_NAME").bright_magenta().bold(),
-						env!("CARGO_PKG_VERSION").bold()
-					);
-					exit(0);
-				}
-				_ => (),
-			}
-		}
+		let mut args = args
+			.iter()
+			.map(|arg| arg.as_ref().to_string())
+			.collect::<Vec<_>>();
 
-		let mut options = OptionsBuilder::default();
-		let mut args = args.into_iter();
-
-		if let Some(arg) = args.next() {
-			let arg = arg.as_ref();
-			match arg {
-				"add" | "install" | "i" => {
-					options.command = Some(Command::Install);
-				}
-				"run" | "run-script" => {
-					options.command = Some(Command::Run);
-				}
-				"exec" | "x" | "--" => {
-					options.command = Some(Command::Exec);
-				}
-				_ => {
-					options.command = if (arg.len() >= 2 && arg.starts_with('-'))
-						|| (arg.len() >= 3 && arg.starts_with("--"))
-					{
-						// If it's a `--flag`, then we probably want `kirbo install`
-						Some(Command::Install)
-					} else {
-						// If it's a `word`, then we probably want `kirbo run $word`
-						Some(Command::Run)
-					};
-					options.remaining_args.push(arg.to_string());
-				}
-			}
+		// `kirbo -- <command>` is shorthand for `kirbo exec <command>`, as long as the
+		// `--` comes before anything that isn't a global flag
+		let mut i = 0;
+		while i < args.len() && args[i].starts_with('-') && args[i] != "--" {
+			i += if args[i] == "--cwd" { 2 } else { 1 };
+		}
+		if args.get(i).is_some_and(|arg| arg == "--") {
+			args[i] = "exec".to_string();
 		}
 
-		options
-			.remaining_args
-			.extend(args.map(|s| s.as_ref().to_string()));
+		Options::try_parse_from(["kirbo".to_string()].into_iter().chain(args))
+	}
+}
+
+/// Remembers the global flags for the rest of the process. Only the first call has
+/// any effect.
+pub fn set_global(options: GlobalOptions) {
+	let _ = GLOBAL.set(options);
+}
+
+/// The global flags that kirbo was started with
+pub fn global() -> &'static GlobalOptions {
+	GLOBAL.get_or_init(GlobalOptions::default)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shorthands() {
+		let command = |args: &[&str]| Options::try_from(args).unwrap().into_command();
+
+		assert!(matches!(command(&[]), Command::Install(_)));
+		assert!(
+			matches!(command(&["add", "-Dtypescript"]), Command::Install(options) if options.dev == ["typescript"])
+		);
+		assert!(
+			matches!(command(&["build", "--watch"]), Command::Run(options) if options.script.as_deref() == Some("build") && options.args == ["--watch"])
+		);
+		assert!(
+			matches!(command(&["--", "prettier", "--check", "."]), Command::Exec(options) if options.command == "prettier" && options.args == ["--check", "."])
+		);
+		assert!(
+			matches!(command(&["x", "tsc", "-p", "."]), Command::Exec(options) if options.args == ["-p", "."])
+		);
+	}
+
+	#[test]
+	fn globals() {
+		let options = Options::try_from(&["audit", "--json", "--cwd", "packages/app"]).unwrap();
+		assert!(options.global.json);
+		assert_eq!(options.global.cwd, Some(PathBuf::from("packages/app")));
+		assert!(Options::try_from(&["--silent", "--verbose"]).is_err());
+	}
+
+	#[test]
+	fn errors() {
+		use clap::error::ErrorKind;
 
-		Ok(options.into())
+		let kind = |args: &[&str]| Options::try_from(args).unwrap_err().kind();
+		assert_eq!(kind(&["--help"]), ErrorKind::DisplayHelp);
+		assert_eq!(kind(&["audit", "--help"]), ErrorKind::DisplayHelp);
+		assert_eq!(kind(&["-v"]), ErrorKind::DisplayVersion);
+		assert_eq!(kind(&["install", "--nope"]), ErrorKind::UnknownArgument);
+		assert_eq!(
+			kind(&["audit", "--level", "nope"]),
+			ErrorKind::ValueValidation
+		);
+		assert_eq!(kind(&["why"]), ErrorKind::MissingRequiredArgument);
 	}
 }
@@ -0,0 +1,374 @@
+use anyhow::anyhow;
+use kirbo_workspace::Override;
+use kirbo_workspace::Package;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::lock::split_descriptor;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+/// A package named by an override, like `bar`, or `bar@^1.0.0` to only match some
+/// of its versions
+#[derive(Clone, Debug)]
+struct Selector {
+	name: String,
+	range: Option<SemverRange>,
+}
+
+impl FromStr for Selector {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, range) = split_descriptor(s);
+		let range = match range {
+			"" => None,
+			range => Some(
+				SemverRange::from_str(range).map_err(|_| anyhow!("invalid range in override \"{}\"", s))?,
+			),
+		};
+
+		Ok(Selector {
+			name: name.to_string(),
+			range,
+		})
+	}
+}
+
+impl Selector {
+	fn matches(&self, name: &str, version: Option<&str>) -> bool {
+		if self.name != name {
+			return false;
+		}
+
+		match (
+			&self.range,
+			version.and_then(|it| Version::from_str(it).ok()),
+		) {
+			(None, _) => true,
+			(Some(range), Some(version)) => version.satisfies(range),
+			(Some(_), None) => false,
+		}
+	}
+}
+
+/// One package along the path to an overridden dependency
+#[derive(Clone, Debug)]
+struct Step {
+	selector: Selector,
+	/// Whether this has to be a direct dependency of the previous step (or of the root,
+	/// if it's the first)
+	direct: bool,
+}
+
+/// A single forced range for a dependency
+#[derive(Clone, Debug)]
+pub struct Rule {
+	/// Packages which the dependency has to be beneath, outermost first
+	parents: Vec<Step>,
+	target: Step,
+	/// The range to resolve instead of the requested one
+	pub replacement: String,
+	/// How the rule was written in the package.json, like `foo > bar` or `**/bar`
+	pub source: String,
+}
+
+/// Every override and resolution from the root of a workspace
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+	rules: Vec<Rule>,
+}
+
+impl Overrides {
+	/// Reads both npm `overrides` and yarn `resolutions` from a package.json
+	pub fn from_package(package: &Package) -> anyhow::Result<Self> {
+		let mut overrides = Overrides::default();
+		overrides.add_npm(package, &[], &package.overrides)?;
+		for (path, range) in &package.resolutions {
+			overrides.add_yarn(path, range)?;
+		}
+		Ok(overrides)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty()
+	}
+
+	/// Whether any rule for `name` only applies to some of its versions, in which case
+	/// `find` needs to know which version would be used without an override
+	pub fn needs_version(&self, name: &str) -> bool {
+		self
+			.rules
+			.iter()
+			.any(|rule| rule.target.selector.name == name && rule.target.selector.range.is_some())
+	}
+
+	/// Finds the rule that applies to `name` (which would otherwise resolve to
+	/// `version`) when it's depended on through `ancestors`, given as `(name, version)`
+	/// pairs starting from a dependency of the root. When more than one rule applies,
+	/// the one with the longest path wins.
+	pub fn find(
+		&self,
+		ancestors: &[(String, String)],
+		name: &str,
+		version: Option<&str>,
+	) -> Option<&Rule> {
+		let path = ancestors
+			.iter()
+			.map(|(name, version)| (name.as_str(), Some(version.as_str())))
+			.chain([(name, version)])
+			.collect::<Vec<_>>();
+
+		let mut found: Option<&Rule> = None;
+		for rule in &self.rules {
+			let steps = rule
+				.parents
+				.iter()
+				.chain([&rule.target])
+				.collect::<Vec<_>>();
+			if fits(&steps, &path, 0)
+				&& found.is_none_or(|found| rule.parents.len() > found.parents.len())
+			{
+				found = Some(rule);
+			}
+		}
+		found
+	}
+
+	/// npm nests overrides beneath the package they apply to, and any of them can be
+	/// anywhere beneath it
+	fn add_npm(
+		&mut self,
+		package: &Package,
+		parents: &[Step],
+		overrides: &BTreeMap<String, Override>,
+	) -> anyhow::Result<()> {
+		for (key, value) in overrides {
+			if key == "." {
+				continue;
+			}
+
+			let step = Step {
+				selector: key.parse()?,
+				direct: false,
+			};
+			let source = parents
+				.iter()
+				.map(|step| step.selector.name.as_str())
+				.chain([key.as_str()])
+				.collect::<Vec<_>>()
+				.join(" > ");
+
+			let replacement = match value {
+				Override::Version(range) => Some(range),
+				Override::Nested(nested) => match nested.get(".") {
+					Some(Override::Version(range)) => Some(range),
+					_ => None,
+				},
+			};
+			if let Some(replacement) = replacement {
+				self.rules.push(Rule {
+					parents: parents.to_vec(),
+					target: step.clone(),
+					replacement: dereference(package, replacement, &source)?,
+					source,
+				});
+			}
+
+			if let Override::Nested(nested) = value {
+				let parents = [parents, &[step]].concat();
+				self.add_npm(package, &parents, nested)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// yarn uses paths like `foo/bar`, where each package has to directly depend on the
+	/// next unless they're separated by `**`
+	fn add_yarn(&mut self, path: &str, range: &str) -> anyhow::Result<()> {
+		let mut steps = vec![];
+		let mut segments = path.split('/');
+		let mut direct = true;
+		while let Some(segment) = segments.next() {
+			match segment {
+				"**" => direct = false,
+				"" => return Err(anyhow!("invalid resolution \"{}\"", path)),
+				_ => {
+					let name = match segment.starts_with('@') {
+						true => format!(
+							"{}/{}",
+							segment,
+							segments
+								.next()
+								.ok_or_else(|| anyhow!("invalid resolution \"{}\"", path))?
+						),
+						false => segment.to_string(),
+					};
+					steps.push(Step {
+						selector: name.parse()?,
+						direct,
+					});
+					direct = true;
+				}
+			}
+		}
+
+		let mut target = steps
+			.pop()
+			.ok_or_else(|| anyhow!("invalid resolution \"{}\"", path))?;
+		// A lone package name applies everywhere, just like `**/name`
+		if steps.is_empty() {
+			target.direct = false;
+		}
+
+		self.rules.push(Rule {
+			parents: steps,
+			target,
+			replacement: range.to_string(),
+			source: path.to_string(),
+		});
+		Ok(())
+	}
+}
+
+/// npm lets an override reference the range of one of the root's dependencies as
+/// `$name`, so that the two can't drift apart
+fn dereference(package: &Package, replacement: &str, source: &str) -> anyhow::Result<String> {
+	let Some(name) = replacement.strip_prefix('$') else {
+		return Ok(replacement.to_string());
+	};
+
+	package
+		.dependencies
+		.get(name)
+		.or_else(|| package.dev_dependencies.get(name))
+		.cloned()
+		.ok_or_else(|| {
+			anyhow!(
+				"override for {} references ${}, but {} isn't a dependency of the workspace",
+				source,
+				name,
+				name
+			)
+		})
+}
+
+/// Checks if `steps` can be matched against `path`, in order, with the last step
+/// matching the end of the path
+fn fits(steps: &[&Step], path: &[(&str, Option<&str>)], from: usize) -> bool {
+	let Some((step, rest)) = steps.split_first() else {
+		return from == path.len();
+	};
+
+	let mut candidates = match step.direct {
+		true => from..(from + 1).min(path.len()),
+		false => from..path.len(),
+	};
+	candidates.any(|i| {
+		let (name, version) = path[i];
+		step.selector.matches(name, version) && fits(rest, path, i + 1)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn package(json: &str) -> Package {
+		serde_json::from_str(json).unwrap()
+	}
+
+	fn ancestors(path: &[(&str, &str)]) -> Vec<(String, String)> {
+		path
+			.iter()
+			.map(|(name, version)| (name.to_string(), version.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn npm_overrides() {
+		let overrides = Overrides::from_package(&package(
+			r#"{
+				"dependencies": { "qux": "^3.0.0" },
+				"overrides": {
+					"bar": "1.0.0",
+					"foo": { ".": "2.0.0", "bar": "1.2.3" },
+					"baz@^1.0.0": "1.5.0",
+					"qux": "$qux"
+				}
+			}"#,
+		))
+		.unwrap();
+
+		let replacement = |path: &[(&str, &str)], name: &str, version: Option<&str>| {
+			overrides
+				.find(&ancestors(path), name, version)
+				.map(|rule| rule.replacement.as_str())
+		};
+
+		assert_eq!(replacement(&[], "bar", None), Some("1.0.0"));
+		assert_eq!(
+			replacement(&[("other", "1.0.0")], "bar", None),
+			Some("1.0.0")
+		);
+		assert_eq!(replacement(&[("foo", "1.0.0")], "bar", None), Some("1.2.3"));
+		assert_eq!(
+			replacement(&[("foo", "1.0.0"), ("other", "1.0.0")], "bar", None),
+			Some("1.2.3")
+		);
+		assert_eq!(replacement(&[], "foo", None), Some("2.0.0"));
+		assert_eq!(replacement(&[], "baz", Some("1.1.0")), Some("1.5.0"));
+		assert_eq!(replacement(&[], "baz", Some("2.0.0")), None);
+		assert_eq!(replacement(&[], "qux", None), Some("^3.0.0"));
+		assert_eq!(
+			overrides
+				.find(&ancestors(&[("foo", "1.0.0")]), "bar", None)
+				.unwrap()
+				.source,
+			"foo > bar"
+		);
+
+		assert!(Overrides::from_package(&package(r#"{ "overrides": { "bar": "$nope" } }"#)).is_err());
+	}
+
+	#[test]
+	fn yarn_resolutions() {
+		let overrides = Overrides::from_package(&package(
+			r#"{
+				"resolutions": {
+					"bar": "1.0.0",
+					"foo/bar": "1.2.3",
+					"**/@scope/baz": "2.0.0",
+					"qux/**/baz": "3.0.0"
+				}
+			}"#,
+		))
+		.unwrap();
+
+		let replacement = |path: &[(&str, &str)], name: &str| {
+			overrides
+				.find(&ancestors(path), name, None)
+				.map(|rule| rule.replacement.as_str())
+		};
+
+		assert_eq!(replacement(&[("other", "1.0.0")], "bar"), Some("1.0.0"));
+		assert_eq!(replacement(&[("foo", "1.0.0")], "bar"), Some("1.2.3"));
+		// `foo/bar` only applies when foo is a direct dependency, and bar is a direct
+		// dependency of foo
+		assert_eq!(
+			replacement(&[("other", "1.0.0"), ("foo", "1.0.0")], "bar"),
+			Some("1.0.0")
+		);
+		assert_eq!(
+			replacement(&[("foo", "1.0.0"), ("other", "1.0.0")], "bar"),
+			Some("1.0.0")
+		);
+		assert_eq!(replacement(&[("a", "1.0.0")], "@scope/baz"), Some("2.0.0"));
+		assert_eq!(
+			replacement(&[("qux", "1.0.0"), ("a", "1.0.0")], "baz"),
+			Some("3.0.0")
+		);
+		assert_eq!(replacement(&[("a", "1.0.0")], "baz"), None);
+	}
+}
@@ -0,0 +1,437 @@
+use anyhow::anyhow;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use kirbo_workspace::glob;
+use kirbo_workspace::Package;
+use sha1::Digest;
+use sha1::Sha1;
+use std::fs;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::store::sha512_base64;
+
+/// npm sets the modification time of every entry to 1985-10-26T08:15:00Z, so that
+/// packing the same files always produces the same tarball
+const MTIME: u64 = 499162500;
+
+/// Files which are never packed, wherever they are
+const ALWAYS_IGNORED: &[&str] = &[
+	".git",
+	".svn",
+	".hg",
+	"CVS",
+	"node_modules",
+	".npmrc",
+	".npmignore",
+	".gitignore",
+	".DS_Store",
+	"._*",
+	".*.swp",
+	"*.orig",
+	"npm-debug.log",
+];
+
+/// Lock files are only left out from the root of the package
+const ROOT_IGNORED: &[&str] = &[
+	"package-lock.json",
+	"npm-shrinkwrap.json",
+	"yarn.lock",
+	"pnpm-lock.yaml",
+	"Kirbo.lock",
+];
+
+/// A line of an `.npmignore` or `.gitignore` file
+#[derive(Clone, Debug)]
+struct Rule {
+	/// The directory containing the ignore file, relative to the package
+	base: PathBuf,
+	pattern: String,
+	negated: bool,
+	/// Only matches directories, because the pattern ended with a slash
+	dir_only: bool,
+	/// Matches from `base` rather than against file names, because the pattern
+	/// contained a slash
+	anchored: bool,
+}
+
+impl Rule {
+	fn parse(base: &Path, line: &str) -> Option<Rule> {
+		let line = line.trim_end();
+		if line.is_empty() || line.starts_with('#') {
+			return None;
+		}
+
+		let (negated, line) = match line.strip_prefix('!') {
+			Some(line) => (true, line),
+			None => (false, line),
+		};
+		let line = line.strip_prefix('\\').unwrap_or(line);
+		let (dir_only, line) = match line.strip_suffix('/') {
+			Some(line) => (true, line),
+			None => (false, line),
+		};
+		let anchored = line.contains('/');
+
+		Some(Rule {
+			base: base.to_path_buf(),
+			pattern: line.trim_start_matches('/').to_string(),
+			negated,
+			dir_only,
+			anchored,
+		})
+	}
+
+	fn matches(&self, path: &Path, is_dir: bool) -> bool {
+		let Ok(relative) = path.strip_prefix(&self.base) else {
+			return false;
+		};
+		if self.dir_only && !is_dir {
+			return false;
+		}
+
+		match self.anchored {
+			true => glob::matches_path(&self.pattern, relative),
+			false => path
+				.file_name()
+				.is_some_and(|name| glob::matches(&self.pattern, &name.to_string_lossy())),
+		}
+	}
+}
+
+/// Whether the last rule which matches `path` ignores it
+fn is_ignored(rules: &[Rule], path: &Path, is_dir: bool) -> bool {
+	rules
+		.iter()
+		.rev()
+		.find(|rule| rule.matches(path, is_dir))
+		.is_some_and(|rule| !rule.negated)
+}
+
+fn read_rules(dir: &Path, base: &Path) -> Vec<Rule> {
+	let text = fs::read_to_string(dir.join(".npmignore"))
+		.or_else(|_| fs::read_to_string(dir.join(".gitignore")))
+		.unwrap_or_default();
+	text
+		.lines()
+		.filter_map(|line| Rule::parse(base, line))
+		.collect()
+}
+
+/// Whether `path` is matched by a pattern from the `files` field, or is inside of a
+/// directory which is
+fn is_listed(files: &[String], path: &Path) -> bool {
+	files.iter().any(|pattern| {
+		let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+		path
+			.ancestors()
+			.filter(|it| !it.as_os_str().is_empty())
+			.any(|it| glob::matches_path(pattern, it))
+	})
+}
+
+fn walk(
+	root: &Path,
+	relative: &Path,
+	files: Option<&[String]>,
+	rules: &mut Vec<Rule>,
+	found: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+	let dir = root.join(relative);
+	// When there's a `files` field, it replaces the ignore file at the root, but nested
+	// ignore files still apply
+	let count = rules.len();
+	if files.is_none() || !relative.as_os_str().is_empty() {
+		rules.extend(read_rules(&dir, relative));
+	}
+
+	let mut entries = fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+	entries.sort_by_key(|entry| entry.file_name());
+
+	for entry in entries {
+		let name = entry.file_name().to_string_lossy().to_string();
+		let root_ignored = match relative.as_os_str().is_empty() {
+			true => ROOT_IGNORED,
+			false => &[],
+		};
+		let ignored = ALWAYS_IGNORED
+			.iter()
+			.chain(root_ignored)
+			.any(|pattern| glob::matches(pattern, &name));
+		let path = relative.join(&name);
+		// Symlinks could lead anywhere, including back up to here, so they're never
+		// followed or packed
+		let file_type = entry.file_type()?;
+		let is_dir = file_type.is_dir();
+		if ignored || file_type.is_symlink() || is_ignored(rules, &path, is_dir) {
+			continue;
+		}
+
+		if is_dir {
+			walk(root, &path, files, rules, found)?;
+		} else if files.is_none_or(|files| is_listed(files, &path)) {
+			found.push(path);
+		}
+	}
+
+	rules.truncate(count);
+	Ok(())
+}
+
+/// An entry point from package.json (`main` or one of `bin`), relative to the
+/// package. Anything that would point outside of the package is an error.
+fn entry_point(field: &str, path: &str) -> anyhow::Result<PathBuf> {
+	let components = Path::new(path)
+		.components()
+		.filter(|component| *component != Component::CurDir);
+	if !components
+		.clone()
+		.all(|component| matches!(component, Component::Normal(_)))
+	{
+		return Err(anyhow!(
+			"\"{}\" points outside of the package: {}",
+			field,
+			path
+		));
+	}
+	Ok(components.collect())
+}
+
+/// Whether `path` is a file, and not a symlink to one
+fn is_file(path: &Path) -> bool {
+	fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Lists the files which npm would pack from `dir`, relative to it and sorted
+pub fn file_list(dir: &Path, package: &Package) -> anyhow::Result<Vec<PathBuf>> {
+	let mut found = vec![];
+	walk(
+		dir,
+		Path::new(""),
+		package.files.as_deref(),
+		&mut vec![],
+		&mut found,
+	)?;
+
+	// Some files are always included, no matter what `files` or the ignore files say
+	for entry in fs::read_dir(dir)? {
+		let name = entry?.file_name().to_string_lossy().to_string();
+		let lower = name.to_lowercase();
+		let always = lower == "package.json"
+			|| ["readme", "license", "licence"]
+				.iter()
+				.any(|prefix| lower.starts_with(prefix));
+		if always && is_file(&dir.join(&name)) {
+			found.push(PathBuf::from(name));
+		}
+	}
+	let entry_points = package
+		.main
+		.iter()
+		.map(|path| ("main", path.as_str()))
+		.chain(
+			package
+				.bin
+				.iter()
+				.flat_map(|bin| bin.paths())
+				.map(|path| ("bin", path)),
+		);
+	for (field, path) in entry_points {
+		let path = entry_point(field, path)?;
+		if is_file(&dir.join(&path)) {
+			found.push(path);
+		}
+	}
+
+	// A tarball left over from packing earlier shouldn't end up inside the new one
+	if let (Some(name), Some(version)) = (&package.name, &package.version) {
+		let tarball = PathBuf::from(tarball_name(name, version));
+		found.retain(|path| *path != tarball);
+	}
+
+	found.sort();
+	found.dedup();
+	Ok(found)
+}
+
+/// The name npm gives the tarball of a package, like `scope-name-1.0.0.tgz`
+pub fn tarball_name(name: &str, version: &str) -> String {
+	format!(
+		"{}-{}.tgz",
+		name.trim_start_matches('@').replace('/', "-"),
+		version
+	)
+}
+
+/// A file in a packed tarball
+#[derive(Clone, Debug)]
+pub struct PackedFile {
+	/// The path of the file, relative to the package
+	pub path: String,
+	pub size: u64,
+	pub mode: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Packed {
+	pub name: String,
+	pub version: String,
+	pub files: Vec<PackedFile>,
+	pub tarball: Vec<u8>,
+	/// The hex encoded sha1 digest of the tarball, which older npm clients still use
+	pub shasum: String,
+	/// The sha512 integrity of the tarball, like `sha512-...`
+	pub integrity: String,
+}
+
+impl Packed {
+	pub fn filename(&self) -> String {
+		tarball_name(&self.name, &self.version)
+	}
+
+	pub fn unpacked_size(&self) -> u64 {
+		self.files.iter().map(|file| file.size).sum()
+	}
+}
+
+fn is_executable(path: &Path) -> bool {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::metadata(path).is_ok_and(|it| it.permissions().mode() & 0o111 != 0)
+	}
+
+	#[cfg(not(unix))]
+	{
+		let _ = path;
+		false
+	}
+}
+
+/// Packs the package in `dir` into a tarball, the same way npm would. `manifest` is
+/// the text to pack as its package.json, which lets publishing pack a rewritten copy.
+/// Entries get the same timestamp and normalized modes, so the same files always
+/// produce the same bytes.
+pub fn pack(dir: &Path, manifest: &str) -> anyhow::Result<Packed> {
+	let package = serde_json::from_str::<Package>(manifest)?;
+	let name = package
+		.name
+		.clone()
+		.ok_or_else(|| anyhow!("package.json in {} has no name", dir.display()))?;
+	let version = package
+		.version
+		.clone()
+		.ok_or_else(|| anyhow!("package.json in {} has no version", dir.display()))?;
+	let bins = package
+		.bin
+		.iter()
+		.flat_map(|bin| bin.paths())
+		.map(|path| entry_point("bin", path))
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	let mut files = vec![];
+	let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+	for path in file_list(dir, &package)? {
+		let contents = match path == Path::new("package.json") {
+			true => manifest.as_bytes().to_vec(),
+			false => fs::read(dir.join(&path))?,
+		};
+		let mode = match bins.contains(&path) || is_executable(&dir.join(&path)) {
+			true => 0o755,
+			false => 0o644,
+		};
+		let name = path.to_string_lossy().replace('\\', "/");
+
+		let mut header = tar::Header::new_gnu();
+		header.set_size(contents.len() as u64);
+		header.set_mode(mode);
+		header.set_mtime(MTIME);
+		header.set_uid(0);
+		header.set_gid(0);
+		header.set_cksum();
+		builder.append_data(&mut header, format!("package/{}", name), &*contents)?;
+
+		files.push(PackedFile {
+			path: name,
+			size: contents.len() as u64,
+			mode,
+		});
+	}
+	let tarball = builder.into_inner()?.finish()?;
+
+	Ok(Packed {
+		name,
+		version,
+		files,
+		shasum: Sha1::digest(&tarball)
+			.iter()
+			.map(|byte| format!("{:02x}", byte))
+			.collect(),
+		integrity: format!("sha512-{}", sha512_base64(&tarball)),
+		tarball,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rules(base: &str, text: &str) -> Vec<Rule> {
+		text
+			.lines()
+			.filter_map(|line| Rule::parse(Path::new(base), line))
+			.collect()
+	}
+
+	#[test]
+	fn ignores() {
+		let rules = rules(
+			"",
+			"# comment\n*.log\n!keep.log\n/build\ndocs/\nsrc/**/*.test.js\n",
+		);
+		let ignored = |path: &str, is_dir| is_ignored(&rules, Path::new(path), is_dir);
+
+		assert!(ignored("debug.log", false));
+		assert!(ignored("lib/debug.log", false));
+		assert!(!ignored("keep.log", false));
+		assert!(ignored("build", true));
+		assert!(!ignored("lib/build", true));
+		assert!(ignored("lib/docs", true));
+		assert!(!ignored("docs", false));
+		assert!(ignored("src/a/b.test.js", false));
+		assert!(!ignored("src/a/b.js", false));
+	}
+
+	#[test]
+	fn nested_ignores() {
+		let rules = rules("lib", "/*.md\n");
+		assert!(is_ignored(&rules, Path::new("lib/notes.md"), false));
+		assert!(!is_ignored(&rules, Path::new("lib/a/notes.md"), false));
+		assert!(!is_ignored(&rules, Path::new("notes.md"), false));
+	}
+
+	#[test]
+	fn lists_files() {
+		let files = ["lib".to_string(), "./dist/*.js".to_string()];
+		assert!(is_listed(&files, Path::new("lib/a/b.js")));
+		assert!(is_listed(&files, Path::new("dist/index.js")));
+		assert!(!is_listed(&files, Path::new("dist/index.d.ts")));
+		assert!(!is_listed(&files, Path::new("src/lib/a.js")));
+	}
+
+	#[test]
+	fn entry_points() {
+		assert_eq!(
+			entry_point("main", "./lib/index.js").unwrap(),
+			PathBuf::from("lib/index.js")
+		);
+		assert_eq!(
+			entry_point("bin", "cli.js").unwrap(),
+			PathBuf::from("cli.js")
+		);
+		assert!(entry_point("main", "../secret.js").is_err());
+		assert!(entry_point("main", "lib/../../secret.js").is_err());
+		assert!(entry_point("bin", "/etc/passwd").is_err());
+	}
+}
@@ -0,0 +1,341 @@
+use anyhow::anyhow;
+use diffy::DiffOptions;
+use diffy::Patch;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::linker;
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
+use crate::lock::KirboLockPackage;
+use crate::store::Store;
+
+const DEV_NULL: &str = "/dev/null";
+
+/// Where the patch for a package is kept, relative to the root of the workspace
+pub fn patch_path(name: &str, version: &str) -> String {
+	format!("patches/{}@{}.patch", name, version)
+}
+
+/// Where `kirbo patch` extracts a package (`name@version`) to be edited
+pub fn edit_dir(id: &str) -> PathBuf {
+	env::temp_dir().join(format!("kirbo-patch-{}", id.replace('/', "+")))
+}
+
+/// The patch registered for a package, and the key it was registered with. A patch
+/// for `name@version` wins over one for every version of `name`.
+pub fn find_patch<'a>(
+	patches: &'a BTreeMap<String, String>,
+	name: &str,
+	version: &str,
+) -> Option<(&'a str, &'a str)> {
+	patches
+		.get_key_value(&format!("{}@{}", name, version))
+		.or_else(|| patches.get_key_value(name))
+		.map(|(key, path)| (key.as_str(), path.as_str()))
+}
+
+/// The locked entry for a package. `version` can be empty when only one version of
+/// the package is installed.
+pub fn locked_package<'a>(
+	lock: &'a KirboLock,
+	name: &str,
+	version: &str,
+) -> anyhow::Result<&'a KirboLockPackage> {
+	let mut found = lock
+		.packages
+		.iter()
+		.filter(|(descriptor, package)| {
+			split_descriptor(descriptor).0 == name && (version.is_empty() || package.version == version)
+		})
+		.map(|(_, package)| package)
+		.collect::<Vec<_>>();
+	found.sort_by(|a, b| a.version.cmp(&b.version));
+	found.dedup_by(|a, b| a.version == b.version);
+
+	match &*found {
+		[] if version.is_empty() => Err(anyhow!("{} isn't installed", name)),
+		[] => Err(anyhow!("{}@{} isn't installed", name, version)),
+		[package] => Ok(package),
+		_ => Err(anyhow!(
+			"more than one version of {} is installed ({}), so pick one like `{}@{}`",
+			name,
+			found
+				.iter()
+				.map(|package| package.version.as_str())
+				.collect::<Vec<_>>()
+				.join(", "),
+			name,
+			found[0].version
+		)),
+	}
+}
+
+/// Extracts the tarball of a locked package into `dir`, exactly as it was published
+pub async fn extract_locked(
+	name: &str,
+	package: &KirboLockPackage,
+	dir: &Path,
+) -> anyhow::Result<()> {
+	let store = Store::default();
+	let id = match package.resolved.strip_prefix("git+") {
+		Some(url) => url.rsplit('#').next().unwrap_or_default(),
+		None => &package.sha512,
+	};
+
+	// Git dependencies and tarballs from outside of the registry were packed into the
+	// store when they were resolved
+	let source = store.source_path(id);
+	let tarball = if source.is_file() {
+		source
+	} else if package.resolved.starts_with("http") {
+		store
+			.fetch(
+				name,
+				&package.version,
+				&package.resolved,
+				Some(&package.sha512),
+			)
+			.await?
+	} else {
+		return Err(anyhow!(
+			"{}@{} comes from {}, which can't be patched",
+			name,
+			package.version,
+			package.resolved
+		));
+	};
+
+	linker::extract_package(&tarball, dir)?;
+	Ok(())
+}
+
+/// Every file in `dir`, relative to it, leaving out anything that was installed
+fn files(dir: &Path, relative: &Path, found: &mut BTreeSet<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir.join(relative))? {
+		let entry = entry?;
+		let name = entry.file_name();
+		if name == "node_modules" || name == ".git" {
+			continue;
+		}
+		let path = relative.join(&name);
+		match entry.file_type()?.is_dir() {
+			true => files(dir, &path, found)?,
+			false => {
+				found.insert(path);
+			}
+		}
+	}
+	Ok(())
+}
+
+fn read_file(dir: &Path, path: &Path) -> io::Result<Option<Vec<u8>>> {
+	match fs::read(dir.join(path)) {
+		Ok(bytes) => Ok(Some(bytes)),
+		Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(err) => Err(err),
+	}
+}
+
+fn read_text(dir: &Path, path: &Path) -> anyhow::Result<Option<String>> {
+	read_file(dir, path)?
+		.map(String::from_utf8)
+		.transpose()
+		.map_err(|_| {
+			anyhow!(
+				"{} isn't a text file, so it can't be patched",
+				path.display()
+			)
+		})
+}
+
+/// The patch for a single file, given its contents before and after. Only text can
+/// be patched, but binary files are fine as long as they weren't changed.
+fn changed_file_patch(
+	name: &str,
+	before: Option<Vec<u8>>,
+	after: Option<Vec<u8>>,
+) -> anyhow::Result<Option<String>> {
+	if before == after {
+		return Ok(None);
+	}
+
+	let text = |bytes: Option<Vec<u8>>| {
+		bytes.map(String::from_utf8).transpose().map_err(|_| {
+			anyhow!(
+				"binary file {} changed, but only text files can be patched",
+				name
+			)
+		})
+	};
+	let (before, after) = (text(before)?, text(after)?);
+	Ok(file_patch(name, before.as_deref(), after.as_deref()))
+}
+
+/// The patch for a single file, in the format of `git diff`. `None` on either side
+/// means that the file was added or deleted.
+fn file_patch(path: &str, original: Option<&str>, modified: Option<&str>) -> Option<String> {
+	if original == modified {
+		return None;
+	}
+
+	let a = format!("a/{}", path);
+	let b = format!("b/{}", path);
+	let patch = DiffOptions::new()
+		.set_original_filename(original.map_or(DEV_NULL.to_string(), |_| a.clone()))
+		.set_modified_filename(modified.map_or(DEV_NULL.to_string(), |_| b.clone()))
+		.create_patch(original.unwrap_or_default(), modified.unwrap_or_default())
+		.to_string();
+
+	let mode = match (original, modified) {
+		(None, _) => "new file mode 100644\n",
+		(_, None) => "deleted file mode 100644\n",
+		_ => "",
+	};
+	Some(format!("diff --git {} {}\n{}{}", a, b, mode, patch))
+}
+
+/// Describes every change made to the files in `original` to get the files in
+/// `modified`, as a patch which `git apply` would also understand
+pub fn diff(original: &Path, modified: &Path) -> anyhow::Result<String> {
+	let mut paths = BTreeSet::new();
+	files(original, Path::new(""), &mut paths)?;
+	files(modified, Path::new(""), &mut paths)?;
+
+	let mut patch = String::new();
+	for path in paths {
+		let name = path.to_string_lossy().replace('\\', "/");
+		let before = read_file(original, &path)?;
+		let after = read_file(modified, &path)?;
+		if let Some(file_patch) = changed_file_patch(&name, before, after)? {
+			patch.push_str(&file_patch);
+		}
+	}
+	Ok(patch)
+}
+
+/// The path a file is patched at, without the `a/` or `b/` prefix, as long as it
+/// stays inside of the package
+fn patched_path(filename: &str) -> Option<PathBuf> {
+	let path = filename
+		.strip_prefix("a/")
+		.or_else(|| filename.strip_prefix("b/"))
+		.unwrap_or(filename);
+	let path = PathBuf::from(path);
+	path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_)))
+		.then_some(path)
+}
+
+/// Splits a patch into the patches for each of its files
+fn sections(text: &str) -> Vec<&str> {
+	let mut starts = text
+		.match_indices("diff --git ")
+		.filter(|(i, _)| *i == 0 || text.as_bytes()[i - 1] == b'\n')
+		.map(|(i, _)| i)
+		.collect::<Vec<_>>();
+	if starts.is_empty() {
+		return vec![text];
+	}
+	starts.push(text.len());
+	starts.windows(2).map(|it| &text[it[0]..it[1]]).collect()
+}
+
+/// Applies a patch to the files in `dir`. Every file is checked before any of them
+/// are changed, so a patch that doesn't apply leaves `dir` alone.
+pub fn apply(text: &str, dir: &Path) -> anyhow::Result<()> {
+	let mut patched = vec![];
+	for section in sections(text) {
+		let patch = Patch::from_str(section).map_err(|err| anyhow!("invalid patch: {}", err))?;
+		let (original, modified) = (patch.original(), patch.modified());
+		let filename = modified
+			.filter(|it| *it != DEV_NULL)
+			.or(original)
+			.ok_or_else(|| anyhow!("invalid patch: a file is missing its name"))?;
+		let path = patched_path(filename)
+			.ok_or_else(|| anyhow!("invalid patch: {} is outside of the package", filename))?;
+
+		let before = match original == Some(DEV_NULL) {
+			true => String::new(),
+			false => read_text(dir, &path)?.ok_or_else(|| anyhow!("{} doesn't exist", path.display()))?,
+		};
+		let after =
+			diffy::apply(&before, &patch).map_err(|err| anyhow!("{} in {}", err, path.display()))?;
+		patched.push((path, (modified != Some(DEV_NULL)).then_some(after)));
+	}
+
+	for (path, contents) in patched {
+		let path = dir.join(path);
+		match contents {
+			Some(contents) => {
+				fs::create_dir_all(path.parent().unwrap())?;
+				fs::write(path, contents)?;
+			}
+			None => fs::remove_file(path)?,
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		let changed = file_patch("index.js", Some("a\nb\nc\n"), Some("a\nB\nc\n")).unwrap();
+		let added = file_patch("lib/new.js", None, Some("new\n")).unwrap();
+		let deleted = file_patch("old.js", Some("old\n"), None).unwrap();
+		assert!(
+			changed.starts_with("diff --git a/index.js b/index.js\n--- a/index.js\n+++ b/index.js\n")
+		);
+		assert!(added.contains("new file mode 100644\n--- /dev/null\n+++ b/lib/new.js\n"));
+		assert!(deleted.contains("--- a/old.js\n+++ /dev/null\n"));
+		assert_eq!(file_patch("same.js", Some("a\n"), Some("a\n")), None);
+
+		let text = [changed, added, deleted].concat();
+		let sections = sections(&text);
+		assert_eq!(sections.len(), 3);
+		let patch = Patch::from_str(sections[0]).unwrap();
+		assert_eq!(diffy::apply("a\nb\nc\n", &patch).unwrap(), "a\nB\nc\n");
+		assert!(diffy::apply("a\nx\nc\n", &patch).is_err());
+		let patch = Patch::from_str(sections[1]).unwrap();
+		assert_eq!(diffy::apply("", &patch).unwrap(), "new\n");
+	}
+
+	#[test]
+	fn binary_files() {
+		let image = || Some(vec![0x89, b'P', b'N', b'G', 0xff]);
+		assert_eq!(
+			changed_file_patch("logo.png", image(), image()).unwrap(),
+			None
+		);
+		assert!(changed_file_patch("logo.png", image(), Some(vec![0xff]))
+			.unwrap_err()
+			.to_string()
+			.contains("binary file logo.png changed"));
+		assert!(
+			changed_file_patch("index.js", Some(b"a\n".to_vec()), Some(b"b\n".to_vec()))
+				.unwrap()
+				.is_some()
+		);
+	}
+
+	#[test]
+	fn patched_paths() {
+		assert_eq!(
+			patched_path("a/lib/index.js"),
+			Some(PathBuf::from("lib/index.js"))
+		);
+		assert_eq!(patched_path("b/index.js"), Some(PathBuf::from("index.js")));
+		assert_eq!(patched_path("a/../../etc/passwd"), None);
+		assert_eq!(patched_path("/etc/passwd"), None);
+	}
+}
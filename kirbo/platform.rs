@@ -0,0 +1,135 @@
+use std::fs;
+use std::process::Command;
+
+/// The platform that packages are being installed for, using the same names that
+/// npm uses in the `os`, `cpu`, and `libc` fields of a package.json
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Platform {
+	pub os: String,
+	pub cpu: String,
+	/// Only meaningful on Linux
+	pub libc: Option<String>,
+}
+
+impl Default for Platform {
+	fn default() -> Self {
+		Platform::current()
+	}
+}
+
+impl Platform {
+	/// The platform kirbo is currently running on
+	pub fn current() -> Self {
+		let os = match std::env::consts::OS {
+			"macos" => "darwin",
+			"windows" => "win32",
+			os => os,
+		};
+		let cpu = match std::env::consts::ARCH {
+			"x86_64" => "x64",
+			"x86" => "ia32",
+			"aarch64" => "arm64",
+			"powerpc64" => "ppc64",
+			"s390x" => "s390x",
+			cpu => cpu,
+		};
+		let libc = (os == "linux").then(|| linux_libc().to_string());
+
+		Platform {
+			os: os.to_string(),
+			cpu: cpu.to_string(),
+			libc,
+		}
+	}
+
+	/// Checks if a package with the given `os`, `cpu`, and `libc` fields can be
+	/// installed on this platform
+	pub fn supports<S: AsRef<str>>(&self, os: &[S], cpu: &[S], libc: &[S]) -> bool {
+		matches(os, Some(&self.os))
+			&& matches(cpu, Some(&self.cpu))
+			&& matches(libc, self.libc.as_ref())
+	}
+}
+
+/// The libc of the system, which isn't necessarily the one kirbo was built against.
+/// Like npm, this looks for musl's dynamic loader, then asks `ldd` as a fallback.
+fn linux_libc() -> &'static str {
+	let has_musl_loader = fs::read_dir("/lib").is_ok_and(|entries| {
+		entries
+			.flatten()
+			.any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+	});
+	if has_musl_loader {
+		return "musl";
+	}
+
+	// musl's ldd prints its version to stderr, and glibc's to stdout
+	match Command::new("ldd").arg("--version").output() {
+		Ok(output)
+			if String::from_utf8_lossy(&output.stdout).contains("musl")
+				|| String::from_utf8_lossy(&output.stderr).contains("musl") =>
+		{
+			"musl"
+		}
+		_ => "glibc",
+	}
+}
+
+/// Each list can include values to allow, and values prefixed with `!` to block. An
+/// empty list allows everything.
+fn matches<S: AsRef<str>>(list: &[S], value: Option<&String>) -> bool {
+	if list.is_empty() {
+		return true;
+	}
+
+	let Some(value) = value else {
+		// If we don't know what this is (like libc on macOS), only an explicit
+		// allow list can rule it out
+		return !list.iter().any(|it| !it.as_ref().starts_with('!'));
+	};
+
+	let mut allowed = false;
+	let mut has_allow_list = false;
+	for item in list {
+		match item.as_ref().strip_prefix('!') {
+			Some(blocked) if blocked == value => return false,
+			Some(_) => (),
+			None => {
+				has_allow_list = true;
+				allowed |= item.as_ref() == value || item.as_ref() == "any";
+			}
+		}
+	}
+
+	allowed || !has_allow_list
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn supports() {
+		let linux = Platform {
+			os: "linux".to_string(),
+			cpu: "x64".to_string(),
+			libc: Some("glibc".to_string()),
+		};
+		let darwin = Platform {
+			os: "darwin".to_string(),
+			cpu: "arm64".to_string(),
+			libc: None,
+		};
+		let none: &[&str] = &[];
+
+		assert!(linux.supports(none, none, none));
+		assert!(linux.supports(&["linux"], &["x64"], &["glibc"]));
+		assert!(!linux.supports(&["linux"], &["x64"], &["musl"]));
+		assert!(!linux.supports(&["darwin"], none, none));
+		assert!(linux.supports(&["!win32"], none, none));
+		assert!(!linux.supports(none, &["!x64"], none));
+		assert!(darwin.supports(&["darwin"], &["arm64"], none));
+		assert!(darwin.supports(none, none, &["!musl"]));
+		assert!(!darwin.supports(none, none, &["glibc"]));
+	}
+}
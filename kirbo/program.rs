@@ -1,40 +1,76 @@
+use anyhow::anyhow;
 use std::env;
+use std::process;
 
-mod commands;
-mod config;
-mod lock;
-mod npm;
-mod options;
-mod resolver;
-mod semver;
-mod workspace;
-use options::Command::*;
-use options::Options;
+use kirbo::commands;
+use kirbo::commands::config::options::Action;
+use kirbo::config;
+use kirbo::exit::ExitCode;
+use kirbo::options;
+use kirbo::options::Command::*;
+use kirbo::options::Options;
 
 fn main() -> anyhow::Result<()> {
 	let program = async {
-		let options = Options::try_from(&*env::args().skip(1).collect::<Vec<_>>())?;
+		let options = match Options::try_from(&*env::args().skip(1).collect::<Vec<_>>()) {
+			Ok(options) => options,
+			// Prints the help, version, or what was wrong with the arguments
+			Err(err) => err.exit(),
+		};
 
-		match &options.command {
-			Install => commands::install::main::main(options).await?,
-			Run => commands::run::main::main(options),
-			Exec => commands::exec::main::main(options),
+		if let Some(cwd) = &options.global.cwd {
+			env::set_current_dir(cwd)
+				.map_err(|err| anyhow!("couldn't use {} as --cwd: {}", cwd.display(), err))?;
+		}
+		options::set_global(options.global.clone());
+
+		let command = options.into_command();
+		if let Install(options) = &command {
+			commands::install::main::set_flags(options)?;
+		}
+		// Setting a value has to keep working when the config is broken, since that's
+		// how it gets fixed
+		if !matches!(&command, Config(options) if matches!(options.action, Some(Action::Set { .. }))) {
+			config::load()?;
+		}
+
+		match command {
+			Install(options) => commands::install::main::main(options).await?,
+			Run(options) => commands::run::main::main(options)?,
+			Exec(options) => commands::exec::main::main(options)?,
+			Workspaces(options) => commands::workspaces::main::main(options)?,
+			Why(options) => commands::why::main::main(options)?,
+			Insight(options) => commands::insight::main::main(options)?,
+			Outdated(options) => commands::outdated::main::main(options).await?,
+			Upgrade(options) => commands::upgrade::main::main(options).await?,
+			Dedupe(options) => commands::dedupe::main::main(options).await?,
+			Audit(options) => commands::audit::main::main(options).await?,
+			Pack(options) => commands::pack::main::main(options)?,
+			Publish(options) => commands::publish::main::main(options).await?,
+			Version(options) => commands::version::main::main(options)?,
+			Init(options) => commands::init::main::main(options)?,
+			Create(options) => commands::create::main::main(options).await?,
+			Dlx(options) => commands::dlx::main::main(options).await?,
+			Patch(options) => commands::patch::main::main(options).await?,
+			PatchCommit(options) => commands::patch_commit::main::main(options).await?,
+			Config(options) => commands::config::main::main(options)?,
+			Completions(options) => commands::completions::main::main(options)?,
+			// `Options::into_command` always turns these into `Run`
+			Script(_) => unreachable!(),
 		}
 
 		Ok(())
 	};
 
 	#[cfg(not(target_os = "wasi"))]
-	return tokio::runtime::Builder::new_multi_thread()
-		.enable_all()
-		.build()
-		.unwrap()
-		.block_on(program);
-
+	let mut runtime = tokio::runtime::Builder::new_multi_thread();
 	#[cfg(target_os = "wasi")]
-	return tokio::runtime::Builder::new_current_thread()
-		.enable_all()
-		.build()
-		.unwrap()
-		.block_on(program);
+	let mut runtime = tokio::runtime::Builder::new_current_thread();
+	let result = runtime.enable_all().build().unwrap().block_on(program);
+
+	// Commands that fail with an exit code have already explained why
+	if let Some(&ExitCode(code)) = result.as_ref().err().and_then(anyhow::Error::downcast_ref) {
+		process::exit(code);
+	}
+	result
 }
@@ -1,26 +1,270 @@
 use anyhow::anyhow;
 use async_recursion::async_recursion;
+use kirbo_firewall::Substitution;
+use kirbo_workspace::Package;
+use kirbo_workspace::PackageJson;
+use kirbo_workspace::Workspace;
 use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::lock::split_descriptor;
+use crate::lock::KirboLock;
 use crate::npm;
+use crate::overrides::Overrides;
+use crate::platform::Platform;
 use crate::semver::SemverRange;
 use crate::semver::Version;
+use crate::sources;
+use crate::sources::LockedGit;
+use crate::specifier::DependencySpecifier;
+use crate::store::Store;
+use crate::workspace::WorkspaceRange;
 
 static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
 
 #[derive(Debug, Default)]
 pub struct Resolver {
 	package_docs: HashMap<String, npm::RegistryDoc>,
+	workspace: Option<Workspace>,
+	/// Packages which should never be substituted by kirbo_firewall
+	firewall_ignore: Vec<String>,
+	/// The platform to install for, which decides which optional dependencies are
+	/// installed
+	platform: Platform,
+	/// Where packages that don't come from the registry are packed into
+	store: Store,
+	/// Overrides and resolutions from the root of the workspace
+	overrides: Overrides,
+	/// The descriptors which each overridden descriptor replaced
+	overridden: BTreeMap<String, BTreeSet<String>>,
+	resolved: ResolvedPackages,
+	/// The peer dependencies requested by each resolved package, by descriptor. Each
+	/// peer is mapped to its range and whether it's optional.
+	peer_requests: BTreeMap<String, BTreeMap<String, (String, bool)>>,
+	peer_conflicts: Vec<PeerConflict>,
+	/// Don't print progress, for commands with output of their own
+	quiet: bool,
+	/// Versions from an existing Kirbo.lock, by descriptor, which are kept for as long
+	/// as they still satisfy their range
+	locked: HashMap<String, String>,
+	/// Commits of git dependencies from an existing Kirbo.lock, by descriptor, which
+	/// are used instead of whatever their reference points to now
+	locked_git: HashMap<String, LockedGit>,
+	/// Integrity of tarball dependencies (urls and `file:` tarballs) from an existing
+	/// Kirbo.lock, by descriptor, which they have to keep matching
+	locked_tarballs: HashMap<String, String>,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Resolution {
+	/// A package which needs to be downloaded from the registry
+	Registry {
+		tarball: String,
+		integrity: Option<String>,
+	},
+	/// A package from the current workspace, which should be linked rather than
+	/// downloaded
+	Workspace { path: PathBuf },
+	/// A package which kirbo_firewall has a replacement for, so it never needs to be
+	/// downloaded at all
+	Firewall { substitution: &'static Substitution },
+	/// A package cloned from a git repository, and packed into the store. The
+	/// integrity is of the tarball it was packed into.
+	Git {
+		url: String,
+		commit: String,
+		integrity: String,
+	},
+	/// A package from a tarball outside of the registry (either a url or a `file:`
+	/// path), which is kept in the store by its integrity
+	Tarball { url: String, integrity: String },
+	/// A package elsewhere on disk, which is linked rather than copied. `link:`
+	/// dependencies are linked without installing any of their dependencies.
+	Directory { path: PathBuf, link: bool },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedPackage {
+	pub name: String,
+	pub version: String,
+	pub resolution: Resolution,
+	/// The ranges requested by this package, keyed by name
+	pub dependencies: BTreeMap<String, String>,
+	/// Names of any `dependencies` which are optional. Optional dependencies which
+	/// couldn't be resolved, or which don't support the platform, are left out of
+	/// `dependencies` entirely.
+	pub optional_dependencies: BTreeSet<String>,
+	/// The peers this package was given by its dependents (or installed for it), mapped
+	/// to the range of the descriptor that provides them
+	pub peer_dependencies: BTreeMap<String, String>,
+	/// Descriptors which were replaced by this one because of an override
+	pub overrides: BTreeSet<String>,
+}
+
+/// A package whose dependent provides a version of one of its peers that doesn't
+/// satisfy the range it asks for
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PeerConflict {
+	/// `name@version` of the package requesting the peer
+	pub package: String,
+	pub peer: String,
+	pub range: String,
+	/// The package (or workspace package.json) providing the wrong version
+	pub provider: String,
+	pub provided_version: String,
+}
+
+/// Every package in the dependency graph, keyed by descriptor (`name@range`)
+pub type ResolvedPackages = BTreeMap<String, ResolvedPackage>;
+
 impl Resolver {
+	pub fn new(workspace: Workspace) -> anyhow::Result<Self> {
+		let root = &workspace.root_package().1;
+		let firewall_ignore = root
+			.kirbo
+			.as_ref()
+			.map(|settings| settings.firewall.ignore.clone())
+			.unwrap_or_default();
+		let overrides = Overrides::from_package(root)?;
+
+		Ok(Resolver {
+			workspace: Some(workspace),
+			firewall_ignore,
+			overrides,
+			..Default::default()
+		})
+	}
+
+	/// Resolves packages for a platform other than the current one
+	pub fn with_platform(mut self, platform: Platform) -> Self {
+		self.platform = platform;
+		self
+	}
+
+	/// Prefers the versions in an existing lock over newer ones, so that installing
+	/// doesn't upgrade anything unless its range changed
+	pub fn with_lock(mut self, lock: &KirboLock) -> Self {
+		self.locked = lock
+			.packages
+			.iter()
+			.filter(|(_, package)| !package.version.is_empty())
+			.map(|(descriptor, package)| (descriptor.clone(), package.version.clone()))
+			.collect();
+		self.locked_git = lock
+			.packages
+			.iter()
+			.filter_map(|(descriptor, package)| {
+				let (_, commit) = package.resolved.strip_prefix("git+")?.rsplit_once('#')?;
+				let locked = LockedGit {
+					commit: commit.to_string(),
+					sha512: package.sha512.clone(),
+				};
+				Some((descriptor.clone(), locked))
+			})
+			.collect();
+		self.locked_tarballs = lock
+			.packages
+			.iter()
+			.filter(|(descriptor, package)| {
+				let specifier = DependencySpecifier::parse(split_descriptor(descriptor).1);
+				let tarball = matches!(
+					specifier,
+					DependencySpecifier::Tarball(_) | DependencySpecifier::File(_)
+				);
+				tarball && !package.sha512.is_empty()
+			})
+			.map(|(descriptor, package)| (descriptor.clone(), package.sha512.clone()))
+			.collect();
+		self
+	}
+
+	/// Stops the resolver from printing progress while it fetches packages
+	pub fn quiet(mut self) -> Self {
+		self.quiet = true;
+		self
+	}
+
+	/// Prints a line of the dependency tree, `layer` levels deep, unless the resolver
+	/// was made quiet
+	fn print_tree(&self, layer: usize, line: fmt::Arguments) {
+		if !self.quiet {
+			println!("\t{}├ {}", "⎜ ".repeat(layer), line);
+		}
+	}
+
+	/// Finds the kirbo_firewall substitute for a dependency, if there is one and it
+	/// hasn't been opted out of. Malicious packages are always substituted, and
+	/// anything else only if the substitute satisfies the requested range.
+	fn query_firewall(&self, package: &str, range: &str) -> Option<&'static Substitution> {
+		if self.firewall_ignore.iter().any(|name| name == package) {
+			return None;
+		}
+
+		let substitution = kirbo_firewall::find(package)?;
+		if substitution.range == "*" {
+			return Some(substitution);
+		}
+
+		let satisfied = match (
+			Version::from_str(substitution.version),
+			SemverRange::from_str(range),
+		) {
+			(Ok(version), Ok(range)) => version.satisfies(&range),
+			_ => false,
+		};
+		Some(substitution).filter(|_| satisfied)
+	}
+
+	/// Finds the workspace package that should be used for a dependency, if there is
+	/// one. `workspace:` ranges must be satisfied by the workspace, but regular ranges
+	/// will only be linked if the local copy matches. Otherwise they come from the
+	/// registry like anything else.
+	fn query_workspace(&self, package: &str, range: &str) -> anyhow::Result<Option<&PackageJson>> {
+		let member = self.workspace.as_ref().and_then(|it| it.get(package));
+		let version = member
+			.and_then(|package_json| package_json.1.version.as_ref())
+			.and_then(|version| Version::from_str(version).ok());
+
+		if WorkspaceRange::is_workspace_range(range) {
+			let member = member.ok_or_else(|| anyhow!("no workspace package is named {}", package))?;
+			let range = WorkspaceRange::from_str(range)?;
+			let satisfied = match &version {
+				Some(version) => range.matches(version),
+				None => range == WorkspaceRange::Any,
+			};
+
+			if !satisfied {
+				return Err(anyhow!(
+					"workspace package {} does not satisfy {:?}",
+					package,
+					range
+				));
+			}
+
+			return Ok(Some(member));
+		}
+
+		let satisfied = match (version, SemverRange::from_str(range)) {
+			(Some(version), Ok(range)) => version.satisfies(&range),
+			_ => false,
+		};
+
+		Ok(member.filter(|_| satisfied))
+	}
+
 	pub async fn query_package(&mut self, package: &str) -> anyhow::Result<&npm::RegistryDoc> {
 		if !self.package_docs.contains_key(package) {
-			print!("❄️ ");
+			if !self.quiet {
+				print!("❄️ ");
+			}
 			let doc = CLIENT
-				.get(format!("https://registry.npmjs.org/{}", package))
+				.get(npm::registry_doc_url(package))
 				.send()
 				.await?
 				.json::<npm::RegistryDoc>()
@@ -33,75 +277,659 @@ impl Resolver {
 		Ok(self.package_docs.get(package).unwrap())
 	}
 
+	/// Resolves every dependency (and all of their dependencies), returning every
+	/// package that will need to be installed, keyed by descriptor (`name@range`).
+	pub async fn resolve<'a, D>(&mut self, dependencies: D) -> anyhow::Result<ResolvedPackages>
+	where
+		D: IntoIterator<Item = (&'a String, &'a String)> + Send,
+		D::IntoIter: Send,
+	{
+		let dependencies = dependencies
+			.into_iter()
+			.map(|(name, range)| (name.clone(), range.clone()))
+			.collect::<BTreeMap<_, _>>();
+		self.resolve_dependencies(&dependencies, &[]).await?;
+		self.resolve_peers(&dependencies).await?;
+
+		for (descriptor, replaced) in &self.overridden {
+			if let Some(package) = self.resolved.get_mut(descriptor) {
+				package.overrides = replaced.clone();
+			}
+		}
+		Ok(self.resolved.clone())
+	}
+
+	/// The newest version of a package in the registry which satisfies `range`
+	pub async fn newest_version(&mut self, package: &str, range: &str) -> Option<String> {
+		let range = SemverRange::from_str(range).ok()?;
+		let doc = self.query_package(package).await.ok()?;
+		doc
+			.versions
+			.keys()
+			.flat_map(|version| Version::from_str(version))
+			.filter(|version| version.satisfies(&range))
+			.max()
+			.map(|version| version.to_string())
+	}
+
+	/// Replaces the range of any dependency that has an override which applies to it
+	/// when it's depended on through `ancestors`
+	async fn apply_overrides(
+		&mut self,
+		ancestors: &[(String, String)],
+		dependencies: BTreeMap<String, String>,
+	) -> BTreeMap<String, String> {
+		if self.overrides.is_empty() {
+			return dependencies;
+		}
+
+		let mut applied = BTreeMap::new();
+		for (name, range) in dependencies {
+			let version = match self.overrides.needs_version(&name) {
+				true => match self.locked.get(&format!("{}@{}", name, range)) {
+					Some(locked) => Some(locked.clone()),
+					None => self.newest_version(&name, &range).await,
+				},
+				false => None,
+			};
+			let replacement = self
+				.overrides
+				.find(ancestors, &name, version.as_deref())
+				.map(|rule| rule.replacement.clone());
+
+			match replacement {
+				Some(replacement) if replacement != range => {
+					self
+						.overridden
+						.entry(format!("{}@{}", name, replacement))
+						.or_default()
+						.insert(format!("{}@{}", name, range));
+					applied.insert(name, replacement);
+				}
+				_ => {
+					applied.insert(name, range);
+				}
+			}
+		}
+		applied
+	}
+
+	/// Peer dependency conflicts found by the last call to `resolve`
+	pub fn peer_conflicts(&self) -> &[PeerConflict] {
+		&self.peer_conflicts
+	}
+
+	/// Gives every package that has peer dependencies the version provided by the
+	/// package that depends on it (or by the root of the workspace), installing any
+	/// peers that nobody provides, like npm 7+ does.
+	async fn resolve_peers(
+		&mut self,
+		root_dependencies: &BTreeMap<String, String>,
+	) -> anyhow::Result<()> {
+		let root_name = self
+			.workspace
+			.as_ref()
+			.and_then(|workspace| workspace.root_package().1.name.clone())
+			.unwrap_or_else(|| "package.json".to_string());
+		let mut settled = BTreeSet::new();
+
+		// Installing a missing peer can bring in more peers, so keep going until there's
+		// nothing left to settle
+		loop {
+			let pending = self
+				.peer_requests
+				.iter()
+				.flat_map(|(descriptor, peers)| {
+					peers
+						.iter()
+						.map(move |(peer, request)| (descriptor.clone(), peer.clone(), request.clone()))
+				})
+				.filter(|(descriptor, peer, _)| !settled.contains(&(descriptor.clone(), peer.clone())))
+				.collect::<Vec<_>>();
+			if pending.is_empty() {
+				break;
+			}
+
+			for (descriptor, peer, (range, optional)) in pending {
+				settled.insert((descriptor.clone(), peer.clone()));
+				let Some(package) = self.resolved.get(&descriptor) else {
+					continue;
+				};
+				let id = format!("{}@{}", package.name, package.version);
+				let peer_range = SemverRange::from_str(&range).ok();
+
+				// Everything which depends on this package, along with the ranges they have
+				// in scope, falling back to whatever is hoisted from the root
+				let mut scopes = self
+					.resolved
+					.values()
+					.filter(|parent| {
+						parent
+							.dependencies
+							.get(&package.name)
+							.map(|range| format!("{}@{}", package.name, range))
+							== Some(descriptor.clone())
+					})
+					.map(|parent| {
+						(
+							format!("{}@{}", parent.name, parent.version),
+							parent.dependencies.get(&peer),
+						)
+					})
+					.collect::<Vec<_>>();
+				if root_dependencies
+					.get(&package.name)
+					.map(|range| format!("{}@{}", package.name, range))
+					== Some(descriptor.clone())
+				{
+					scopes.push((root_name.clone(), root_dependencies.get(&peer)));
+				}
+
+				let mut provided = None;
+				let mut conflicts = vec![];
+				for (provider, provided_range) in scopes {
+					let Some(provided_range) = provided_range.or_else(|| root_dependencies.get(&peer)) else {
+						continue;
+					};
+					let Some(provided_package) = self.resolved.get(&format!("{}@{}", peer, provided_range))
+					else {
+						continue;
+					};
+
+					let satisfied = match (&peer_range, Version::from_str(&provided_package.version)) {
+						(Some(peer_range), Ok(version)) => version.satisfies(peer_range),
+						_ => false,
+					};
+					if satisfied {
+						provided.get_or_insert_with(|| provided_range.clone());
+					} else {
+						conflicts.push(PeerConflict {
+							package: id.clone(),
+							peer: peer.clone(),
+							range: range.clone(),
+							provider,
+							provided_version: provided_package.version.clone(),
+						});
+					}
+				}
+
+				if provided.is_none() && conflicts.is_empty() && !optional {
+					self.print_tree(0, format_args!("{}@{} (peer of {})", peer, range, id));
+					self
+						.resolve_dependencies(&BTreeMap::from([(peer.clone(), range.clone())]), &[])
+						.await?;
+					provided = Some(range.clone());
+				}
+
+				self.peer_conflicts.extend(conflicts);
+				if let Some(provided) = provided {
+					self
+						.resolved
+						.get_mut(&descriptor)
+						.unwrap()
+						.peer_dependencies
+						.insert(peer, provided);
+				}
+			}
+		}
+
+		Ok(())
+	}
+
 	#[async_recursion(?Send)]
-	pub async fn resolve_dependencies<'a, D>(
+	async fn resolve_dependencies<'a, D>(
 		&mut self,
 		dependencies: D,
-		layer: usize,
-	) -> anyhow::Result<HashMap<String, String>>
+		ancestors: &[(String, String)],
+	) -> anyhow::Result<()>
 	where
 		D: IntoIterator<Item = (&'a String, &'a String)> + Send,
 		D::IntoIter: Send,
 	{
-		let mut resolved_dependencies = HashMap::new();
-
+		let layer = ancestors.len();
 		for (dependency, version) in dependencies {
-			// Not ideal to clone, but the borrow checker thinks this is a mutable borrow?
-			let doc = self.query_package(dependency).await?.clone();
-			let mut available_versions = doc
-				.versions
-				.keys()
-				.map(AsRef::as_ref)
-				.map(Version::from_str)
+			let descriptor = format!("{}@{}", dependency, version);
+			if self.resolved.contains_key(&descriptor) {
+				continue;
+			}
+
+			let specifier = DependencySpecifier::parse(version);
+			// Names are used as paths when installing, so they have to be checked before
+			// anything else sees them
+			let alias = match &specifier {
+				DependencySpecifier::Alias { name, .. } => Some(name.as_str()),
+				_ => None,
+			};
+			if let Some(name) = [Some(dependency.as_str()), alias]
+				.into_iter()
 				.flatten()
-				.collect::<Vec<_>>();
-			// This is less than ideal, but whatever
-			available_versions.sort();
-			available_versions.reverse();
-			let version_range = SemverRange::from_str(version)
-				.map_err(|_| anyhow!("Invalid version range \"{}\"", version))?;
-			let matched_version = available_versions
+				.find(|name| !npm::is_valid_name(name))
+			{
+				let dependent = match ancestors.last() {
+					Some((parent, parent_version)) => format!("{}@{}", parent, parent_version),
+					None => "package.json".to_string(),
+				};
+				return Err(anyhow!(
+					"{} depends on {}, but {:?} isn't a valid package name",
+					dependent,
+					descriptor,
+					name
+				));
+			}
+
+			// Aliases are installed under a different name, but the firewall still needs
+			// to see the real one
+			if let DependencySpecifier::Alias { name, range } = &specifier {
+				if !self.substitute(dependency, name, range, &descriptor, layer) {
+					self
+						.resolve_registry(dependency, name, range, &descriptor, ancestors)
+						.await?;
+				}
+				continue;
+			}
+			if let (true, Some((parent, parent_version))) = (specifier.is_local(), ancestors.last()) {
+				return Err(anyhow!(
+					"{}@{} depends on {}, but it isn't on disk to depend on anything relative to it",
+					parent,
+					parent_version,
+					descriptor
+				));
+			}
+			if let Some((package, resolution)) =
+				self.fetch_source(&specifier, version, &descriptor).await?
+			{
+				self
+					.resolve_source(dependency, package, resolution, &descriptor, ancestors)
+					.await?;
+				continue;
+			}
+
+			if let Some(member) = self.query_workspace(dependency, version)?.cloned() {
+				self.print_tree(layer, format_args!("{} (workspace)", dependency));
+
+				// Workspace packages are developed locally, so their dev dependencies need
+				// to be available too.
+				let member_dependencies = self.rebase_local_dependencies(
+					member.dir(),
+					member
+						.1
+						.dependencies
+						.clone()
+						.into_iter()
+						.chain(member.1.dev_dependencies.clone()),
+				);
+
+				self.resolved.insert(
+					descriptor,
+					ResolvedPackage {
+						name: dependency.clone(),
+						version: member.1.version.clone().unwrap_or_default(),
+						resolution: Resolution::Workspace {
+							path: member.dir().to_path_buf(),
+						},
+						dependencies: member_dependencies.clone(),
+						optional_dependencies: BTreeSet::new(),
+						peer_dependencies: BTreeMap::new(),
+						overrides: BTreeSet::new(),
+					},
+				);
+
+				// Workspace packages are as good as the root, so they aren't ancestors as far
+				// as overrides are concerned, and their own dependencies are never overridden
+				self
+					.resolve_dependencies(&member_dependencies, ancestors)
+					.await?;
+				continue;
+			}
+
+			if self.substitute(dependency, dependency, version, &descriptor, layer) {
+				continue;
+			}
+
+			self
+				.resolve_registry(dependency, dependency, version, &descriptor, ancestors)
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// Resolves a dependency to its kirbo_firewall substitute, if it has one.
+	/// `registry_name` is the package it would have come from, which differs from
+	/// `dependency` when using an `npm:` alias.
+	fn substitute(
+		&mut self,
+		dependency: &str,
+		registry_name: &str,
+		range: &str,
+		descriptor: &str,
+		layer: usize,
+	) -> bool {
+		let Some(substitution) = self.query_firewall(registry_name, range) else {
+			return false;
+		};
+		self.print_tree(
+			layer,
+			format_args!("{}@{} (firewall)", dependency, substitution.version),
+		);
+
+		self.resolved.insert(
+			descriptor.to_string(),
+			ResolvedPackage {
+				name: dependency.to_string(),
+				version: substitution.version.to_string(),
+				resolution: Resolution::Firewall { substitution },
+				dependencies: BTreeMap::new(),
+				optional_dependencies: BTreeSet::new(),
+				peer_dependencies: BTreeMap::new(),
+				overrides: BTreeSet::new(),
+			},
+		);
+		true
+	}
+
+	/// Fetches the package.json of a dependency that doesn't come from the registry,
+	/// along with how to install it. Paths are relative to the root of the workspace.
+	async fn fetch_source(
+		&self,
+		specifier: &DependencySpecifier,
+		version: &str,
+		descriptor: &str,
+	) -> anyhow::Result<Option<(Package, Resolution)>> {
+		let root = self
+			.workspace
+			.as_ref()
+			.map(|workspace| workspace.root().to_path_buf())
+			.unwrap_or_default();
+
+		Ok(Some(match specifier {
+			DependencySpecifier::Range(_)
+			| DependencySpecifier::Workspace(_)
+			| DependencySpecifier::Alias { .. } => return Ok(None),
+			DependencySpecifier::Git { url, reference } => {
+				let locked = self.locked_git.get(descriptor);
+				let source = sources::fetch_git(&self.store, url, reference, locked)?;
+				(
+					source.package,
+					Resolution::Git {
+						url: url.clone(),
+						commit: source.commit,
+						integrity: format!("sha512-{}", source.sha512),
+					},
+				)
+			}
+			DependencySpecifier::Tarball(url) => {
+				let locked = self.locked_tarballs.get(descriptor).map(String::as_str);
+				let source = sources::fetch_tarball(&self.store, url, locked).await?;
+				(
+					source.package,
+					Resolution::Tarball {
+						url: version.to_string(),
+						integrity: format!("sha512-{}", source.sha512),
+					},
+				)
+			}
+			DependencySpecifier::File(path) if root.join(path).is_file() => {
+				let path = sources::normalize(&root.join(path));
+				let locked = self.locked_tarballs.get(descriptor).map(String::as_str);
+				let source = sources::fetch_tarball(&self.store, &path.to_string_lossy(), locked).await?;
+				(
+					source.package,
+					Resolution::Tarball {
+						url: version.to_string(),
+						integrity: format!("sha512-{}", source.sha512),
+					},
+				)
+			}
+			DependencySpecifier::File(path) | DependencySpecifier::Link(path) => {
+				let path = sources::normalize(&root.join(path));
+				let package_json = PackageJson::try_from(path.join("package.json"))
+					.map_err(|err| anyhow!("failed to read {}: {:?}", path.display(), err))?;
+				(
+					package_json.1,
+					Resolution::Directory {
+						path,
+						link: matches!(specifier, DependencySpecifier::Link(_)),
+					},
+				)
+			}
+		}))
+	}
+
+	/// Rewrites any `file:` or `link:` dependencies of a package in `dir` so that
+	/// they're relative to the root of the workspace instead, which keeps descriptors
+	/// the same no matter which package they come from
+	fn rebase_local_dependencies<I>(&self, dir: &Path, dependencies: I) -> BTreeMap<String, String>
+	where
+		I: IntoIterator<Item = (String, String)>,
+	{
+		let root = self
+			.workspace
+			.as_ref()
+			.map(|workspace| workspace.root().to_path_buf())
+			.unwrap_or_default();
+
+		dependencies
+			.into_iter()
+			.map(|(name, range)| {
+				let (prefix, path) = match DependencySpecifier::parse(&range) {
+					DependencySpecifier::File(path) => ("file", path),
+					DependencySpecifier::Link(path) => ("link", path),
+					_ => return (name, range),
+				};
+				let path = sources::normalize(&dir.join(path));
+				let path = path.strip_prefix(&root).unwrap_or(&path);
+				let range = format!("{}:{}", prefix, path.to_string_lossy().replace('\\', "/"));
+				(name, range)
+			})
+			.collect()
+	}
+
+	/// Adds a package that doesn't come from the registry, and resolves its dependencies
+	async fn resolve_source(
+		&mut self,
+		dependency: &str,
+		package: Package,
+		resolution: Resolution,
+		descriptor: &str,
+		ancestors: &[(String, String)],
+	) -> anyhow::Result<()> {
+		let version = package.version.clone().unwrap_or_default();
+		let kind = match &resolution {
+			Resolution::Git { .. } => "git",
+			Resolution::Tarball { .. } => "tarball",
+			Resolution::Directory { link: true, .. } => "link",
+			_ => "file",
+		};
+		self.print_tree(
+			ancestors.len(),
+			format_args!("{}@{} ({})", dependency, version, kind),
+		);
+
+		// Packages on disk are as good as the root, just like workspace packages, so
+		// they aren't ancestors as far as overrides are concerned
+		let (ancestors, dependencies) = match &resolution {
+			Resolution::Directory { link: true, .. } => (ancestors.to_vec(), BTreeMap::new()),
+			Resolution::Directory { path, .. } => (
+				ancestors.to_vec(),
+				self.rebase_local_dependencies(path, package.dependencies),
+			),
+			_ => {
+				let ancestors = [ancestors, &[(dependency.to_string(), version.clone())]].concat();
+				let dependencies = self
+					.apply_overrides(&ancestors, package.dependencies.into_iter().collect())
+					.await;
+				(ancestors, dependencies)
+			}
+		};
+
+		self.resolved.insert(
+			descriptor.to_string(),
+			ResolvedPackage {
+				name: dependency.to_string(),
+				version,
+				resolution,
+				dependencies: dependencies.clone(),
+				optional_dependencies: BTreeSet::new(),
+				peer_dependencies: BTreeMap::new(),
+				overrides: BTreeSet::new(),
+			},
+		);
+
+		self.resolve_dependencies(&dependencies, &ancestors).await
+	}
+
+	/// Resolves a package from the registry, along with its dependencies. `dependency`
+	/// is the name that it's installed as, which can differ from `registry_name` when
+	/// using an `npm:` alias.
+	async fn resolve_registry(
+		&mut self,
+		dependency: &str,
+		registry_name: &str,
+		range: &str,
+		descriptor: &str,
+		ancestors: &[(String, String)],
+	) -> anyhow::Result<()> {
+		let layer = ancestors.len();
+		// Not ideal to clone, but the borrow checker thinks this is a mutable borrow?
+		let doc = self.query_package(registry_name).await?.clone();
+		let mut available_versions = doc
+			.versions
+			.keys()
+			.map(AsRef::as_ref)
+			.flat_map(Version::from_str)
+			.collect::<Vec<_>>();
+		// This is less than ideal, but whatever
+		available_versions.sort();
+		available_versions.reverse();
+		let version_range =
+			SemverRange::from_str(range).map_err(|_| anyhow!("Invalid version range \"{}\"", range))?;
+		let locked_version = self
+			.locked
+			.get(descriptor)
+			.and_then(|version| Version::from_str(version).ok())
+			.filter(|version| {
+				version.satisfies(&version_range) && doc.versions.contains_key(&version.to_string())
+			});
+		let matched_version = match &locked_version {
+			Some(version) => version,
+			None => available_versions
 				.iter()
 				.find(|version| version.satisfies(&version_range))
-				.ok_or_else(|| anyhow!("no version of {} satisfies {}", dependency, version))?;
+				.ok_or_else(|| anyhow!("no version of {} satisfies {}", registry_name, range))?,
+		};
 
-			println!("about to unwrap {}", dependency);
-			let desired_version = doc.versions.get(&matched_version.to_string()).unwrap();
+		let desired_version = doc
+			.versions
+			.get(&matched_version.to_string())
+			.ok_or_else(|| anyhow!("registry is missing {}@{}", registry_name, matched_version))?;
 
-			let desired_tarball = desired_version.dist.tarball.clone();
-
-			// println!("----------------------------------------");
-			println!(
-				// "\t{}⎣ {}@{}",
-				"\t{}├ {}@{}",
-				"⎜ ".repeat(layer),
+		if !self.platform.supports(
+			&desired_version.os,
+			&desired_version.cpu,
+			&desired_version.libc,
+		) {
+			return Err(anyhow!(
+				"{}@{} does not support {} {}",
 				dependency,
-				matched_version
-			);
-			// println!("----------------------------------------");
-			// println!("{:#?}\n\n", &desired_tarball);
+				matched_version,
+				self.platform.os,
+				self.platform.cpu
+			));
+		}
 
-			if layer > 100 {
-				panic!("PANIC");
-			}
+		self.print_tree(layer, format_args!("{}@{}", dependency, matched_version));
 
-			resolved_dependencies.insert(dependency.clone(), desired_tarball);
+		if layer > 100 {
+			return Err(anyhow!("dependency tree is too deep at {}", descriptor));
+		}
 
-			let transitive_dependencies = &desired_version.dependencies;
+		// Optional dependencies take precedence over regular dependencies of the same
+		// name, so they get left out here
+		let ancestors = [
+			ancestors,
+			&[(dependency.to_string(), matched_version.to_string())],
+		]
+		.concat();
+		let transitive_dependencies = desired_version
+			.dependencies
+			.clone()
+			.into_iter()
+			.filter(|(name, _)| !desired_version.optional_dependencies.contains_key(name))
+			.collect::<BTreeMap<_, _>>();
+		let optional_dependencies = desired_version
+			.optional_dependencies
+			.clone()
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+		let transitive_dependencies = self
+			.apply_overrides(&ancestors, transitive_dependencies)
+			.await;
+		let optional_dependencies = self
+			.apply_overrides(&ancestors, optional_dependencies)
+			.await;
 
-			resolved_dependencies.extend(
-				self
-					.resolve_dependencies(
-						transitive_dependencies
-							.iter()
-							.filter(|&(name, _)| !self.package_docs.contains_key(name))
-							.collect::<HashMap<_, _>>(),
-						layer + 1,
-					)
-					.await?,
+		self.resolved.insert(
+			descriptor.to_string(),
+			ResolvedPackage {
+				name: dependency.to_string(),
+				version: matched_version.to_string(),
+				resolution: Resolution::Registry {
+					tarball: desired_version.dist.tarball.clone(),
+					integrity: desired_version.dist.integrity.clone(),
+				},
+				dependencies: transitive_dependencies.clone(),
+				optional_dependencies: BTreeSet::new(),
+				peer_dependencies: BTreeMap::new(),
+				overrides: BTreeSet::new(),
+			},
+		);
+
+		if !desired_version.peer_dependencies.is_empty() {
+			let peers = desired_version
+				.peer_dependencies
+				.iter()
+				.map(|(name, range)| {
+					let optional = desired_version
+						.peer_dependencies_meta
+						.get(name)
+						.map(|meta| meta.optional)
+						.unwrap_or(false);
+					(name.clone(), (range.clone(), optional))
+				})
+				.collect();
+			self.peer_requests.insert(descriptor.to_string(), peers);
+		}
+
+		self
+			.resolve_dependencies(&transitive_dependencies, &ancestors)
+			.await?;
+
+		// Anything that goes wrong with an optional dependency (usually just that it's
+		// for a different platform) means we leave it out, along with anything that was
+		// resolved because of it.
+		for (name, range) in &optional_dependencies {
+			let snapshot = (
+				self.resolved.clone(),
+				self.peer_requests.clone(),
+				self.overridden.clone(),
 			);
+			match self.resolve_dependencies([(name, range)], &ancestors).await {
+				Ok(()) => {
+					let package = self.resolved.get_mut(descriptor).unwrap();
+					package.dependencies.insert(name.clone(), range.clone());
+					package.optional_dependencies.insert(name.clone());
+				}
+				Err(err) => {
+					(self.resolved, self.peer_requests, self.overridden) = snapshot;
+					self.print_tree(
+						layer + 1,
+						format_args!("{}@{} (skipped optional: {})", name, range, err),
+					);
+				}
+			}
 		}
 
-		Ok(resolved_dependencies)
+		Ok(())
 	}
 }
@@ -0,0 +1,301 @@
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Variables which are passed along from our own environment. Anything else (like
+/// `KIRBO_AUTH_TOKEN` or `NPM_TOKEN`) could be written somewhere for later, even
+/// without network access.
+const INHERITED_ENV: &[&str] = &[
+	"PATH",
+	"HOME",
+	"TERM",
+	"LANG",
+	#[cfg(windows)]
+	"SYSTEMROOT",
+	#[cfg(windows)]
+	"COMSPEC",
+	#[cfg(windows)]
+	"PATHEXT",
+	#[cfg(windows)]
+	"USERPROFILE",
+];
+
+/// Clears the environment of a command, apart from `INHERITED_ENV`, and whatever
+/// was set on the command itself (like `npm_lifecycle_event`)
+fn clear_env(command: &mut Command) {
+	let explicit = command
+		.get_envs()
+		.filter_map(|(name, value)| Some((name.to_owned(), value?.to_owned())))
+		.collect::<Vec<_>>();
+
+	command.env_clear();
+	for name in INHERITED_ENV {
+		if let Some(value) = env::var_os(name) {
+			command.env(name, value);
+		}
+	}
+	command.envs(explicit);
+}
+
+/// Confines a command so that the only part of the filesystem it can write to is
+/// `dir` (and a private `/tmp`), it has no network access, it can't make any of the
+/// system calls that would let it undo those restrictions, and it only sees the
+/// environment variables it needs.
+#[cfg(target_os = "linux")]
+pub fn confine(command: &mut Command, dir: &Path) -> io::Result<()> {
+	clear_env(command);
+	linux::confine(command, dir)
+}
+
+/// Sandboxing is only implemented for Linux, so everywhere else we have to trust the
+/// packages that have been explicitly allowed to run scripts. They still don't get
+/// to see the whole environment though.
+#[cfg(not(target_os = "linux"))]
+pub fn confine(command: &mut Command, dir: &Path) -> io::Result<()> {
+	clear_env(command);
+	eprintln!(
+		"warning: scripts can't be sandboxed on this platform, running {} unconfined",
+		dir.display()
+	);
+	Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+	use std::ffi::CString;
+	use std::io;
+	use std::os::unix::ffi::OsStrExt;
+	use std::os::unix::process::CommandExt;
+	use std::path::Path;
+	use std::process::Command;
+	use std::ptr;
+
+	const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+	#[repr(C)]
+	struct MountAttr {
+		attr_set: u64,
+		attr_clr: u64,
+		propagation: u64,
+		userns_fd: u64,
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	const AUDIT_ARCH: u32 = 0xc000_003e;
+	#[cfg(target_arch = "aarch64")]
+	const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+	/// Syscalls which could be used to escape from, or see outside of, the sandbox
+	const DENIED_SYSCALLS: &[libc::c_long] = &[
+		libc::SYS_mount,
+		libc::SYS_umount2,
+		libc::SYS_mount_setattr,
+		libc::SYS_move_mount,
+		libc::SYS_open_tree,
+		libc::SYS_fsopen,
+		libc::SYS_fsmount,
+		libc::SYS_fspick,
+		libc::SYS_pivot_root,
+		libc::SYS_chroot,
+		libc::SYS_unshare,
+		libc::SYS_setns,
+		libc::SYS_ptrace,
+		libc::SYS_process_vm_readv,
+		libc::SYS_process_vm_writev,
+		libc::SYS_kexec_load,
+		libc::SYS_init_module,
+		libc::SYS_finit_module,
+		libc::SYS_delete_module,
+		libc::SYS_reboot,
+		libc::SYS_swapon,
+		libc::SYS_swapoff,
+		libc::SYS_bpf,
+		libc::SYS_perf_event_open,
+		libc::SYS_keyctl,
+		libc::SYS_add_key,
+		libc::SYS_request_key,
+		libc::SYS_userfaultfd,
+	];
+
+	fn statement(code: u32, k: u32) -> libc::sock_filter {
+		libc::sock_filter {
+			code: code as u16,
+			jt: 0,
+			jf: 0,
+			k,
+		}
+	}
+
+	fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+		libc::sock_filter {
+			code: code as u16,
+			jt,
+			jf,
+			k,
+		}
+	}
+
+	/// A seccomp program which kills the process if it's running under an unexpected
+	/// architecture, and fails any of the denied syscalls with `EPERM`
+	fn seccomp_filter() -> Vec<libc::sock_filter> {
+		// Offsets into `struct seccomp_data`
+		let nr = 0;
+		let arch = 4;
+
+		let mut filter = vec![
+			statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, arch),
+			jump(
+				libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+				AUDIT_ARCH,
+				1,
+				0,
+			),
+			statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+			statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, nr),
+		];
+
+		for &syscall in DENIED_SYSCALLS {
+			filter.push(jump(
+				libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+				syscall as u32,
+				0,
+				1,
+			));
+			filter.push(statement(
+				libc::BPF_RET | libc::BPF_K,
+				libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+			));
+		}
+
+		filter.push(statement(
+			libc::BPF_RET | libc::BPF_K,
+			libc::SECCOMP_RET_ALLOW,
+		));
+		filter
+	}
+
+	fn check(result: libc::c_long) -> io::Result<()> {
+		match result {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(()),
+		}
+	}
+
+	/// Writes to a file using nothing but syscalls, since we can't allocate between
+	/// `fork` and `exec`
+	unsafe fn write_file(path: &CString, contents: &[u8]) -> io::Result<()> {
+		let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+		check(fd as libc::c_long)?;
+		let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+		libc::close(fd);
+		check(written as libc::c_long)
+	}
+
+	unsafe fn mount_setattr(path: &CString, attr: &MountAttr) -> io::Result<()> {
+		check(libc::syscall(
+			libc::SYS_mount_setattr,
+			libc::AT_FDCWD,
+			path.as_ptr(),
+			libc::AT_RECURSIVE,
+			attr as *const MountAttr,
+			std::mem::size_of::<MountAttr>(),
+		))
+	}
+
+	pub fn confine(command: &mut Command, dir: &Path) -> io::Result<()> {
+		// Everything that needs to allocate has to happen out here, before we fork
+		let dir = dir.canonicalize()?;
+		let dir_c = CString::new(dir.as_os_str().as_bytes())?;
+		let root = CString::new("/")?;
+		let tmp = CString::new("/tmp")?;
+		let tmpfs = CString::new("tmpfs")?;
+		let setgroups = CString::new("/proc/self/setgroups")?;
+		let uid_map = CString::new("/proc/self/uid_map")?;
+		let gid_map = CString::new("/proc/self/gid_map")?;
+		// Map ourselves to ourselves, so that anything the script creates is owned by
+		// the user who ran the install
+		let uid = unsafe { libc::getuid() };
+		let gid = unsafe { libc::getgid() };
+		let uid_line = format!("{} {} 1", uid, uid);
+		let gid_line = format!("{} {} 1", gid, gid);
+		// If the package lives in /tmp, then covering /tmp up would hide it
+		let private_tmp = !dir.starts_with("/tmp");
+		let filter = seccomp_filter();
+
+		let read_only = MountAttr {
+			attr_set: MOUNT_ATTR_RDONLY,
+			attr_clr: 0,
+			propagation: 0,
+			userns_fd: 0,
+		};
+		let writable = MountAttr {
+			attr_set: 0,
+			attr_clr: MOUNT_ATTR_RDONLY,
+			propagation: 0,
+			userns_fd: 0,
+		};
+
+		let setup = move || unsafe {
+			check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET) as _)?;
+			write_file(&setgroups, b"deny")?;
+			write_file(&uid_map, uid_line.as_bytes())?;
+			write_file(&gid_map, gid_line.as_bytes())?;
+
+			// Keep our mounts to ourselves
+			check(libc::mount(
+				ptr::null(),
+				root.as_ptr(),
+				ptr::null(),
+				libc::MS_REC | libc::MS_PRIVATE,
+				ptr::null(),
+			) as _)?;
+
+			// Give the package directory (and /tmp) mounts of their own, so that they can
+			// stay writable after everything else is made read-only
+			check(libc::mount(
+				dir_c.as_ptr(),
+				dir_c.as_ptr(),
+				ptr::null(),
+				libc::MS_BIND | libc::MS_REC,
+				ptr::null(),
+			) as _)?;
+			if private_tmp {
+				check(libc::mount(
+					tmpfs.as_ptr(),
+					tmp.as_ptr(),
+					tmpfs.as_ptr(),
+					libc::MS_NOSUID | libc::MS_NODEV,
+					ptr::null(),
+				) as _)?;
+			}
+
+			mount_setattr(&root, &read_only)?;
+			mount_setattr(&dir_c, &writable)?;
+			if private_tmp {
+				mount_setattr(&tmp, &writable)?;
+			}
+
+			// Our working directory still points at the old, read-only mount
+			check(libc::chdir(dir_c.as_ptr()) as _)?;
+
+			let program = libc::sock_fprog {
+				len: filter.len() as u16,
+				filter: filter.as_ptr() as *mut libc::sock_filter,
+			};
+			check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as _)?;
+			check(libc::prctl(
+				libc::PR_SET_SECCOMP,
+				libc::SECCOMP_MODE_FILTER,
+				&program as *const libc::sock_fprog,
+			) as _)?;
+
+			Ok(())
+		};
+
+		unsafe {
+			command.pre_exec(setup);
+		}
+		Ok(())
+	}
+}
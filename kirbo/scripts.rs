@@ -0,0 +1,85 @@
+use anyhow::anyhow;
+use colored::Colorize;
+use kirbo_workspace::Package;
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+/// Builds a `PATH` which includes the `node_modules/.bin/` folder of `dir` and of
+/// each of its parents, so that scripts in a workspace package can use binaries
+/// installed at the root.
+pub fn path_env(dir: &Path) -> OsString {
+	let bins = dir
+		.ancestors()
+		.map(|dir| dir.join("node_modules/.bin/"))
+		.filter(|bin| bin.is_dir());
+	let path = env::var_os("PATH").unwrap_or_default();
+
+	env::join_paths(bins.chain(env::split_paths(&path))).unwrap_or(path)
+}
+
+/// Creates a command which will run a package.json script in `dir`, with any
+/// additional arguments passed along to it.
+pub fn command(dir: &Path, script: &str, args: &[String]) -> Command {
+	// Pass in additional arguments. I always thought this npm/yarn behavior felt hacky,
+	// but I like it even less after seeing what it takes to implement it.
+	#[cfg(not(windows))]
+	let mut cmd = {
+		let mut script = script.to_string();
+		for it in 1..=args.len() {
+			script.push_str(&format!(" \"${}\"", it));
+		}
+
+		let mut cmd = Command::new("sh");
+		// `sh -c` takes the first argument after the script as `$0`
+		cmd.arg("-c").arg(&script).arg("sh").args(args);
+		cmd
+	};
+
+	#[cfg(windows)]
+	let mut cmd = {
+		let mut cmd = Command::new("powershell");
+		cmd.arg("-Command").arg(script).args(args);
+		cmd
+	};
+
+	cmd.current_dir(dir).env("PATH", path_env(dir));
+	cmd
+}
+
+/// Scripts which npm runs automatically when a package is installed, in the order it
+/// runs them
+pub const LIFECYCLE_EVENTS: &[&str] = &["preinstall", "install", "postinstall"];
+
+/// The install lifecycle scripts that a package defines, in the order they should run
+pub fn lifecycle_scripts(package: &Package) -> Vec<(&'static str, &str)> {
+	LIFECYCLE_EVENTS
+		.iter()
+		.filter_map(|event| Some((*event, package.scripts.get(*event)?.as_str())))
+		.collect()
+}
+
+/// Runs the `event` script of the package in `dir`, if it has one, like npm does
+/// around commands such as `pack` and `version`
+pub fn run_event(dir: &Path, package: &Package, event: &str) -> anyhow::Result<()> {
+	let Some(script) = package.scripts.get(event) else {
+		return Ok(());
+	};
+
+	println!("{} {}", event.dimmed(), script);
+	let mut command = command(dir, script, &[]);
+	command.env("npm_lifecycle_event", event);
+	if let Some(name) = &package.name {
+		command.env("npm_package_name", name);
+	}
+	if let Some(version) = &package.version {
+		command.env("npm_package_version", version);
+	}
+
+	let status = command.status()?;
+	if !status.success() {
+		return Err(anyhow!("{} script failed ({})", event, status));
+	}
+	Ok(())
+}
@@ -17,11 +17,11 @@ pub struct Version {
 impl Display for Version {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
-		if self.prerelease_info.is_some() {
-			write!(f, "-{}", self.prerelease_info.as_ref().unwrap())?;
+		if let Some(prerelease_info) = &self.prerelease_info {
+			write!(f, "-{}", prerelease_info)?;
 		}
-		if self.build_info.is_some() {
-			write!(f, "+{}", self.build_info.as_ref().unwrap())?;
+		if let Some(build_info) = &self.build_info {
+			write!(f, "+{}", build_info)?;
 		}
 		Ok(())
 	}
@@ -44,72 +44,43 @@ impl FromStr for Version {
 	type Err = anyhow::Error;
 
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let mut prerelease_start = None;
-		let mut build_start = None;
-		let mut must_be_dot_or_positive_digit = true;
-		for (i, c) in s.chars().enumerate() {
-			if c == '+' {
-				if must_be_dot_or_positive_digit {
-					return Err(anyhow!("unexpected character {}", c));
-				}
-				build_start = Some(i);
-				break;
-			}
-
-			if prerelease_start.is_some() {
-				continue;
-			}
+		let (without_build, build_info) = match s.split_once('+') {
+			Some((rest, build)) => (rest, Some(build.to_string())),
+			None => (s, None),
+		};
+		let (version, prerelease_info) = match without_build.split_once('-') {
+			Some((rest, prerelease)) => (rest, Some(prerelease.to_string())),
+			None => (without_build, None),
+		};
 
-			if c == '-' {
-				if must_be_dot_or_positive_digit {
-					return Err(anyhow!("unexpected character {}", c));
-				}
-				prerelease_start = Some(i);
-				continue;
+		for info in [&prerelease_info, &build_info].into_iter().flatten() {
+			if info.is_empty()
+				|| info.split('.').any(str::is_empty)
+				|| !info
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+			{
+				return Err(anyhow!("invalid version \"{}\"", s));
 			}
+		}
 
-			if !c.is_ascii_digit() && c != '.' {
-				return Err(anyhow!("unexpected character {}", c));
+		let mut parts = version.split('.').map(|part| {
+			if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+				return Err(anyhow!("unexpected version segment \"{}\"", part));
 			}
-
-			if must_be_dot_or_positive_digit && (c == '0' || c == '.') {
-				return Err(anyhow!("unexpected character {}", c));
+			if part.len() > 1 && part.starts_with('0') {
+				return Err(anyhow!("version segments cannot have leading zeros"));
 			}
-
-			must_be_dot_or_positive_digit = c == '.';
-		}
-
-		dbg!(&prerelease_start);
-
-		let version = {
-			let end = prerelease_start.unwrap_or(s.len());
-			&s[0..end]
-		};
-		let prerelease_info = prerelease_start.map(|start| {
-			let end = build_start.unwrap_or(s.len());
-			s[start..end].to_string()
+			Ok(part.parse::<u64>()?)
 		});
-		let build_info = build_start.map(|start| s[start..s.len()].to_string());
-
-		prerelease_info
-			.as_ref()
-			.map(|it| it.is_empty())
-			.unwrap_or(false);
 
-		let mut parts = version.split('.');
 		let major = parts
 			.next()
-			.ok_or(anyhow!("empty version is not allowed"))?
-			.parse()?;
+			.ok_or(anyhow!("empty version is not allowed"))??;
 		// XXX: Should we be stricter and require minor and patch? Maybe only
 		// require minor? How does this interact with ranges like `~1`?
-		let (minor, patch) = {
-			let mut next = || match parts.next() {
-				Some(part) => dbg!(part).parse(),
-				None => Ok(0),
-			};
-			(next()?, next()?)
-		};
+		let minor = parts.next().unwrap_or(Ok(0))?;
+		let patch = parts.next().unwrap_or(Ok(0))?;
 
 		if parts.next().is_some() {
 			return Err(anyhow!("semver versions must have exactly three segments"));
@@ -138,6 +109,71 @@ impl Ord for Version {
 			.cmp(&other.major)
 			.then(self.minor.cmp(&other.minor))
 			.then(self.patch.cmp(&other.patch))
+			.then_with(|| compare_prerelease(&self.prerelease_info, &other.prerelease_info))
+			// Build info doesn't have any meaningful order, but it still needs to agree
+			// with `Eq`.
+			.then_with(|| self.build_info.cmp(&other.build_info))
+	}
+}
+
+/// A version without prerelease info is always greater than the same version with
+/// it. Otherwise, each dot separated identifier is compared in order, numerically if
+/// possible.
+fn compare_prerelease(a: &Option<String>, b: &Option<String>) -> Ordering {
+	let (a, b) = match (a, b) {
+		(None, None) => return Ordering::Equal,
+		(None, Some(_)) => return Ordering::Greater,
+		(Some(_), None) => return Ordering::Less,
+		(Some(a), Some(b)) => (a, b),
+	};
+
+	let mut a = a.split('.');
+	let mut b = b.split('.');
+	loop {
+		let ordering = match (a.next(), b.next()) {
+			(None, None) => return Ordering::Equal,
+			(None, Some(_)) => return Ordering::Less,
+			(Some(_), None) => return Ordering::Greater,
+			(Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+				(Ok(a), Ok(b)) => a.cmp(&b),
+				(Ok(_), Err(_)) => Ordering::Less,
+				(Err(_), Ok(_)) => Ordering::Greater,
+				(Err(_), Err(_)) => a.cmp(b),
+			},
+		};
+
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+	}
+}
+
+/// Which part of a version to increment, named like the arguments to `npm version`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Bump {
+	Major,
+	Minor,
+	Patch,
+	Premajor,
+	Preminor,
+	Prepatch,
+	Prerelease,
+}
+
+impl FromStr for Bump {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"major" => Ok(Bump::Major),
+			"minor" => Ok(Bump::Minor),
+			"patch" => Ok(Bump::Patch),
+			"premajor" => Ok(Bump::Premajor),
+			"preminor" => Ok(Bump::Preminor),
+			"prepatch" => Ok(Bump::Prepatch),
+			"prerelease" => Ok(Bump::Prerelease),
+			_ => Err(anyhow!("unknown version bump \"{}\"", s)),
+		}
 	}
 }
 
@@ -146,8 +182,50 @@ impl Version {
 		range.matches(self)
 	}
 
-	pub fn to_string(&self) -> String {
-		format!("{}.{}.{}", self.major, self.minor, self.patch)
+	/// The next version, following the same rules as npm. Bumping a prerelease to a
+	/// release drops its prerelease info if it's already at that release, so
+	/// `patch` turns `1.2.3-beta.1` into `1.2.3`. Prerelease bumps start counting from
+	/// `<preid>.0`, or just `0` without a preid.
+	pub fn bump(&self, bump: Bump, preid: Option<&str>) -> Version {
+		let first_prerelease = || {
+			Some(match preid {
+				Some(preid) => format!("{}.0", preid),
+				None => "0".to_string(),
+			})
+		};
+		let is_prerelease = self.prerelease_info.is_some();
+		let (major, minor, patch) = (self.major, self.minor, self.patch);
+
+		let (version, prerelease_info) = match bump {
+			Bump::Major if is_prerelease && minor == 0 && patch == 0 => ((major, 0, 0), None),
+			Bump::Major => ((major + 1, 0, 0), None),
+			Bump::Minor if is_prerelease && patch == 0 => ((major, minor, 0), None),
+			Bump::Minor => ((major, minor + 1, 0), None),
+			Bump::Patch if is_prerelease => ((major, minor, patch), None),
+			Bump::Patch => ((major, minor, patch + 1), None),
+			Bump::Premajor => ((major + 1, 0, 0), first_prerelease()),
+			Bump::Preminor => ((major, minor + 1, 0), first_prerelease()),
+			Bump::Prepatch => ((major, minor, patch + 1), first_prerelease()),
+			Bump::Prerelease => match &self.prerelease_info {
+				None => ((major, minor, patch + 1), first_prerelease()),
+				Some(info) if preid.is_some_and(|preid| info.split('.').next() != Some(preid)) => {
+					((major, minor, patch), first_prerelease())
+				}
+				Some(info) => {
+					let mut parts = info.split('.').map(str::to_string).collect::<Vec<_>>();
+					match parts.last().and_then(|last| last.parse::<u64>().ok()) {
+						Some(n) => *parts.last_mut().unwrap() = (n + 1).to_string(),
+						None => parts.push("0".to_string()),
+					}
+					((major, minor, patch), Some(parts.join(".")))
+				}
+			},
+		};
+
+		Version {
+			prerelease_info,
+			..Version::from(version)
+		}
 	}
 }
 
@@ -159,6 +237,8 @@ pub enum SemverRange {
 	GreaterThanOrEqual(Version),
 	/// <x.y.z
 	LessThan(Version),
+	/// <=x.y.z
+	LessThanOrEqual(Version),
 	/// x.y.z
 	Exact(Version),
 	/// ~x.y.z
@@ -167,6 +247,10 @@ pub enum SemverRange {
 	Compatible(Version),
 	/// *
 	Any,
+	/// >=x.y.z <a.b.c, etc. Every range must match.
+	All(Vec<SemverRange>),
+	/// ^x.y.z || ^a.b.c, etc. Any range may match.
+	Either(Vec<SemverRange>),
 }
 
 impl Debug for SemverRange {
@@ -176,38 +260,133 @@ impl Debug for SemverRange {
 			SemverRange::GreaterThan(range) => write!(f, ">{:?}", range),
 			SemverRange::GreaterThanOrEqual(range) => write!(f, ">={:?}", range),
 			SemverRange::LessThan(range) => write!(f, "<{:?}", range),
+			SemverRange::LessThanOrEqual(range) => write!(f, "<={:?}", range),
 			SemverRange::Exact(range) => write!(f, "{:?}", range),
 			SemverRange::Patched(range) => write!(f, "~{:?}", range),
 			SemverRange::Compatible(range) => write!(f, "^{:?}", range),
 			SemverRange::Any => write!(f, "*"),
+			SemverRange::All(ranges) => write!(f, "{:?}", ranges),
+			SemverRange::Either(ranges) => write!(f, "Either{:?}", ranges),
 		}?;
 		write!(f, ")")
 	}
 }
 
+/// Parses a version which might have missing or wildcard (`x`, `X`, `*`) parts,
+/// returning the lowest matching version and how many parts were actually given
+fn parse_partial(s: &str) -> anyhow::Result<(Version, usize)> {
+	let s = s.strip_prefix(['v', '=']).unwrap_or(s);
+	let (core, rest) = match s.find(['-', '+']) {
+		Some(index) => s.split_at(index),
+		None => (s, ""),
+	};
+
+	let parts = core
+		.split('.')
+		.take_while(|part| !matches!(*part, "x" | "X" | "*"))
+		.collect::<Vec<_>>();
+	if parts.is_empty() || parts.len() > 3 {
+		return Ok((Version::from((0, 0, 0)), 0));
+	}
+
+	let mut version = parts.join(".");
+	for _ in parts.len()..3 {
+		version.push_str(".0");
+	}
+	if parts.len() == 3 {
+		version.push_str(rest);
+	}
+	Ok((version.parse()?, parts.len()))
+}
+
+/// The first version which doesn't match a partial version with `parts` parts given.
+/// ie. 1.2 is followed by 1.3.0, and 1 by 2.0.0.
+fn after_partial(version: &Version, parts: usize) -> Version {
+	match parts {
+		1 => Version::from((version.major + 1, 0, 0)),
+		_ => Version::from((version.major, version.minor + 1, 0)),
+	}
+}
+
+/// Parses a single comparator, like `^1.2.3`, `>=1`, or `1.x`
+fn parse_comparator(s: &str) -> anyhow::Result<SemverRange> {
+	let (operator, version) = match s.find(|c: char| c.is_ascii_alphanumeric() || c == '*') {
+		Some(index) => s.split_at(index),
+		None => return Err(anyhow!("invalid semver range {}", s)),
+	};
+	let (lowest, parts) = parse_partial(version)?;
+
+	Ok(match (operator, parts) {
+		("" | "=" | "^" | "~" | ">=", 0) => SemverRange::Any,
+		(">" | "<", 0) => SemverRange::LessThan(Version::from((0, 0, 0))),
+		("<=", 0) => SemverRange::Any,
+		("" | "=", 3) => SemverRange::Exact(lowest),
+		// A partial version like 1.2 or 1.x means 1.2.x or 1.x.x
+		("" | "=", parts) => SemverRange::All(vec![
+			SemverRange::LessThan(after_partial(&lowest, parts)),
+			SemverRange::GreaterThanOrEqual(lowest),
+		]),
+		("^", _) => SemverRange::Compatible(lowest),
+		("~", 1) => SemverRange::All(vec![
+			SemverRange::GreaterThanOrEqual(lowest.clone()),
+			SemverRange::LessThan(after_partial(&lowest, 1)),
+		]),
+		("~", _) => SemverRange::Patched(lowest),
+		(">=", _) => SemverRange::GreaterThanOrEqual(lowest),
+		(">", 3) => SemverRange::GreaterThan(lowest),
+		(">", parts) => SemverRange::GreaterThanOrEqual(after_partial(&lowest, parts)),
+		("<", _) => SemverRange::LessThan(lowest),
+		("<=", 3) => SemverRange::LessThanOrEqual(lowest),
+		("<=", parts) => SemverRange::LessThan(after_partial(&lowest, parts)),
+		(operator, _) => return Err(anyhow!("invalid semver operator {}", operator)),
+	})
+}
+
 impl FromStr for SemverRange {
 	type Err = anyhow::Error;
 
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		if s == "*" {
-			Ok(Self::Any)
-		} else if s.starts_with(|a| char::is_ascii_digit(&a)) {
-			Ok(Self::Exact(s.parse()?))
-		} else if s.starts_with('^') {
-			Ok(Self::Compatible(s[1..].parse()?))
-		} else if s.starts_with('~') {
-			Ok(Self::Patched(s[1..].parse()?))
-		} else if s.starts_with('^') {
-			Ok(Self::Compatible(s[1..].parse()?))
-		} else if s.starts_with(">=") {
-			Ok(Self::GreaterThanOrEqual(s[2..].parse()?))
-		} else if s.starts_with(">=") {
-			Ok(Self::GreaterThan(s[2..].parse()?))
-		} else if s.starts_with("<") {
-			Ok(Self::LessThan(s[2..].parse()?))
-		} else {
-			Err(anyhow!("invalid semver range {}", s))
-		}
+		let mut alternatives = s
+			.split("||")
+			.map(|alternative| {
+				let alternative = alternative.trim();
+
+				// 1.2.3 - 2.3.4
+				if let Some((from, to)) = alternative.split_once(" - ") {
+					let (from, _) = parse_partial(from.trim())?;
+					return Ok(SemverRange::All(vec![
+						SemverRange::GreaterThanOrEqual(from),
+						parse_comparator(&format!("<={}", to.trim()))?,
+					]));
+				}
+
+				// Operators are allowed to be separated from their version by a space
+				let mut comparators = vec![];
+				let mut operator = String::new();
+				for token in alternative.split_whitespace() {
+					if token
+						.chars()
+						.all(|c| matches!(c, '<' | '>' | '=' | '^' | '~'))
+					{
+						operator.push_str(token);
+						continue;
+					}
+					comparators.push(parse_comparator(&format!("{}{}", operator, token))?);
+					operator.clear();
+				}
+
+				Ok(match comparators.len() {
+					0 => SemverRange::Any,
+					1 => comparators.pop().unwrap(),
+					_ => SemverRange::All(comparators),
+				})
+			})
+			.collect::<anyhow::Result<Vec<_>>>()?;
+
+		Ok(match alternatives.len() {
+			1 => alternatives.pop().unwrap(),
+			_ => SemverRange::Either(alternatives),
+		})
 	}
 }
 
@@ -217,6 +396,7 @@ impl SemverRange {
 			SemverRange::GreaterThan(range) => version > range,
 			SemverRange::GreaterThanOrEqual(range) => version >= range,
 			SemverRange::LessThan(range) => version < range,
+			SemverRange::LessThanOrEqual(range) => version <= range,
 			SemverRange::Exact(range) => version == range,
 			SemverRange::Patched(range) => {
 				version.major == range.major && version.minor == range.minor && version.patch >= range.patch
@@ -236,6 +416,8 @@ impl SemverRange {
 				version.major == range.major && version >= range
 			}
 			SemverRange::Any => true,
+			SemverRange::All(ranges) => ranges.iter().all(|range| range.matches(version)),
+			SemverRange::Either(ranges) => ranges.iter().any(|range| range.matches(version)),
 		}
 	}
 }
@@ -253,6 +435,10 @@ mod tests {
 			Version::from((1, 2, 3))
 		);
 		assert!("1.2.3-".parse::<Version>().is_err());
+		assert_eq!(
+			"1.2.3-be_ta+7".parse::<Version>().unwrap_err().to_string(),
+			"invalid version \"1.2.3-be_ta+7\""
+		);
 		assert!("1.2.3.4".parse::<Version>().is_err());
 		assert!("1.2.3b".parse::<Version>().is_err());
 
@@ -288,6 +474,43 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn bump() {
+		let bump = |version: &str, bump: &str, preid: Option<&str>| {
+			Version::from_str(version)
+				.unwrap()
+				.bump(bump.parse().unwrap(), preid)
+				.to_string()
+		};
+
+		assert_eq!(bump("1.2.3", "major", None), "2.0.0");
+		assert_eq!(bump("1.2.3", "minor", None), "1.3.0");
+		assert_eq!(bump("1.2.3", "patch", None), "1.2.4");
+		assert_eq!(bump("1.2.3+build", "patch", None), "1.2.4");
+		assert_eq!(bump("2.0.0-rc.1", "major", None), "2.0.0");
+		assert_eq!(bump("1.3.0-rc.1", "minor", None), "1.3.0");
+		assert_eq!(bump("1.2.4-rc.1", "patch", None), "1.2.4");
+		assert_eq!(bump("1.2.3", "premajor", None), "2.0.0-0");
+		assert_eq!(bump("1.2.3", "preminor", Some("beta")), "1.3.0-beta.0");
+		assert_eq!(bump("1.2.3", "prepatch", None), "1.2.4-0");
+		assert_eq!(bump("1.2.3", "prerelease", Some("alpha")), "1.2.4-alpha.0");
+		assert_eq!(bump("1.2.4-alpha.0", "prerelease", None), "1.2.4-alpha.1");
+		assert_eq!(bump("1.2.4-alpha", "prerelease", None), "1.2.4-alpha.0");
+		assert_eq!(
+			bump("1.2.4-alpha.3", "prerelease", Some("beta")),
+			"1.2.4-beta.0"
+		);
+		assert_eq!(
+			bump("1.2.4-alphabet.3", "prerelease", Some("alpha")),
+			"1.2.4-alpha.0"
+		);
+		assert_eq!(
+			bump("1.2.4-alpha.3", "prerelease", Some("alpha")),
+			"1.2.4-alpha.4"
+		);
+		assert!("huge".parse::<Bump>().is_err());
+	}
+
 	#[test]
 	fn parse_version_range() {
 		matches!("1".parse(), Ok(SemverRange::Exact(_)));
@@ -399,6 +622,37 @@ mod tests {
 		assert!(!Version::from((2, 0, 0)).satisfies(&range));
 	}
 
+	#[test]
+	fn compound() {
+		let range = "^17.0.0 || >=18.1 <19".parse::<SemverRange>().unwrap();
+		assert!(Version::from((17, 2, 0)).satisfies(&range));
+		assert!(!Version::from((18, 0, 0)).satisfies(&range));
+		assert!(Version::from((18, 1, 0)).satisfies(&range));
+		assert!(!Version::from((19, 0, 0)).satisfies(&range));
+
+		let range = ">= 1.2.3 <= 1.4".parse::<SemverRange>().unwrap();
+		assert!(!Version::from((1, 2, 2)).satisfies(&range));
+		assert!(Version::from((1, 4, 9)).satisfies(&range));
+		assert!(!Version::from((1, 5, 0)).satisfies(&range));
+
+		let range = "1.2.3 - 2".parse::<SemverRange>().unwrap();
+		assert!(Version::from((1, 2, 3)).satisfies(&range));
+		assert!(Version::from((2, 9, 0)).satisfies(&range));
+		assert!(!Version::from((3, 0, 0)).satisfies(&range));
+
+		let range = "1.x".parse::<SemverRange>().unwrap();
+		assert!(Version::from((1, 9, 0)).satisfies(&range));
+		assert!(!Version::from((2, 0, 0)).satisfies(&range));
+
+		let range = "~1".parse::<SemverRange>().unwrap();
+		assert!(Version::from((1, 9, 0)).satisfies(&range));
+		assert!(!Version::from((2, 0, 0)).satisfies(&range));
+
+		assert!(Version::from((5, 0, 0)).satisfies(&"x".parse().unwrap()));
+		assert!(Version::from((5, 0, 0)).satisfies(&"".parse().unwrap()));
+		assert!("poyo".parse::<SemverRange>().is_err());
+	}
+
 	#[test]
 	fn any() {
 		use SemverRange::Any;
@@ -0,0 +1,242 @@
+use anyhow::anyhow;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use kirbo_workspace::Package;
+use once_cell::sync::Lazy;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use crate::git;
+use crate::semver::SemverRange;
+use crate::semver::Version;
+use crate::specifier::GitReference;
+use crate::store::sha512_base64;
+use crate::store::Store;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// A git dependency that has been packed into the store
+#[derive(Clone, Debug)]
+pub struct GitSource {
+	pub commit: String,
+	/// Base64 encoded, of the tarball it was packed into
+	pub sha512: String,
+	pub package: Package,
+}
+
+/// How a git dependency was resolved by an existing lock
+#[derive(Clone, Debug)]
+pub struct LockedGit {
+	pub commit: String,
+	/// Base64 encoded, or empty for locks written before it was recorded
+	pub sha512: String,
+}
+
+/// A tarball that has been downloaded (or copied) into the store
+#[derive(Clone, Debug)]
+pub struct TarballSource {
+	/// Base64 encoded, and also used to find it in the store
+	pub sha512: String,
+	pub package: Package,
+}
+
+/// Clones a git dependency, and packs the commit that it refers to into the store,
+/// where it can be found again with `store.source_path(commit)`. If the dependency
+/// is locked then the locked commit is used instead of wherever `reference` points
+/// now, and it must pack into exactly the same tarball as it did before.
+pub fn fetch_git(
+	store: &Store,
+	url: &str,
+	reference: &GitReference,
+	locked: Option<&LockedGit>,
+) -> anyhow::Result<GitSource> {
+	// There's no need to clone anything if the store already has the locked commit
+	if let Some(locked) = locked.filter(|locked| !locked.sha512.is_empty()) {
+		let tarball = store.source_path(&locked.commit);
+		if fs::read(&tarball).is_ok_and(|bytes| sha512_base64(&bytes) == locked.sha512) {
+			return Ok(GitSource {
+				commit: locked.commit.clone(),
+				sha512: locked.sha512.clone(),
+				package: read_package_json(&tarball)?,
+			});
+		}
+	}
+
+	let clone = store
+		.source_path(url)
+		.with_extension(format!("{}.git", process::id()));
+	fs::create_dir_all(clone.parent().unwrap())?;
+	let _ = fs::remove_dir_all(&clone);
+
+	let fetched = (|| {
+		git::clone_bare(url, &clone)?;
+		let rev = match (locked, reference) {
+			(Some(locked), _) => locked.commit.clone(),
+			(None, GitReference::Head) => "HEAD".to_string(),
+			(None, GitReference::Committish(committish)) => committish.clone(),
+			(None, GitReference::Semver(range)) => newest_tag(&clone, range)?
+				.ok_or_else(|| anyhow!("no tag in {} satisfies {}", url, range))?,
+		};
+		let commit = git::resolve_commit(&clone, &rev)?;
+		let package = serde_json::from_str(&git::read_file(&clone, &commit, "package.json")?)?;
+
+		// Always pack it again, rather than trusting whatever is already in the store
+		let tarball = store.source_path(&commit);
+		let tar = tarball.with_extension("tar.partial");
+		let partial = tarball.with_extension("tgz.partial");
+		git::archive(&clone, &commit, &tar)?;
+		let bytes = gzip(&fs::read(&tar)?)?;
+		let _ = fs::remove_file(&tar);
+
+		let sha512 = sha512_base64(&bytes);
+		if let Some(locked) = locked.filter(|locked| !locked.sha512.is_empty()) {
+			if sha512 != locked.sha512 {
+				return Err(anyhow!(
+					"integrity check failed for git+{}#{}: expected sha512 {}, got {}",
+					url,
+					commit,
+					locked.sha512,
+					sha512
+				));
+			}
+		}
+		fs::write(&partial, &bytes)?;
+		fs::rename(&partial, &tarball)?;
+
+		Ok(GitSource {
+			commit,
+			sha512,
+			package,
+		})
+	})();
+
+	let _ = fs::remove_dir_all(&clone);
+	fetched
+}
+
+/// Compresses a tarball. This has to give the same bytes every time for the same
+/// tarball, so that its integrity can be locked, which is why git isn't left to do it.
+fn gzip(tar: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let mut encoder = GzEncoder::new(vec![], Compression::default());
+	encoder.write_all(tar)?;
+	Ok(encoder.finish()?)
+}
+
+/// The newest tag (ignoring a leading `v`) which satisfies a range
+fn newest_tag(dir: &Path, range: &str) -> anyhow::Result<Option<String>> {
+	let range = SemverRange::from_str(range)?;
+	Ok(
+		git::tags(dir)?
+			.into_iter()
+			.filter_map(|tag| {
+				let version = Version::from_str(tag.strip_prefix('v').unwrap_or(&tag)).ok()?;
+				Some((version, tag))
+			})
+			.filter(|(version, _)| version.satisfies(&range))
+			.max_by(|(a, _), (b, _)| a.cmp(b))
+			.map(|(_, tag)| tag),
+	)
+}
+
+/// Downloads a tarball into the store, or copies it if `location` is a path rather
+/// than a url. It can be found again with `store.source_path(sha512)`. If the
+/// tarball is locked (`locked` is base64, as it appears in a lock), then it must
+/// still match, and urls aren't downloaded again if the store already has it.
+pub async fn fetch_tarball(
+	store: &Store,
+	location: &str,
+	locked: Option<&str>,
+) -> anyhow::Result<TarballSource> {
+	let locked = locked.filter(|locked| !locked.is_empty());
+	let bytes = match location.starts_with("http://") || location.starts_with("https://") {
+		true => {
+			if let Some(locked) = locked {
+				let tarball = store.source_path(locked);
+				if fs::read(&tarball).is_ok_and(|bytes| sha512_base64(&bytes) == locked) {
+					return Ok(TarballSource {
+						sha512: locked.to_string(),
+						package: read_package_json(&tarball)?,
+					});
+				}
+			}
+
+			CLIENT
+				.get(location)
+				.send()
+				.await?
+				.error_for_status()?
+				.bytes()
+				.await?
+				.to_vec()
+		}
+		false => fs::read(location).map_err(|err| anyhow!("failed to read {}: {}", location, err))?,
+	};
+
+	let sha512 = sha512_base64(&bytes);
+	if let Some(locked) = locked.filter(|locked| *locked != sha512) {
+		return Err(anyhow!(
+			"integrity check failed for {}: expected sha512 {}, got {}",
+			location,
+			locked,
+			sha512
+		));
+	}
+
+	// Always write it again, rather than trusting whatever is already in the store
+	let tarball = store.source_path(&sha512);
+	fs::create_dir_all(tarball.parent().unwrap())?;
+	let partial = tarball.with_extension("tgz.partial");
+	fs::write(&partial, &bytes)?;
+	fs::rename(&partial, &tarball)?;
+
+	let package = read_package_json(&tarball)?;
+	Ok(TarballSource { sha512, package })
+}
+
+/// Reads the package.json from the top of a package tarball, whatever the directory
+/// inside of it happens to be called
+pub fn read_package_json(tarball: &Path) -> anyhow::Result<Package> {
+	let mut archive = tar::Archive::new(GzDecoder::new(File::open(tarball)?));
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let path = entry.path()?.into_owned();
+		let mut components = path.components();
+		components.next();
+		if components.as_path() == Path::new("package.json") {
+			let mut text = String::new();
+			entry.read_to_string(&mut text)?;
+			return Ok(serde_json::from_str(&text)?);
+		}
+	}
+
+	Err(anyhow!(
+		"{} doesn't contain a package.json",
+		tarball.display()
+	))
+}
+
+/// Resolves `..` and `.` in a path without touching the filesystem, since the path
+/// might not exist yet
+pub fn normalize(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => (),
+			Component::ParentDir => {
+				if !normalized.pop() {
+					normalized.push("..");
+				}
+			}
+			component => normalized.push(component),
+		}
+	}
+	normalized
+}
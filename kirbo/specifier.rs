@@ -0,0 +1,276 @@
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::lock::split_descriptor;
+use crate::semver::Version;
+
+/// Everything that can appear as the value of a dependency in a package.json, like
+/// `^1.0.0`, `npm:real-name@^2.0.0`, or `github:org/repo#v1.0.0`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DependencySpecifier {
+	/// A semver range (or anything else) that should be looked up in the registry
+	Range(String),
+	/// `workspace:<range>`, for another package in the same workspace
+	Workspace(String),
+	/// `npm:<name>@<range>`, for installing a package under a different name
+	Alias { name: String, range: String },
+	/// A repository that should be cloned, with the url already normalized into
+	/// something git itself can clone
+	Git {
+		url: String,
+		reference: GitReference,
+	},
+	/// A url to a tarball, which should be downloaded as is
+	Tarball(String),
+	/// `file:<path>`, for a directory or tarball elsewhere on disk
+	File(PathBuf),
+	/// `link:<path>`, for a directory which should be linked without installing any of
+	/// its dependencies
+	Link(PathBuf),
+}
+
+/// What to check out from a git dependency, taken from the part of the url after `#`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GitReference {
+	/// The default branch
+	Head,
+	/// A branch, tag, or commit
+	Committish(String),
+	/// `#semver:<range>`, for the newest tag matching a range
+	Semver(String),
+}
+
+impl FromStr for DependencySpecifier {
+	type Err = Infallible;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(DependencySpecifier::parse(s))
+	}
+}
+
+impl DependencySpecifier {
+	/// Anything that isn't recognized as something else is treated as a range, so
+	/// parsing can't fail
+	pub fn parse(s: &str) -> Self {
+		if s.starts_with("workspace:") {
+			return DependencySpecifier::Workspace(s.to_string());
+		}
+		if let Some(alias) = s.strip_prefix("npm:") {
+			let (name, range) = split_descriptor(alias);
+			return DependencySpecifier::Alias {
+				name: name.to_string(),
+				range: match range {
+					"" => "*".to_string(),
+					range => range.to_string(),
+				},
+			};
+		}
+		if let Some(path) = s.strip_prefix("file:") {
+			return DependencySpecifier::File(PathBuf::from(path));
+		}
+		if let Some(path) = s.strip_prefix("link:") {
+			return DependencySpecifier::Link(PathBuf::from(path));
+		}
+		if ["./", "../", "/", "~/"]
+			.iter()
+			.any(|prefix| s.starts_with(prefix))
+		{
+			return DependencySpecifier::File(PathBuf::from(s));
+		}
+
+		let (url, fragment) = match s.split_once('#') {
+			Some((url, fragment)) => (url, Some(fragment)),
+			None => (s, None),
+		};
+		let git_url = if let Some(url) = url.strip_prefix("git+") {
+			Some(url.to_string())
+		} else if url.starts_with("git://") || url.starts_with("ssh://") {
+			Some(url.to_string())
+		} else if let Some((host, path)) = url.split_once(':').and_then(|(host, path)| {
+			let host = match host {
+				"github" => "github.com",
+				"gitlab" => "gitlab.com",
+				"bitbucket" => "bitbucket.org",
+				_ => return None,
+			};
+			Some((host, path))
+		}) {
+			Some(format!(
+				"https://{}/{}.git",
+				host,
+				path.trim_end_matches(".git")
+			))
+		} else if (url.starts_with("http://") || url.starts_with("https://")) && url.ends_with(".git") {
+			Some(url.to_string())
+		} else if is_github_shorthand(url) {
+			Some(format!(
+				"https://github.com/{}.git",
+				url.trim_end_matches(".git")
+			))
+		} else {
+			None
+		};
+
+		if let Some(url) = git_url {
+			let reference = match fragment {
+				None | Some("") => GitReference::Head,
+				Some(fragment) => match fragment.strip_prefix("semver:") {
+					Some(range) => GitReference::Semver(range.to_string()),
+					None => GitReference::Committish(fragment.to_string()),
+				},
+			};
+			return DependencySpecifier::Git { url, reference };
+		}
+
+		if s.starts_with("http://") || s.starts_with("https://") {
+			return DependencySpecifier::Tarball(s.to_string());
+		}
+
+		DependencySpecifier::Range(s.to_string())
+	}
+
+	/// Whether this has to be resolved relative to the package that depends on it,
+	/// which only makes sense for packages that are on disk
+	pub fn is_local(&self) -> bool {
+		matches!(
+			self,
+			DependencySpecifier::File(_) | DependencySpecifier::Link(_)
+		)
+	}
+
+	/// The lowest version allowed by a range made of a single version, with an optional
+	/// `^`, `~`, or `=` in front of it, which is the only kind of range that
+	/// `with_version` knows how to rewrite
+	pub fn floor(&self) -> Option<Version> {
+		match self {
+			DependencySpecifier::Range(range) | DependencySpecifier::Alias { range, .. } => {
+				Some(split_range(range)?.1)
+			}
+			_ => None,
+		}
+	}
+
+	/// Rewrites the range to start at `version`, keeping its operator and any `npm:`
+	/// alias, so `^1.0.0` becomes `^1.2.0` and `npm:real@~1.0.0` becomes
+	/// `npm:real@~1.2.0`
+	pub fn with_version(&self, version: &Version) -> Option<String> {
+		match self {
+			DependencySpecifier::Range(range) => Some(format!("{}{}", split_range(range)?.0, version)),
+			DependencySpecifier::Alias { name, range } => {
+				Some(format!("npm:{}@{}{}", name, split_range(range)?.0, version))
+			}
+			_ => None,
+		}
+	}
+}
+
+/// Splits a range like `^1.2.0` into its operator and version
+fn split_range(range: &str) -> Option<(&str, Version)> {
+	let range = range.trim();
+	let version = range.trim_start_matches(['^', '~', '=']);
+	let operator = &range[..range.len() - version.len()];
+	if !["", "^", "~", "="].contains(&operator) {
+		return None;
+	}
+	Some((operator, Version::from_str(version).ok()?))
+}
+
+/// `org/repo`, which npm treats as a GitHub repository
+fn is_github_shorthand(s: &str) -> bool {
+	let Some((org, repo)) = s.split_once('/') else {
+		return false;
+	};
+	let valid = |part: &str| {
+		!part.is_empty()
+			&& !part.starts_with('.')
+			&& part
+				.chars()
+				.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+	};
+	valid(org) && valid(repo)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(s: &str) -> DependencySpecifier {
+		DependencySpecifier::from_str(s).unwrap()
+	}
+
+	fn git(url: &str, reference: GitReference) -> DependencySpecifier {
+		DependencySpecifier::Git {
+			url: url.to_string(),
+			reference,
+		}
+	}
+
+	#[test]
+	fn classifies() {
+		assert_eq!(
+			parse("^1.0.0"),
+			DependencySpecifier::Range("^1.0.0".to_string())
+		);
+		assert_eq!(
+			parse("latest"),
+			DependencySpecifier::Range("latest".to_string())
+		);
+		assert_eq!(
+			parse("workspace:*"),
+			DependencySpecifier::Workspace("workspace:*".to_string())
+		);
+		assert_eq!(
+			parse("npm:real-name@^2"),
+			DependencySpecifier::Alias {
+				name: "real-name".to_string(),
+				range: "^2".to_string()
+			}
+		);
+		assert_eq!(
+			parse("npm:@scope/real"),
+			DependencySpecifier::Alias {
+				name: "@scope/real".to_string(),
+				range: "*".to_string()
+			}
+		);
+		assert_eq!(
+			parse("github:org/repo#v1.2.3"),
+			git(
+				"https://github.com/org/repo.git",
+				GitReference::Committish("v1.2.3".to_string())
+			)
+		);
+		assert_eq!(
+			parse("org/repo"),
+			git("https://github.com/org/repo.git", GitReference::Head)
+		);
+		assert_eq!(
+			parse("git+ssh://git@github.com/org/repo.git#semver:^1"),
+			git(
+				"ssh://git@github.com/org/repo.git",
+				GitReference::Semver("^1".to_string())
+			)
+		);
+		assert_eq!(
+			parse("git+file:///tmp/repo.git"),
+			git("file:///tmp/repo.git", GitReference::Head)
+		);
+		assert_eq!(
+			parse("https://example.com/pkg.tgz"),
+			DependencySpecifier::Tarball("https://example.com/pkg.tgz".to_string())
+		);
+		assert_eq!(
+			parse("file:../local-pkg"),
+			DependencySpecifier::File(PathBuf::from("../local-pkg"))
+		);
+		assert_eq!(
+			parse("../local-pkg"),
+			DependencySpecifier::File(PathBuf::from("../local-pkg"))
+		);
+		assert_eq!(
+			parse("link:../sibling"),
+			DependencySpecifier::Link(PathBuf::from("../sibling"))
+		);
+	}
+}
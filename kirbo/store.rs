@@ -0,0 +1,157 @@
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use sha2::Digest;
+use sha2::Sha512;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::CONFIG;
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// The on-disk cache of every package tarball we've downloaded, laid out as
+/// `<cache>/<name>/<version>.tgz`, with anything from outside of the registry in
+/// `<cache>/_sources/`
+#[derive(Clone, Debug)]
+pub struct Store {
+	dir: PathBuf,
+}
+
+impl Default for Store {
+	fn default() -> Self {
+		Store::new(&CONFIG.cache_dir)
+	}
+}
+
+impl Store {
+	pub fn new(dir: &Path) -> Self {
+		Store {
+			dir: dir.to_path_buf(),
+		}
+	}
+
+	pub fn tarball_path(&self, name: &str, version: &str) -> PathBuf {
+		self.dir.join(name).join(format!("{}.tgz", version))
+	}
+
+	/// Where a tarball that didn't come from the registry is kept, keyed by something
+	/// that identifies its contents (like a commit or an integrity hash)
+	pub fn source_path(&self, id: &str) -> PathBuf {
+		let digest = format!("{:x}", Sha512::digest(id.as_bytes()));
+		self
+			.dir
+			.join("_sources")
+			.join(format!("{}.tgz", &digest[..40]))
+	}
+
+	/// The path to a tarball, if it has already been downloaded
+	pub fn get(&self, name: &str, version: &str) -> Option<PathBuf> {
+		Some(self.tarball_path(name, version)).filter(|path| path.is_file())
+	}
+
+	/// Downloads a tarball into the store, unless it's already there. If `sha512` is
+	/// provided (base64, as it appears in a lock), the tarball must match it, even if
+	/// it was already in the store. Tarballs without an integrity to check are always
+	/// downloaded again, since the store is shared by every project on the machine.
+	pub async fn fetch(
+		&self,
+		name: &str,
+		version: &str,
+		url: &str,
+		sha512: Option<&str>,
+	) -> anyhow::Result<PathBuf> {
+		let sha512 = sha512.filter(|it| !it.is_empty());
+
+		// Whatever is in the store might have been tampered with, cut short, or be a
+		// package with the same name and version from another registry. Any of those
+		// get replaced by a fresh download.
+		if let (Some(path), Some(expected)) = (self.get(name, version), sha512) {
+			if fs::read(&path).is_ok_and(|bytes| sha512_base64(&bytes) == expected) {
+				return Ok(path);
+			}
+		}
+
+		let response = CLIENT.get(url).send().await?.error_for_status()?;
+		let bytes = response.bytes().await?;
+
+		if let Some(expected) = sha512 {
+			let actual = sha512_base64(&bytes);
+			if actual != expected {
+				return Err(anyhow!(
+					"integrity check failed for {}@{}: expected sha512 {}, got {}",
+					name,
+					version,
+					expected,
+					actual
+				));
+			}
+		}
+
+		let path = self.tarball_path(name, version);
+		fs::create_dir_all(path.parent().unwrap())?;
+		// Write to a temporary file first, so that an interrupted download never looks
+		// like a complete one.
+		let partial = path.with_extension("tgz.partial");
+		fs::write(&partial, &bytes)?;
+		fs::rename(&partial, &path)?;
+
+		Ok(path)
+	}
+}
+
+/// The base64 encoded sha512 digest of some bytes, in the same form used by a lock
+pub fn sha512_base64(bytes: &[u8]) -> String {
+	BASE64.encode(Sha512::digest(bytes))
+}
+
+/// The total size of every file in a tarball once it's been extracted
+pub fn unpacked_size(tarball: &Path) -> io::Result<u64> {
+	let mut archive = tar::Archive::new(GzDecoder::new(File::open(tarball)?));
+	let mut size = 0;
+	for entry in archive.entries()? {
+		size += entry?.header().size()?;
+	}
+	Ok(size)
+}
+
+/// Formats a number of bytes for people to read, like `1.2 kB`
+pub fn format_size(bytes: u64) -> String {
+	let units = ["B", "kB", "MB", "GB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1000.0 && unit < units.len() - 1 {
+		size /= 1000.0;
+		unit += 1;
+	}
+
+	match unit {
+		0 => format!("{} {}", bytes, units[0]),
+		_ => format!("{:.1} {}", size, units[unit]),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn digests() {
+		assert_eq!(
+			sha512_base64(b"kirbo"),
+			"v5Lh53JLSrQqyX+4MALXl8j+lfrhfwtZ6vmld2TcLL46hHrlBhRGxN+3Fc25qCNJz+UgDIyWGhxMoJfcInVXnQ=="
+		);
+	}
+
+	#[test]
+	fn sizes() {
+		assert_eq!(format_size(12), "12 B");
+		assert_eq!(format_size(1234), "1.2 kB");
+		assert_eq!(format_size(5_600_000), "5.6 MB");
+	}
+}
@@ -0,0 +1,83 @@
+use anyhow::anyhow;
+use kirbo_workspace::Package;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::process::ExitStatus;
+
+use crate::commands::install;
+use crate::scripts;
+
+/// Installs `dependencies` into `dir` as a package of their own, which has nothing to
+/// do with whatever project we're in. Used to run packages that aren't dependencies,
+/// like starters for `kirbo create` and
+/// packages for `kirbo dlx`.
+pub async fn install(dir: &Path, dependencies: &BTreeMap<String, String>) -> anyhow::Result<()> {
+	fs::create_dir_all(dir)?;
+	let package_json = serde_json::json!({
+		"name": "kirbo-temporary",
+		"private": true,
+		"dependencies": dependencies,
+	});
+	fs::write(
+		dir.join("package.json"),
+		serde_json::to_string_pretty(&package_json)?,
+	)?;
+
+	let options = install::options::Options {
+		quiet: true,
+		..Default::default()
+	};
+	install::main::install(dir, options).await
+}
+
+/// The executable to run from an installed package when nothing more specific was
+/// asked for. That's its only executable, or the one named after it.
+pub fn default_bin(dir: &Path, name: &str) -> anyhow::Result<String> {
+	let text = fs::read_to_string(dir.join("node_modules").join(name).join("package.json"))?;
+	let package = serde_json::from_str::<Package>(&text)?;
+	let commands = package
+		.bin
+		.as_ref()
+		.map(|bin| bin.commands(name))
+		.unwrap_or_default();
+
+	let basename = name.rsplit('/').next().unwrap_or(name);
+	match &*commands {
+		[] => Err(anyhow!("{} doesn't have any executables", name)),
+		[(command, _)] => Ok(command.to_string()),
+		_ => commands
+			.iter()
+			.find(|(command, _)| *command == basename)
+			.map(|(command, _)| command.to_string())
+			.ok_or_else(|| {
+				anyhow!(
+					"{} has more than one executable ({}), so you'll need to pick one",
+					name,
+					commands
+						.iter()
+						.map(|(command, _)| *command)
+						.collect::<Vec<_>>()
+						.join(", ")
+				)
+			}),
+	}
+}
+
+/// Runs an executable installed in `dir` from `cwd`, with everything else that was
+/// installed alongside it on the `PATH`
+pub fn run_bin(
+	dir: &Path,
+	command: &str,
+	args: &[String],
+	cwd: &Path,
+) -> anyhow::Result<ExitStatus> {
+	let bin = dir.join("node_modules/.bin").join(command);
+	Command::new(&bin)
+		.args(args)
+		.current_dir(cwd)
+		.env("PATH", scripts::path_env(dir))
+		.status()
+		.map_err(|err| anyhow!("failed to run {}: {}", command, err))
+}
@@ -1,3 +1,6 @@
+use kirbo::lock::KirboLock;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
 
 mod testing;
@@ -27,3 +30,1834 @@ fn run_prettier() {
 
 	assert!(result.status.success());
 }
+
+#[test]
+fn install_workspace() {
+	testing::before();
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/40-workspace/packages/app/")
+		.arg("install")
+		.output()
+		.unwrap();
+
+	assert!(result.status.success());
+	for name in ["app", "ui", "utils"] {
+		assert_eq!(
+			fs::read_link(format!(
+				"./tests/testdata/40-workspace/node_modules/{}",
+				name
+			))
+			.unwrap(),
+			Path::new("../packages/").join(name)
+		);
+	}
+}
+
+#[test]
+fn install_shadowed_member() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("shadowed-member-cache");
+	let dir = testing::temp_dir("shadowed-member");
+	testing::copy_dir(Path::new("./tests/testdata/64-shadowed-member/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(
+		result.status.success(),
+		"{}",
+		String::from_utf8_lossy(&result.stderr)
+	);
+
+	// The root asked for a version of `shared` that only the registry has, so it
+	// doesn't get the workspace package of the same name
+	let shared = dir.join("node_modules/shared");
+	assert!(!fs::symlink_metadata(&shared)
+		.unwrap()
+		.file_type()
+		.is_symlink());
+	assert_eq!(
+		fs::read_to_string(shared.join("index.js")).unwrap(),
+		"module.exports = \"shared@1.1.0\";\n"
+	);
+	assert_eq!(
+		fs::read_link(dir.join("node_modules/app")).unwrap(),
+		Path::new("../packages/app")
+	);
+	// ...but app does, so that gets linked beneath app instead
+	assert_eq!(
+		fs::read_link(dir.join("packages/app/node_modules/shared")).unwrap(),
+		Path::new("../../shared")
+	);
+
+	let lock: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	assert_eq!(lock.packages["shared@^1.0.0"].version, "1.1.0");
+	assert_eq!(
+		lock.packages["shared@workspace:*"].resolved,
+		"workspace:packages/shared"
+	);
+}
+
+#[test]
+fn workspaces_foreach() {
+	testing::before();
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/41-foreach/")
+		.args(["workspaces", "foreach", "--parallel", "2", "build"])
+		.output()
+		.unwrap();
+
+	assert!(!result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	let built_a = stdout.find("built a").unwrap();
+	let built_b = stdout.find("built b").unwrap();
+	assert!(built_a < built_b);
+	assert!(!stdout.contains("built d"));
+	assert!(stdout.contains("skipped because c failed"));
+	assert!(String::from_utf8_lossy(&result.stderr).contains("broken c"));
+}
+
+#[test]
+fn run_recursive_with_filters() {
+	testing::before();
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/41-foreach/packages/a/")
+		.args([
+			"run",
+			"-r",
+			"--exclude",
+			"packages/c",
+			"--exclude",
+			"d",
+			"build",
+		])
+		.output()
+		.unwrap();
+
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("built a"));
+	assert!(stdout.contains("built b"));
+}
+
+#[test]
+fn workspaces_foreach_since() {
+	testing::before();
+
+	let dir = testing::temp_dir("since");
+	testing::copy_dir(Path::new("./tests/testdata/42-since/"), &dir);
+
+	let git = |args: &[&str]| {
+		let status = Command::new("git")
+			.current_dir(&dir)
+			.args([
+				"-c",
+				"user.name=kirbo",
+				"-c",
+				"user.email=kirbo@example.com",
+			])
+			.args(args)
+			.output()
+			.unwrap()
+			.status;
+		assert!(status.success());
+	};
+	let foreach_since = || {
+		let result = Command::new(&*EXE)
+			.current_dir(&dir)
+			.args(["workspaces", "foreach", "--since", "HEAD", "build"])
+			.output()
+			.unwrap();
+		assert!(result.status.success());
+		String::from_utf8_lossy(&result.stdout).into_owned()
+	};
+
+	git(&["init", "-q"]);
+	git(&["add", "-A"]);
+	git(&["commit", "-q", "-m", "init"]);
+
+	// Changing a package should also run its dependents
+	fs::write(dir.join("packages/a/index.js"), "module.exports = 'A';").unwrap();
+	let stdout = foreach_since();
+	assert!(stdout.contains("built a"));
+	assert!(stdout.contains("built b"));
+	assert!(!stdout.contains("built c"));
+	assert!(!stdout.contains("built d"));
+
+	git(&["commit", "-q", "-am", "a"]);
+	let stdout = foreach_since();
+	assert!(!stdout.contains("built"));
+
+	// Changing the lock should run anything depending on what changed in it
+	let lock = fs::read_to_string(dir.join("Kirbo.lock")).unwrap();
+	fs::write(
+		dir.join("Kirbo.lock"),
+		lock.replace("1.0.0.tgz", "1.0.1.tgz"),
+	)
+	.unwrap();
+	let stdout = foreach_since();
+	assert!(!stdout.contains("built a"));
+	assert!(!stdout.contains("built b"));
+	assert!(!stdout.contains("built c"));
+	assert!(stdout.contains("built d"));
+}
+
+#[test]
+fn why() {
+	testing::before();
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/43-why/packages/app/")
+		.args(["why", "scheduler"])
+		.output()
+		.unwrap();
+
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("0.23.0"));
+	assert!(stdout.contains("react-dom@^18.2.0 → scheduler@^0.23.0"));
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/43-why/")
+		.args(["why", "loose-envify@^1.0.0", "--json"])
+		.output()
+		.unwrap();
+
+	assert!(result.status.success());
+	let explanations = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert_eq!(explanations[0]["version"], "1.4.0");
+	assert_eq!(explanations[0]["chains"].as_array().unwrap().len(), 5);
+	assert_eq!(explanations[0]["chains"][0]["root"]["name"], "app");
+
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/43-why/")
+		.args(["why", "loose-envify@^2.0.0"])
+		.output()
+		.unwrap();
+
+	assert!(!result.status.success());
+
+	// JSON output still fails, but stays parseable
+	let result = Command::new(&*EXE)
+		.current_dir("./tests/testdata/43-why/")
+		.args(["why", "loose-envify@^2.0.0", "--json"])
+		.output()
+		.unwrap();
+
+	assert_eq!(result.status.code(), Some(1));
+	let explanations = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert!(explanations.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn insight() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("insight");
+	let cache = testing::temp_dir("insight-cache");
+	testing::copy_dir(Path::new("./tests/testdata/44-insight/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(cache.join("shared/2.0.0.tgz").is_file());
+
+	// A tarball in the store that doesn't match its integrity gets downloaded again
+	let original = fs::read(cache.join("shared/2.0.0.tgz")).unwrap();
+	fs::write(cache.join("shared/2.0.0.tgz"), b"tampered").unwrap();
+	fs::remove_dir_all(dir.join("node_modules")).unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(fs::read(cache.join("shared/2.0.0.tgz")).unwrap(), original);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["insight", "--json"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let report = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+
+	let left = &report["dependencies"][0];
+	assert_eq!(left["name"], "left");
+	assert_eq!(left["transitivePackages"], 1);
+	assert_eq!(left["missingFromStore"], 0);
+	assert!(left["tarballSize"].as_u64().unwrap() > 0);
+	assert!(left["unpackedSize"].as_u64().unwrap() > 0);
+
+	let shared = &report["duplicates"][0];
+	assert_eq!(shared["name"], "shared");
+	assert_eq!(shared["versions"][0]["version"], "1.1.0");
+	assert_eq!(shared["versions"][0]["requestedBy"][0], "left@1.0.0");
+	assert_eq!(shared["versions"][1]["version"], "2.0.0");
+	assert_eq!(
+		shared["suggestions"][0],
+		serde_json::json!({ "requester": "left@1.0.0", "from": "^1.0.0", "to": "^2.0.0" })
+	);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("insight")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("bump shared@^1.0.0 to ^2.0.0 in left@1.0.0"));
+}
+
+#[test]
+fn install_firewall() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("firewall");
+	let cache = testing::temp_dir("firewall-cache");
+	testing::copy_dir(Path::new("./tests/testdata/45-firewall/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("substituted by kirbo_firewall"));
+	assert!(stdout.contains("crossenv@0.0.0 (malicious)"));
+	assert!(stdout.contains("left-pad@1.3.0 (deprecated)"));
+
+	let lock = fs::read_to_string(dir.join("Kirbo.lock")).unwrap();
+	assert!(lock.contains("resolved: firewall:left-pad@1.3.0"));
+	assert!(lock.contains("resolved: firewall:is-negative-zero@2.0.3"));
+	// Aliases can't be used to sneak past the firewall
+	let packages = serde_yaml::from_str::<KirboLock>(&lock).unwrap().packages;
+	assert_eq!(
+		packages["sneaky@npm:crossenv@^7.0.0"].resolved,
+		"firewall:crossenv@0.0.0"
+	);
+	assert!(lock.contains(&format!(
+		"resolved: {}object-assign/-/4.1.1.tgz",
+		registry.url
+	)));
+	assert!(!cache.join("left-pad").exists());
+
+	let result = Command::new("node")
+		.current_dir(&dir)
+		.args([
+			"-e",
+			"process.exit(require('left-pad')(5, 3, 0) === '005' ? 0 : 1)",
+		])
+		.status()
+		.unwrap();
+	assert!(result.success());
+
+	let result = Command::new("node")
+		.current_dir(&dir)
+		.args(["-e", "require('crossenv')"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+
+	let result = Command::new("node")
+		.current_dir(&dir)
+		.args(["-e", "require('sneaky')"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+}
+
+#[test]
+fn install_lifecycle_scripts() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("scripts");
+	let cache = testing::temp_dir("scripts-cache");
+	testing::copy_dir(Path::new("./tests/testdata/46-scripts/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.env("KIRBO_AUTH_TOKEN", "hunter2")
+		.env("NPM_TOKEN", "hunter2")
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("skipped lifecycle scripts"));
+	assert!(stdout.contains("sketchy@1.0.0 (postinstall)"));
+	// Aliases don't get to use the allowance of whatever name they're installed as
+	assert!(stdout.contains("trusted@1.0.0 (postinstall) as \"sketchy\""));
+
+	assert!(dir.join("node_modules/sketchy/index.js").is_file());
+	assert!(!dir.join("node_modules/sketchy/ran.txt").exists());
+	assert!(!dir.join("node_modules/trusted/ran.txt").exists());
+
+	let built = fs::read_to_string(dir.join("node_modules/builder/built.json")).unwrap();
+	let built = serde_json::from_str::<serde_json::Value>(&built).unwrap();
+	assert_eq!(built["escaped"], false);
+	assert_eq!(built["network"], false);
+	assert_eq!(built["package"], "builder");
+	assert_eq!(built["leaked"], serde_json::json!([]));
+	assert!(!dir.join("escaped.txt").exists());
+
+	// Tarballs are only allowed by their exact url
+	let tarball = format!("{}sketchy/-/1.0.0.tgz", registry.url);
+	for (allowed, ran) in [("tarred", false), (tarball.as_str(), true)] {
+		fs::write(
+			dir.join("package.json"),
+			serde_json::json!({
+				"name": "scripts",
+				"dependencies": { "tarred": tarball },
+				"kirbo": { "allowScripts": [allowed] },
+			})
+			.to_string(),
+		)
+		.unwrap();
+		let result = registry
+			.command(&cache)
+			.current_dir(&dir)
+			.arg("install")
+			.output()
+			.unwrap();
+		assert!(result.status.success());
+		assert_eq!(dir.join("node_modules/tarred/ran.txt").exists(), ran);
+	}
+}
+
+#[test]
+fn install_optional_platforms() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("optional-cache");
+
+	for (args, installed) in [
+		(&["--os", "darwin", "--cpu=arm64"][..], "darwin-arm64"),
+		(
+			&["--os=linux", "--cpu", "x64", "--libc=musl"][..],
+			"linux-x64-musl",
+		),
+	] {
+		let dir = testing::temp_dir("optional");
+		testing::copy_dir(Path::new("./tests/testdata/47-optional/"), &dir);
+
+		let result = registry
+			.command(&cache)
+			.current_dir(&dir)
+			.arg("install")
+			.args(args)
+			.output()
+			.unwrap();
+		assert!(result.status.success());
+
+		let lock = fs::read_to_string(dir.join("Kirbo.lock")).unwrap();
+		for name in [
+			"broken",
+			"darwin-arm64",
+			"linux-x64",
+			"linux-x64-musl",
+			"missing",
+		] {
+			assert_eq!(
+				dir.join("node_modules/@native").join(name).exists(),
+				name == installed
+			);
+			assert_eq!(
+				lock.contains(&format!("'@native/{}@1.0.0':", name)),
+				name == installed,
+			);
+		}
+		assert!(lock.contains("optionalDependencies"));
+		// A skipped optional dependency leaves nothing behind, including the overrides
+		// which were applied to its own dependencies
+		assert!(!lock.contains("host@^1.0.0"));
+	}
+}
+
+#[test]
+fn install_peer_dependencies() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("peers-cache");
+
+	// Nobody provides `host`, so it should be installed for `plugin`
+	let dir = testing::temp_dir("peers");
+	testing::copy_dir(Path::new("./tests/testdata/48-peers/"), &dir);
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(
+		fs::read_to_string(dir.join("node_modules/host/package.json"))
+			.unwrap()
+			.contains("\"2.0.0\"")
+	);
+	assert!(!dir.join("node_modules/extra").exists());
+	let lock = fs::read_to_string(dir.join("Kirbo.lock")).unwrap();
+	assert!(lock.contains("peerDependencies"));
+
+	// The root provides a version of `host` that `plugin` can't use
+	let dir = testing::temp_dir("peer-conflict");
+	testing::copy_dir(Path::new("./tests/testdata/49-peer-conflict/"), &dir);
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("plugin@1.0.0"));
+	assert!(stdout.contains("but peer-conflict provides host@"));
+
+	let dir = testing::temp_dir("peer-conflict-strict");
+	testing::copy_dir(Path::new("./tests/testdata/49-peer-conflict/"), &dir);
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["install", "--strict-peer-deps"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(!dir.join("node_modules").exists());
+}
+
+#[test]
+fn install_overrides() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("overrides-cache");
+
+	// npm style, nested beneath the package it applies to
+	let dir = testing::temp_dir("overrides");
+	testing::copy_dir(Path::new("./tests/testdata/50-overrides/"), &dir);
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let lock: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	assert!(lock.packages["left@^1.0.0"]
+		.dependencies
+		.contains("shared@1.0.0"));
+	assert_eq!(lock.packages["shared@1.0.0"].version, "1.0.0");
+	assert!(lock.packages["shared@1.0.0"]
+		.overrides
+		.contains("shared@^1.0.0"));
+	assert!(!lock.packages.contains_key("shared@^1.0.0"));
+	assert_eq!(lock.packages["shared@^2.0.0"].version, "2.0.0");
+
+	// yarn style, as a path
+	let dir = testing::temp_dir("resolutions");
+	testing::copy_dir(Path::new("./tests/testdata/51-resolutions/"), &dir);
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let lock: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	assert!(lock.packages["right@^1.0.0"]
+		.dependencies
+		.contains("shared@1.1.0"));
+	assert!(lock.packages["shared@1.1.0"]
+		.overrides
+		.contains("shared@^2.0.0"));
+	assert_eq!(lock.packages["shared@^1.0.0"].version, "1.1.0");
+}
+
+#[test]
+fn install_sources() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("sources-cache");
+	let dir = testing::temp_dir("sources");
+	testing::copy_dir(Path::new("./tests/testdata/52-sources/"), &dir);
+
+	// Turn the git package into a tagged repository, and clone it somewhere else so that
+	// it isn't in the workspace
+	let git = |args: &[&str]| {
+		let status = Command::new("git")
+			.current_dir(dir.join("git-package"))
+			.args([
+				"-c",
+				"user.name=kirbo",
+				"-c",
+				"user.email=kirbo@example.com",
+			])
+			.args(args)
+			.status()
+			.unwrap();
+		assert!(status.success());
+	};
+	git(&["init", "--quiet"]);
+	git(&["add", "."]);
+	git(&["commit", "--quiet", "-m", "v1.2.0"]);
+	git(&["tag", "v1.2.0"]);
+	let repo = testing::temp_dir("sources-repo.git");
+	fs::remove_dir_all(&repo).unwrap();
+	git(&["clone", "--quiet", "--bare", ".", &repo.to_string_lossy()]);
+	fs::remove_dir_all(dir.join("git-package")).unwrap();
+	fs::write(
+		dir.join("packed.tgz"),
+		testing::pack(Path::new("./tests/testdata/registry/left/1.0.0")),
+	)
+	.unwrap();
+
+	fs::write(
+		dir.join("package.json"),
+		format!(
+			r#"{{
+				"name": "sources",
+				"dependencies": {{
+					"git-package": "git+file://{}#semver:^1.0.0",
+					"tarred": "{}right/-/1.0.0.tgz",
+					"local": "file:./local",
+					"linked": "link:./linked",
+					"lefty": "npm:left@^1.0.0",
+					"packed": "file:./packed.tgz"
+				}}
+			}}"#,
+			repo.display(),
+			registry.url
+		),
+	)
+	.unwrap();
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(
+		result.status.success(),
+		"{}",
+		String::from_utf8_lossy(&result.stdout)
+	);
+
+	let package_json = |name: &str| {
+		fs::read_to_string(dir.join("node_modules").join(name).join("package.json")).unwrap()
+	};
+	assert!(package_json("git-package").contains("1.2.0"));
+	assert!(package_json("tarred").contains("\"right\""));
+	assert!(package_json("local").contains("\"local\""));
+	assert!(package_json("lefty").contains("\"left\""));
+	assert!(fs::symlink_metadata(dir.join("node_modules/local"))
+		.unwrap()
+		.file_type()
+		.is_symlink());
+	assert!(fs::symlink_metadata(dir.join("node_modules/linked"))
+		.unwrap()
+		.file_type()
+		.is_symlink());
+	// `local` brings in left, but `linked` doesn't bring in right
+	assert!(dir.join("node_modules/left").exists());
+	assert!(!dir.join("node_modules/right").exists());
+
+	let lock: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	let commit = &lock.packages[&format!("git-package@git+file://{}#semver:^1.0.0", repo.display())];
+	assert!(commit
+		.resolved
+		.starts_with(&format!("git+file://{}#", repo.display())));
+	assert_eq!(commit.resolved.rsplit('#').next().unwrap().len(), 40);
+	assert!(
+		!lock.packages[&format!("tarred@{}right/-/1.0.0.tgz", registry.url)]
+			.sha512
+			.is_empty()
+	);
+	assert_eq!(lock.packages["local@file:./local"].resolved, "file:local");
+	assert_eq!(
+		lock.packages["linked@link:./linked"].resolved,
+		"link:linked"
+	);
+	assert_eq!(lock.packages["lefty@npm:left@^1.0.0"].version, "1.0.0");
+	assert!(!commit.sha512.is_empty());
+
+	// Releasing a newer version upstream doesn't change what's locked
+	let upstream = testing::temp_dir("sources-upstream");
+	fs::remove_dir_all(&upstream).unwrap();
+	let status = Command::new("git")
+		.args(["clone", "--quiet", &repo.to_string_lossy()])
+		.arg(&upstream)
+		.status()
+		.unwrap();
+	assert!(status.success());
+	let manifest = upstream.join("package.json");
+	fs::write(
+		&manifest,
+		fs::read_to_string(&manifest)
+			.unwrap()
+			.replace("1.2.0", "1.3.0"),
+	)
+	.unwrap();
+	for args in [
+		&["commit", "--quiet", "-am", "v1.3.0"][..],
+		&["tag", "v1.3.0"],
+		&["push", "--quiet", "--tags", "origin", "HEAD"],
+	] {
+		let status = Command::new("git")
+			.current_dir(&upstream)
+			.args([
+				"-c",
+				"user.name=kirbo",
+				"-c",
+				"user.email=kirbo@example.com",
+			])
+			.args(args)
+			.status()
+			.unwrap();
+		assert!(status.success());
+	}
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(package_json("git-package").contains("1.2.0"));
+	let relocked: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	assert_eq!(
+		relocked.packages[&format!("git-package@git+file://{}#semver:^1.0.0", repo.display())],
+		*commit
+	);
+
+	// A locked commit has to pack into the same tarball it did before
+	let lock_text = fs::read_to_string(dir.join("Kirbo.lock")).unwrap();
+	fs::write(
+		dir.join("Kirbo.lock"),
+		lock_text.replace(&commit.sha512, &"A".repeat(commit.sha512.len())),
+	)
+	.unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("integrity check failed"));
+
+	// So does a tarball, whether it's downloaded or on disk
+	let tarred = &lock.packages[&format!("tarred@{}right/-/1.0.0.tgz", registry.url)];
+	fs::write(
+		dir.join("Kirbo.lock"),
+		lock_text.replace(&tarred.sha512, &"A".repeat(tarred.sha512.len())),
+	)
+	.unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains(&format!(
+		"integrity check failed for {}right/-/1.0.0.tgz",
+		registry.url
+	)));
+
+	fs::write(dir.join("Kirbo.lock"), &lock_text).unwrap();
+	fs::write(
+		dir.join("packed.tgz"),
+		testing::pack(Path::new("./tests/testdata/registry/right/1.0.0")),
+	)
+	.unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("integrity check failed"));
+	assert!(package_json("packed").contains("\"left\""));
+}
+
+#[test]
+fn install_invalid_names() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let cache = testing::temp_dir("invalid-names-cache");
+	let dir = testing::temp_dir("invalid-names");
+	let escaped = dir.join("../escaped");
+	let _ = fs::remove_dir_all(&escaped);
+
+	// `escape` depends on `"../../escaped": "npm:left@^1.0.0"`, which would be
+	// installed outside of node_modules
+	fs::write(
+		dir.join("package.json"),
+		r#"{ "name": "invalid-names", "dependencies": { "escape": "^1.0.0" } }"#,
+	)
+	.unwrap();
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains(
+		"escape@1.0.0 depends on ../../escaped@npm:left@^1.0.0, but \"../../escaped\" isn't a valid package name"
+	));
+	assert!(!escaped.exists());
+}
+
+#[test]
+fn outdated() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("outdated");
+	let cache = testing::temp_dir("outdated-cache");
+	testing::copy_dir(Path::new("./tests/testdata/53-outdated/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+
+	// Pretend that an older version of shared was locked
+	let mut lock: KirboLock =
+		serde_yaml::from_str(&fs::read_to_string(dir.join("Kirbo.lock")).unwrap()).unwrap();
+	lock.packages.get_mut("shared@^1.0.0").unwrap().version = "1.0.0".to_string();
+	lock.write(&dir).unwrap();
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["outdated", "--json"])
+		.output()
+		.unwrap();
+	assert_eq!(result.status.code(), Some(1));
+	assert!(!String::from_utf8_lossy(&result.stderr).contains("Error"));
+	let report = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert_eq!(
+		report,
+		serde_json::json!([{
+			"name": "shared",
+			"range": "^1.0.0",
+			"dependent": "outdated",
+			"current": "1.0.0",
+			"wanted": "1.1.0",
+			"latest": "2.0.0",
+			"major": true,
+		}])
+	);
+
+	// Once shared is removed, everything left is up to date
+	fs::write(
+		dir.join("package.json"),
+		r#"{ "name": "outdated", "dependencies": { "left": "^1.0.0" } }"#,
+	)
+	.unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("outdated")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(String::from_utf8_lossy(&result.stdout).contains("everything is up to date"));
+}
+
+#[test]
+fn upgrade() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("upgrade");
+	let cache = testing::temp_dir("upgrade-cache");
+	testing::copy_dir(Path::new("./tests/testdata/54-upgrade/"), &dir);
+	let original = fs::read_to_string(dir.join("package.json")).unwrap();
+
+	// By default, ranges only move as far as they already allow
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("upgrade")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(
+		fs::read_to_string(dir.join("package.json")).unwrap(),
+		original.replace("\"^1.0.0\"", "\"^1.1.0\"")
+	);
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert_eq!(lock.packages["shared@^1.1.0"].version, "1.1.0");
+
+	// With --latest, they can cross major versions
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["upgrade", "--latest", "shared"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(
+		fs::read_to_string(dir.join("package.json")).unwrap(),
+		original.replace("\"^1.0.0\"", "\"^2.0.0\"")
+	);
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert_eq!(lock.packages["shared@^2.0.0"].version, "2.0.0");
+	assert_eq!(lock.packages["left@~1.0.0"].version, "1.0.0");
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["upgrade", "nope"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+}
+
+#[test]
+fn dedupe() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("dedupe");
+	let cache = testing::temp_dir("dedupe-cache");
+	testing::copy_dir(Path::new("./tests/testdata/55-dedupe/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert_eq!(lock.packages["shared@^1.0.0"].version, "1.1.0");
+	assert_eq!(lock.packages["shared@~1.0.0"].version, "1.0.0");
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["dedupe", "--check"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert_eq!(KirboLock::read(&dir).unwrap().unwrap(), lock);
+
+	// 1.0.0 satisfies both ranges, so it's the only version that needs to be installed
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("dedupe")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert_eq!(lock.packages["shared@^1.0.0"].version, "1.0.0");
+	assert!(
+		fs::read_to_string(dir.join("node_modules/shared/package.json"))
+			.unwrap()
+			.contains("\"1.0.0\"")
+	);
+
+	// Installing again sticks with what's locked
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(KirboLock::read(&dir).unwrap().unwrap(), lock);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["dedupe", "--check"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+}
+
+#[test]
+fn audit() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("audit");
+	let cache = testing::temp_dir("audit-cache");
+	let advisories = Path::new("./tests/testdata/advisories/osv/")
+		.canonicalize()
+		.unwrap();
+	testing::copy_dir(Path::new("./tests/testdata/56-audit/"), &dir);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.arg("install")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+
+	// Pretend that left's copy of shared was locked before the fix was released
+	let mut lock = KirboLock::read(&dir).unwrap().unwrap();
+	lock.packages.get_mut("shared@^1.0.0").unwrap().version = "1.0.0".to_string();
+	lock.write(&dir).unwrap();
+
+	let expected = serde_json::json!([{
+		"name": "shared",
+		"version": "1.0.0",
+		"severity": "high",
+		"advisories": [{
+			"id": "GHSA-kirb-0000-0001",
+			"package": "shared",
+			"title": "Shared state leaks between requests",
+			"severity": "high",
+			"url": "https://github.com/advisories/GHSA-kirb-0000-0001",
+			"vulnerableVersions": "<1.1.0",
+		}],
+		"paths": [
+			{
+				"root": { "name": "audit", "type": "dependencies" },
+				"links": [{ "name": "shared", "range": "~1.0.0", "version": "1.0.0" }],
+			},
+			{
+				"root": { "name": "audit", "type": "dependencies" },
+				"links": [
+					{ "name": "left", "range": "^1.0.0", "version": "1.0.0" },
+					{ "name": "shared", "range": "^1.0.0", "version": "1.0.0" },
+				],
+			},
+		],
+		"fixedIn": "1.1.0",
+		"fixes": [
+			{ "type": "override", "selector": "shared@1.0.0", "range": "^1.1.0" },
+			{
+				"type": "dependency",
+				"dependent": "audit",
+				"field": "dependencies",
+				"name": "shared",
+				"from": "~1.0.0",
+				"to": "~1.1.0",
+			},
+		],
+	}]);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["audit", "--json", "--advisories"])
+		.arg(&advisories)
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	let report = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert_eq!(report, expected);
+
+	// The registry knows about the same vulnerability, and one in a package that
+	// isn't installed
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["audit", "--json"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	let report = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert_eq!(report[0]["advisories"][0]["id"], "1001");
+	assert_eq!(report[0]["fixes"], expected[0]["fixes"]);
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["audit", "--level", "critical"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["audit", "--fix", "--json", "--advisories"])
+		.arg(&advisories)
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	// Installing the fixes doesn't print anything that isn't JSON
+	let report = serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap();
+	assert_eq!(report, expected);
+	let package_json = fs::read_to_string(dir.join("package.json")).unwrap();
+	assert_eq!(
+		package_json,
+		"{\n\t\"name\": \"audit\",\n\t\"dependencies\": {\n\t\t\"left\": \"^1.0.0\",\n\t\t\"shared\": \"~1.1.0\"\n\t},\n\t\"overrides\": {\n\t\t\"shared@1.0.0\": \"^1.1.0\"\n\t}\n}\n"
+	);
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert!(lock
+		.packages
+		.iter()
+		.all(|(descriptor, package)| !descriptor.starts_with("shared@") || package.version == "1.1.0"));
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["audit", "--advisories"])
+		.arg(&advisories)
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(String::from_utf8_lossy(&result.stdout).contains("no known vulnerabilities"));
+}
+
+#[test]
+fn pack() {
+	testing::before();
+	let dir = testing::temp_dir("pack");
+	testing::copy_dir(Path::new("./tests/testdata/57-pack/"), &dir);
+	let tarball = dir.join("kirbo-packed-1.2.3.tgz");
+
+	// Symlinks are never followed, whether they loop or lead somewhere private
+	#[cfg(unix)]
+	{
+		let private = testing::temp_dir("pack-private");
+		fs::write(private.join("secret.txt"), "hunter2").unwrap();
+		std::os::unix::fs::symlink("..", dir.join("lib/loop")).unwrap();
+		std::os::unix::fs::symlink(&private, dir.join("lib/private")).unwrap();
+		std::os::unix::fs::symlink(private.join("secret.txt"), dir.join("lib/secret.txt")).unwrap();
+	}
+
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args(["pack", "--dry-run"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("lib/packed.js"));
+	assert!(!stdout.contains("generated.js"));
+	assert!(!tarball.exists());
+
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.arg("pack")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	// postpack cleans up after prepack
+	assert!(!dir.join("dist/generated.js").exists());
+
+	let bytes = fs::read(&tarball).unwrap();
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains(&format!("sha512-{}", kirbo::store::sha512_base64(&bytes))));
+
+	let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&*bytes));
+	let entries = archive
+		.entries()
+		.unwrap()
+		.map(|entry| {
+			let entry = entry.unwrap();
+			let header = entry.header();
+			assert_eq!(header.mtime().unwrap(), 499162500);
+			(
+				entry.path().unwrap().to_string_lossy().to_string(),
+				header.mode().unwrap(),
+			)
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(
+		entries,
+		[
+			("package/LICENSE", 0o644),
+			("package/README.md", 0o644),
+			("package/bin/cli.js", 0o755),
+			("package/dist/generated.js", 0o644),
+			("package/index.js", 0o644),
+			("package/lib/packed.js", 0o644),
+			("package/package.json", 0o644),
+		]
+		.map(|(path, mode)| (path.to_string(), mode))
+	);
+
+	// Packing again produces exactly the same tarball
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.arg("pack")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(fs::read(&tarball).unwrap(), bytes);
+
+	// Entry points have to be inside of the package
+	let package_json = fs::read_to_string(dir.join("package.json")).unwrap();
+	fs::write(
+		dir.join("package.json"),
+		package_json.replace("\"main\": \"index.js\"", "\"main\": \"../secret.js\""),
+	)
+	.unwrap();
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args(["pack", "--dry-run"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("points outside of the package"));
+}
+
+#[test]
+fn publish() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("publish");
+	let cache = testing::temp_dir("publish-cache");
+	testing::copy_dir(Path::new("./tests/testdata/58-publish/"), &dir);
+
+	// Private packages are never published
+	let result = registry
+		.command(&cache)
+		.current_dir(dir.join("packages/secret"))
+		.env("KIRBO_AUTH_TOKEN", "token")
+		.arg("publish")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("private"));
+
+	// Without a token, the registry turns us away
+	let result = registry
+		.command(&cache)
+		.current_dir(dir.join("packages/core"))
+		.arg("publish")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(registry.published.lock().unwrap().is_empty());
+
+	// ...and the token isn't sent anywhere that the project picked
+	fs::write(
+		dir.join(".kirborc"),
+		format!("registry = \"{}\"\n", registry.url),
+	)
+	.unwrap();
+	let result = registry
+		.command(&cache)
+		.current_dir(dir.join("packages/core"))
+		.env_remove("KIRBO_REGISTRY")
+		.env("KIRBO_AUTH_TOKEN", "token")
+		.arg("publish")
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stdout).contains("not sending auth-token"));
+	assert!(registry.published.lock().unwrap().is_empty());
+	fs::remove_file(dir.join(".kirborc")).unwrap();
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.env("KIRBO_AUTH_TOKEN", "otp-token")
+		.args([
+			"publish",
+			"-r",
+			"--tag",
+			"next",
+			"--access=public",
+			"--otp",
+			"123456",
+		])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("left@1.0.0 (already published)"));
+
+	let published = registry.published.lock().unwrap();
+	let names = published
+		.iter()
+		.map(|document| document["name"].as_str().unwrap())
+		.collect::<Vec<_>>();
+	// core has to be published before cli, which depends on it
+	assert_eq!(names, ["@kirbo/core", "@kirbo/cli"]);
+
+	let cli = &published[1];
+	assert_eq!(cli["dist-tags"], serde_json::json!({ "next": "2.0.0" }));
+	assert_eq!(cli["access"], "public");
+	let version = &cli["versions"]["2.0.0"];
+	assert_eq!(
+		version["dependencies"],
+		serde_json::json!({ "@kirbo/core": "^1.0.0", "shared": "^1.0.0" })
+	);
+	assert_eq!(
+		version["devDependencies"],
+		serde_json::json!({ "@kirbo/core": "1.0.0" })
+	);
+	assert!(dir.join("packages/cli/ready.txt").exists());
+
+	// The attached tarball is the one described by the version's integrity
+	let attachment = &cli["_attachments"]["@kirbo/cli-2.0.0.tgz"];
+	let tarball = base64::Engine::decode(
+		&base64::engine::general_purpose::STANDARD,
+		attachment["data"].as_str().unwrap(),
+	)
+	.unwrap();
+	assert_eq!(
+		version["dist"]["integrity"].as_str().unwrap(),
+		format!("sha512-{}", kirbo::store::sha512_base64(&tarball))
+	);
+	let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&*tarball));
+	let manifest = archive
+		.entries()
+		.unwrap()
+		.map(Result::unwrap)
+		.find(|entry| entry.path().unwrap() == Path::new("package/package.json"))
+		.map(|mut entry| {
+			let mut text = String::new();
+			std::io::Read::read_to_string(&mut entry, &mut text).unwrap();
+			text
+		})
+		.unwrap();
+	assert!(manifest.contains("\"@kirbo/core\": \"^1.0.0\""));
+	assert!(!manifest.contains("workspace:"));
+}
+
+#[test]
+fn version() {
+	testing::before();
+	let dir = testing::temp_dir("version");
+	testing::copy_dir(Path::new("./tests/testdata/59-version/"), &dir);
+	let git = |args: &[&str]| {
+		let output = Command::new("git")
+			.current_dir(&dir)
+			.args(args)
+			.env("GIT_AUTHOR_NAME", "kirbo")
+			.env("GIT_AUTHOR_EMAIL", "kirbo@example.com")
+			.env("GIT_COMMITTER_NAME", "kirbo")
+			.env("GIT_COMMITTER_EMAIL", "kirbo@example.com")
+			.output()
+			.unwrap();
+		assert!(output.status.success());
+		String::from_utf8(output.stdout).unwrap()
+	};
+	git(&["init", "--quiet"]);
+	git(&["add", "."]);
+	git(&["commit", "--quiet", "-m", "initial"]);
+
+	let core = fs::read_to_string(dir.join("packages/core/package.json")).unwrap();
+	let app = fs::read_to_string(dir.join("packages/app/package.json")).unwrap();
+
+	let result = Command::new(&*EXE)
+		.current_dir(dir.join("packages/core"))
+		.args(["version", "minor"])
+		.env("GIT_AUTHOR_NAME", "kirbo")
+		.env("GIT_AUTHOR_EMAIL", "kirbo@example.com")
+		.env("GIT_COMMITTER_NAME", "kirbo")
+		.env("GIT_COMMITTER_EMAIL", "kirbo@example.com")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+
+	// Only the versions change, so the formatting survives
+	assert_eq!(
+		fs::read_to_string(dir.join("packages/core/package.json")).unwrap(),
+		core.replace("\"1.0.0\"", "\"1.1.0\"")
+	);
+	assert_eq!(
+		fs::read_to_string(dir.join("packages/app/package.json")).unwrap(),
+		app.replace("\"^1.0.0\"", "\"^1.1.0\"")
+	);
+	assert_eq!(
+		fs::read_to_string(dir.join("packages/core/version.txt"))
+			.unwrap()
+			.trim(),
+		"1.1.0"
+	);
+	assert_eq!(git(&["tag", "--list"]).trim(), "core@1.1.0");
+	assert_eq!(git(&["log", "-1", "--format=%s"]).trim(), "core@1.1.0");
+	assert_eq!(
+		git(&["status", "--porcelain"]).trim(),
+		"?? packages/core/version.txt"
+	);
+
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args([
+			"version",
+			"prerelease",
+			"--preid",
+			"beta",
+			"--include",
+			"app",
+			"--no-git-tag-version",
+		])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert!(fs::read_to_string(dir.join("packages/app/package.json"))
+		.unwrap()
+		.contains("\"version\": \"0.1.1-beta.0\""));
+	assert_eq!(git(&["tag", "--list"]).trim(), "core@1.1.0");
+
+	// Bumping to the version it's already at is a mistake
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args(["version", "1.0.0"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+}
+
+#[test]
+fn init() {
+	testing::before();
+	let dir = testing::temp_dir("init");
+
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args(["init", "-y"])
+		.env("KIRBO_INIT_LICENSE", "Apache-2.0")
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	let package = serde_json::from_str::<serde_json::Value>(
+		&fs::read_to_string(dir.join("package.json")).unwrap(),
+	)
+	.unwrap();
+	assert_eq!(
+		package,
+		serde_json::json!({
+			"name": "kirbo-test-init",
+			"version": "0.0.0",
+			"main": "index.js",
+			"license": "Apache-2.0",
+			"packageManager": format!("kirbo@{}", env!("CARGO_PKG_VERSION")),
+		})
+	);
+
+	// It won't overwrite an existing package.json
+	let result = Command::new(&*EXE)
+		.current_dir(&dir)
+		.args(["init", "-y"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+}
+
+#[test]
+fn create() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("create");
+	let cache = testing::temp_dir("create-cache");
+
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["create", "kirbo", "my-app"])
+		.output()
+		.unwrap();
+	assert!(result.status.success());
+	assert_eq!(
+		fs::read_to_string(dir.join("my-app/package.json"))
+			.unwrap()
+			.trim(),
+		"{ \"name\": \"my-app\" }"
+	);
+	// Nothing gets installed into the directory we ran it from
+	assert!(!dir.join("node_modules").exists());
+	assert!(!dir.join("Kirbo.lock").exists());
+
+	// A starter that fails gets to decide how kirbo exits
+	let result = registry
+		.command(&cache)
+		.current_dir(&dir)
+		.args(["create", "kirbo", "/proc/kirbo/my-app"])
+		.output()
+		.unwrap();
+	assert!(!result.status.success());
+	assert!(!String::from_utf8_lossy(&result.stderr).contains("Error"));
+}
+
+#[test]
+fn dlx() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("dlx");
+	let cache = testing::temp_dir("dlx-cache");
+
+	let dlx = |args: &[&str]| {
+		let result = registry
+			.command(&cache)
+			.current_dir(&dir)
+			.arg("dlx")
+			.args(args)
+			.output()
+			.unwrap();
+		assert!(result.status.success());
+		String::from_utf8(result.stdout).unwrap()
+	};
+
+	// The install is only an implementation detail, so it stays quiet
+	let first = dlx(&["create-kirbo@^1", "one"]);
+	assert!(!first.contains("using"));
+	assert!(!first.contains("kirbo install"));
+	assert!(!first.contains("├"));
+	assert!(dir.join("one/package.json").exists());
+	// Nothing gets installed into the directory we ran it from
+	assert!(!dir.join("node_modules").exists());
+
+	// The same resolution reuses the install from before
+	let second = dlx(&["create-kirbo", "two"]);
+	assert!(second.contains("using"));
+	assert!(dir.join("two/package.json").exists());
+
+	// Extra packages get an install of their own
+	let third = dlx(&["-p", "left", "-p", "create-kirbo", "create-kirbo", "three"]);
+	assert!(!third.contains("using"));
+	assert!(dir.join("three/package.json").exists());
+	assert_eq!(fs::read_dir(cache.join("dlx")).unwrap().count(), 2);
+}
+
+#[test]
+fn patch() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("patch");
+	let cache = testing::temp_dir("patch-cache");
+	let tmp = testing::temp_dir("patch-tmp");
+	testing::copy_dir(Path::new("./tests/testdata/60-patch/"), &dir);
+	let kirbo = |args: &[&str]| {
+		registry
+			.command(&cache)
+			.current_dir(&dir)
+			.env("TMPDIR", &tmp)
+			.args(args)
+			.output()
+			.unwrap()
+	};
+
+	assert!(kirbo(&["install"]).status.success());
+	assert!(kirbo(&["patch", "left"]).status.success());
+	let edit = tmp.join("kirbo-patch-left@1.0.0");
+	assert_eq!(
+		fs::read_to_string(edit.join("index.js")).unwrap(),
+		"module.exports = \"left@1.0.0\";\n"
+	);
+	fs::write(edit.join("index.js"), "module.exports = \"patched\";\n").unwrap();
+
+	let result = kirbo(&["patch-commit", &edit.to_string_lossy()]);
+	assert!(result.status.success());
+	assert!(!edit.exists());
+	let patch = fs::read_to_string(dir.join("patches/left@1.0.0.patch")).unwrap();
+	assert!(patch.contains("-module.exports = \"left@1.0.0\";\n+module.exports = \"patched\";\n"));
+	assert!(fs::read_to_string(dir.join("package.json"))
+		.unwrap()
+		.contains("\"left@1.0.0\": \"patches/left@1.0.0.patch\""));
+	assert_eq!(
+		fs::read_to_string(dir.join("node_modules/left/index.js")).unwrap(),
+		"module.exports = \"patched\";\n"
+	);
+	let lock = KirboLock::read(&dir).unwrap().unwrap();
+	assert_eq!(
+		lock.packages["left@^1.0.0"].patch,
+		kirbo::store::sha512_base64(patch.as_bytes())
+	);
+
+	// Directories that `kirbo patch` didn't make are left alone
+	let copy = tmp.join("left-copy");
+	testing::copy_dir(&dir.join("node_modules/left"), &copy);
+	fs::write(copy.join("index.js"), "module.exports = \"copied\";\n").unwrap();
+	let result = kirbo(&["patch-commit", &copy.to_string_lossy()]);
+	assert!(result.status.success());
+	assert!(copy.join("index.js").is_file());
+
+	// A patch that no longer matches the package fails the install
+	fs::write(
+		dir.join("patches/left@1.0.0.patch"),
+		patch.replace(
+			"-module.exports = \"left@1.0.0\";",
+			"-module.exports = \"left@0.9.0\";",
+		),
+	)
+	.unwrap();
+	let result = kirbo(&["install"]);
+	assert!(!result.status.success());
+	assert!(
+		String::from_utf8_lossy(&result.stderr).contains("failed to apply patches/left@1.0.0.patch")
+	);
+}
+
+#[test]
+fn engines() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("engines");
+	let cache = testing::temp_dir("engines-cache");
+	let bin = testing::temp_dir("engines-bin");
+	testing::copy_dir(Path::new("./tests/testdata/61-engines/"), &dir);
+
+	// Pretend that node 20 is installed, whatever the real version is
+	fs::write(bin.join("node"), "#!/bin/sh\necho v20.0.0\n").unwrap();
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(bin.join("node"), fs::Permissions::from_mode(0o755)).unwrap();
+	}
+	let path = std::env::join_paths(
+		[bin.clone()]
+			.into_iter()
+			.chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+	)
+	.unwrap();
+	let kirbo = |args: &[&str]| {
+		registry
+			.command(&cache)
+			.current_dir(&dir)
+			.env("PATH", &path)
+			.args(args)
+			.output()
+			.unwrap()
+	};
+
+	let result = kirbo(&["install"]);
+	assert!(result.status.success());
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	assert!(stdout.contains("modern@1.0.0 wants node >=22"));
+	assert!(!stdout.contains("engines wants node"));
+
+	let result = kirbo(&["install", "--engine-strict"]);
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("node 20.0.0 isn't supported"));
+
+	// Projects that belong to another package manager are left alone
+	let package_json = fs::read_to_string(dir.join("package.json")).unwrap();
+	fs::write(
+		dir.join("package.json"),
+		package_json.replacen("{", "{\n\t\"packageManager\": \"pnpm@8.15.0\",", 1),
+	)
+	.unwrap();
+	let result = kirbo(&["install"]);
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("this project uses pnpm@8.15.0"));
+	assert!(!kirbo(&["hello"]).status.success());
+	assert!(kirbo(&["install", "--force"]).status.success());
+}
+
+#[test]
+fn config() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("config");
+	let cache = testing::temp_dir("config-cache");
+	let home = testing::temp_dir("config-home");
+	testing::copy_dir(Path::new("./tests/testdata/62-config/"), &dir);
+	let kirbo = |args: &[&str]| {
+		registry
+			.command(&cache)
+			.current_dir(&dir)
+			.env("XDG_CONFIG_HOME", &home)
+			.env("KIRBO_ENGINE_STRICT", "1")
+			.args(args)
+			.output()
+			.unwrap()
+	};
+	let line = |stdout: &[u8], key: &str| {
+		String::from_utf8_lossy(stdout)
+			.lines()
+			.find(|line| line.starts_with(&format!("{} = ", key)))
+			.unwrap_or_else(|| panic!("{} wasn't listed", key))
+			.to_string()
+	};
+
+	assert!(kirbo(&["config", "set", "init-license", "ISC"])
+		.status
+		.success());
+	assert_eq!(
+		fs::read_to_string(home.join("kirbo/config.toml")).unwrap(),
+		"init-license = \"ISC\"\n"
+	);
+
+	// Each value comes from the highest layer which sets it
+	let result = kirbo(&["config", "list", "--show-origin"]);
+	assert!(result.status.success());
+	let global = line(&result.stdout, "init-license");
+	assert!(global.contains("\"ISC\"") && global.contains("config.toml"));
+	let package_json = line(&result.stdout, "linker");
+	assert!(package_json.contains("\"nested\"") && package_json.contains("package.json"));
+	let rc = line(&result.stdout, "concurrency");
+	assert!(rc.contains("= 2") && rc.contains(".kirborc"));
+	assert!(line(&result.stdout, "engine-strict").contains("$KIRBO_ENGINE_STRICT"));
+	assert!(line(&result.stdout, "strict-peer-deps").contains("default"));
+
+	// Nothing gets hoisted past the packages the project depends on directly
+	assert!(kirbo(&["install"]).status.success());
+	assert!(dir.join("node_modules/left/node_modules/shared").is_dir());
+	assert!(!dir.join("node_modules/shared").exists());
+
+	assert!(kirbo(&["config", "set", "--project", "linker", "hoisted"])
+		.status
+		.success());
+	assert_eq!(
+		fs::read_to_string(dir.join(".kirborc")).unwrap(),
+		"# downloads at a time\nconcurrency = 2\nlinker = \"hoisted\"\n"
+	);
+	let result = kirbo(&["config", "get", "linker", "--show-origin"]);
+	assert!(String::from_utf8_lossy(&result.stdout).contains("hoisted"));
+	assert!(String::from_utf8_lossy(&result.stdout).contains(".kirborc"));
+
+	assert!(!kirbo(&["config", "set", "linker", "isolated"])
+		.status
+		.success());
+	assert!(!kirbo(&["config", "get", "nope"]).status.success());
+
+	// Secrets are only printed when asked for by name
+	let secret = |args: &[&str]| {
+		let output = registry
+			.command(&cache)
+			.current_dir(&dir)
+			.env("XDG_CONFIG_HOME", &home)
+			.env("KIRBO_AUTH_TOKEN", "hunter2")
+			.args(args)
+			.output()
+			.unwrap();
+		String::from_utf8_lossy(&output.stdout).to_string()
+	};
+	assert!(!secret(&["config", "list"]).contains("hunter2"));
+	assert!(!secret(&["config", "get", "auth-token"]).contains("hunter2"));
+	assert_eq!(
+		secret(&["config", "get", "auth-token", "--show-secret"]),
+		"hunter2\n"
+	);
+	assert!(
+		!kirbo(&["config", "set", "--project", "auth-token", "hunter2"])
+			.status
+			.success()
+	);
+
+	// A broken config is an error like any other, but it can still be fixed
+	fs::write(
+		home.join("kirbo/config.toml"),
+		"strict-peer-deps = \"maybe\"\n",
+	)
+	.unwrap();
+	let result = kirbo(&["config", "list"]);
+	assert!(!result.status.success());
+	assert!(String::from_utf8_lossy(&result.stderr).contains("\"maybe\" isn't a valid value"));
+	assert!(kirbo(&["config", "set", "strict-peer-deps", "false"])
+		.status
+		.success());
+	assert!(kirbo(&["config", "list"]).status.success());
+}
+
+#[test]
+fn cli() {
+	testing::before();
+	let kirbo = |args: &[&str]| Command::new(&*EXE).args(args).output().unwrap();
+
+	let result = kirbo(&["audit", "--help"]);
+	assert!(result.status.success());
+	let help = String::from_utf8_lossy(&result.stdout);
+	assert!(help.contains("--advisories <path>"));
+	assert!(help.contains("--cwd <dir>"));
+
+	// Mistakes are reported before anything happens, with a usage status
+	let result = kirbo(&["install", "--nope"]);
+	assert_eq!(result.status.code(), Some(2));
+	assert!(String::from_utf8_lossy(&result.stderr).contains("--nope"));
+	assert_eq!(
+		kirbo(&["publish", "--access", "secret"]).status.code(),
+		Some(2)
+	);
+
+	for shell in ["bash", "zsh", "fish"] {
+		let result = kirbo(&["completions", shell]);
+		assert!(result.status.success());
+		assert!(String::from_utf8_lossy(&result.stdout).contains("patch-commit"));
+	}
+	assert!(!kirbo(&["completions", "tcsh"]).status.success());
+}
+
+#[test]
+fn workspace_targets() {
+	testing::before();
+	let registry = testing::Registry::start();
+	let dir = testing::temp_dir("targets");
+	let cache = testing::temp_dir("targets-cache");
+	testing::copy_dir(Path::new("./tests/testdata/63-targets/"), &dir);
+	let src = dir.join("packages/app/src");
+	let kirbo = |args: &[&str]| {
+		let result = registry
+			.command(&cache)
+			.current_dir(&src)
+			.args(args)
+			.output()
+			.unwrap();
+		(
+			result.status.success(),
+			String::from_utf8(result.stdout).unwrap(),
+		)
+	};
+
+	// Scripts come from the nearest package.json, or from the member that's asked for
+	let (success, stdout) = kirbo(&["run", "where"]);
+	assert!(success);
+	assert!(stdout.contains("app in") && stdout.contains("packages/app"));
+	let (success, stdout) = kirbo(&["run", "-w", "lib", "where"]);
+	assert!(success);
+	assert!(stdout.contains("lib in") && stdout.contains("packages/lib"));
+	assert!(!kirbo(&["run", "-w", "nope", "where"]).0);
+
+	let (success, stdout) = kirbo(&["--cwd", "../../lib", "run", "where"]);
+	assert!(success);
+	assert!(stdout.contains("lib in"));
+
+	// Whatever runs decides how kirbo exits
+	for args in [
+		&["run", "-w", "lib", "fail"][..],
+		&["exec", "-w", "lib", "sh", "-c", "exit 3"],
+	] {
+		let result = registry
+			.command(&cache)
+			.current_dir(&src)
+			.args(args)
+			.output()
+			.unwrap();
+		assert_eq!(result.status.code(), Some(3));
+	}
+
+	// New dependencies go to the same place, and the whole workspace gets installed
+	assert!(kirbo(&["add", "right@^1.0.0"]).0);
+	assert!(kirbo(&["add", "--workspace", "lib", "left", "-Dapp"]).0);
+	let app = fs::read_to_string(dir.join("packages/app/package.json")).unwrap();
+	assert!(app.contains("\t\"dependencies\": {\n\t\t\"right\": \"^1.0.0\"\n\t}"));
+	let lib = fs::read_to_string(dir.join("packages/lib/package.json")).unwrap();
+	assert!(lib.contains("\"left\": \"^1.0.0\""));
+	assert!(lib.contains("\"devDependencies\": {\n\t\t\"app\": \"workspace:*\"\n\t}"));
+	assert!(dir.join("node_modules/left/package.json").exists());
+	assert!(dir.join("node_modules/right/package.json").exists());
+	assert!(dir.join("Kirbo.lock").exists());
+	assert!(!src.join("node_modules").exists());
+}
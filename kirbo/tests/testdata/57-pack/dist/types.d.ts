@@ -0,0 +1 @@
+export declare const value: number;
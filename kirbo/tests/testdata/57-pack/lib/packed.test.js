@@ -0,0 +1 @@
+require("./packed.js");
@@ -0,0 +1 @@
+require("@kirbo/core");
@@ -0,0 +1 @@
+module.exports = "darwin-arm64";
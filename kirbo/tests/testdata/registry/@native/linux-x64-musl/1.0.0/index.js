@@ -0,0 +1 @@
+module.exports = "linux-x64-musl";
@@ -0,0 +1,27 @@
+"use strict";
+
+const fs = require("fs");
+const http = require("http");
+
+const result = {};
+
+try {
+	fs.writeFileSync("../../escaped.txt", "escaped");
+	result.escaped = true;
+} catch {
+	result.escaped = false;
+}
+
+// Only what's needed to build is passed through
+result.package = process.env.npm_package_name;
+result.leaked = ["KIRBO_AUTH_TOKEN", "NPM_TOKEN"].filter((name) => name in process.env);
+
+http
+	.get(process.env.npm_config_registry, () => {
+		result.network = true;
+		fs.writeFileSync("built.json", JSON.stringify(result));
+	})
+	.on("error", () => {
+		result.network = false;
+		fs.writeFileSync("built.json", JSON.stringify(result));
+	});
@@ -0,0 +1,3 @@
+#!/bin/sh
+mkdir -p "$1"
+echo "{ \"name\": \"$1\" }" > "$1/package.json"
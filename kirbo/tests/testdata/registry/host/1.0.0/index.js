@@ -0,0 +1 @@
+module.exports = "host@1.0.0";
@@ -0,0 +1 @@
+module.exports = "host@2.0.0";
@@ -0,0 +1 @@
+module.exports = "left@1.0.0";
@@ -0,0 +1 @@
+module.exports = "modern@1.0.0";
@@ -0,0 +1 @@
+module.exports = "right@1.0.0";
@@ -0,0 +1 @@
+module.exports = "shared@1.0.0";
@@ -0,0 +1 @@
+module.exports = "shared@1.1.0";
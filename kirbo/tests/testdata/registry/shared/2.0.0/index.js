@@ -0,0 +1 @@
+module.exports = "shared@2.0.0";
@@ -1,10 +1,29 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use kirbo::semver::Version;
+use kirbo::store::sha512_base64;
 use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::env;
 use std::env::current_dir;
 use std::ffi::OsString;
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::sync::Once;
+use std::thread;
 
-pub const EXE: Lazy<OsString> = Lazy::new(|| {
+pub static EXE: Lazy<OsString> = Lazy::new(|| {
 	current_dir()
 		.unwrap()
 		.join("../build/release/kirbo")
@@ -16,8 +35,279 @@ static BUILD: Once = Once::new();
 pub fn before() {
 	BUILD.call_once(|| {
 		Command::new("cargo")
-			.args(&["build", "--release"])
+			.args(["build", "--release"])
 			.status()
 			.expect("failed to build test binary");
 	});
 }
+
+/// Creates an empty directory for a test to use, outside of the repository
+pub fn temp_dir(name: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("kirbo-test-{}", name));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// Recursively copies the contents of `from` into `to`
+pub fn copy_dir(from: &Path, to: &Path) {
+	fs::create_dir_all(to).unwrap();
+	for entry in fs::read_dir(from).unwrap() {
+		let entry = entry.unwrap();
+		let target = to.join(entry.file_name());
+		if entry.file_type().unwrap().is_dir() {
+			copy_dir(&entry.path(), &target);
+		} else {
+			fs::copy(entry.path(), target).unwrap();
+		}
+	}
+}
+
+/// A stand-in for the npm registry, serving the packages in `tests/testdata/registry/`
+/// from a local port. Packages are laid out as `<name>/<version>/`, and each
+/// version directory is packed into a tarball on request.
+///
+/// Publishing requires a token, and the token `otp-token` also requires the one-time
+/// password `123456`. Published documents are kept in `published`, but aren't served.
+pub struct Registry {
+	pub url: String,
+	pub published: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+fn find_versions(root: &Path) -> BTreeMap<String, BTreeMap<String, PathBuf>> {
+	let mut packages = BTreeMap::<String, BTreeMap<String, PathBuf>>::new();
+	for entry in fs::read_dir(root).unwrap() {
+		let path = entry.unwrap().path();
+		let name = path.file_name().unwrap().to_string_lossy().to_string();
+		let dirs = match name.starts_with('@') {
+			true => fs::read_dir(&path)
+				.unwrap()
+				.map(|entry| entry.unwrap().path())
+				.collect(),
+			false => vec![path],
+		};
+
+		for dir in dirs {
+			let name = dir
+				.strip_prefix(root)
+				.unwrap()
+				.to_string_lossy()
+				.replace('\\', "/");
+			for version in fs::read_dir(&dir).unwrap() {
+				let version = version.unwrap().path();
+				packages.entry(name.clone()).or_default().insert(
+					version.file_name().unwrap().to_string_lossy().to_string(),
+					version,
+				);
+			}
+		}
+	}
+	packages
+}
+
+fn add_to_tarball(builder: &mut tar::Builder<GzEncoder<Vec<u8>>>, root: &Path, dir: &Path) {
+	let mut entries = fs::read_dir(dir)
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.collect::<Vec<_>>();
+	entries.sort();
+
+	for path in entries {
+		if path.is_dir() {
+			add_to_tarball(builder, root, &path);
+			continue;
+		}
+
+		let contents = fs::read(&path).unwrap();
+		let mut header = tar::Header::new_gnu();
+		header.set_size(contents.len() as u64);
+		header.set_mode(0o644);
+		header.set_mtime(0);
+		header.set_cksum();
+		builder
+			.append_data(
+				&mut header,
+				Path::new("package").join(path.strip_prefix(root).unwrap()),
+				&*contents,
+			)
+			.unwrap();
+	}
+}
+
+/// Packs a package directory the same way every time, so that its integrity is stable
+pub fn pack(dir: &Path) -> Vec<u8> {
+	let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+	add_to_tarball(&mut builder, dir, dir);
+	builder.into_inner().unwrap().finish().unwrap()
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+	let _ = write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		status,
+		content_type,
+		body.len()
+	);
+	let _ = stream.write_all(body);
+}
+
+fn handle(
+	mut stream: TcpStream,
+	root: &Path,
+	url: &str,
+	published: &Mutex<Vec<serde_json::Value>>,
+) {
+	let mut reader = BufReader::new(stream.try_clone().unwrap());
+	let mut request_line = String::new();
+	reader.read_line(&mut request_line).unwrap();
+	let mut headers = BTreeMap::new();
+	loop {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		if line.trim().is_empty() {
+			break;
+		}
+		if let Some((key, value)) = line.split_once(':') {
+			headers.insert(key.to_ascii_lowercase(), value.trim().to_string());
+		}
+	}
+	let content_length = headers
+		.get("content-length")
+		.map_or(0, |it| it.parse().unwrap());
+	let mut body = vec![0; content_length];
+	reader.read_exact(&mut body).unwrap();
+
+	let path = request_line
+		.split(' ')
+		.nth(1)
+		.unwrap_or("/")
+		.replace("%2f", "/");
+	let path = path.trim_start_matches('/');
+	let packages = find_versions(root);
+
+	if request_line.starts_with("PUT ") {
+		let otp = headers.get("npm-otp").map(String::as_str);
+		match headers.get("authorization").map(String::as_str) {
+			None => respond(&mut stream, "401 Unauthorized", "application/json", b"{}"),
+			Some("Bearer otp-token") if otp != Some("123456") => {
+				let _ = write!(
+					stream,
+					"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: OTP\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+				);
+			}
+			Some(_) => {
+				published
+					.lock()
+					.unwrap()
+					.push(serde_json::from_slice(&body).unwrap());
+				respond(&mut stream, "201 Created", "application/json", b"{}");
+			}
+		}
+		return;
+	}
+
+	// Advisories come from `tests/testdata/advisories/bulk.json`, filtered down to the
+	// packages that were asked about
+	if path == "-/npm/v1/security/advisories/bulk" {
+		let requested = serde_json::from_slice::<BTreeMap<String, Vec<String>>>(&body).unwrap();
+		let advisories = serde_json::from_slice::<BTreeMap<String, serde_json::Value>>(
+			&fs::read(root.join("../advisories/bulk.json")).unwrap(),
+		)
+		.unwrap()
+		.into_iter()
+		.filter(|(name, _)| requested.contains_key(name))
+		.collect::<BTreeMap<_, _>>();
+		respond(
+			&mut stream,
+			"200 OK",
+			"application/json",
+			serde_json::to_string(&advisories).unwrap().as_bytes(),
+		);
+		return;
+	}
+
+	// Tarballs are served from `<name>/-/<version>.tgz`
+	if let Some((name, file)) = path.split_once("/-/") {
+		let version = file.trim_end_matches(".tgz");
+		match packages
+			.get(name)
+			.and_then(|versions| versions.get(version))
+		{
+			Some(dir) => respond(
+				&mut stream,
+				"200 OK",
+				"application/octet-stream",
+				&pack(dir),
+			),
+			None => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+		}
+		return;
+	}
+
+	let Some(versions) = packages.get(path) else {
+		respond(&mut stream, "404 Not Found", "application/json", b"{}");
+		return;
+	};
+
+	let mut docs = serde_json::Map::new();
+	for (version, dir) in versions {
+		let mut doc =
+			serde_json::from_slice::<serde_json::Value>(&fs::read(dir.join("package.json")).unwrap())
+				.unwrap();
+		let integrity = format!("sha512-{}", sha512_base64(&pack(dir)));
+		doc["dist"] = serde_json::json!({
+			"tarball": format!("{}{}/-/{}.tgz", url, path, version),
+			"integrity": integrity,
+		});
+		docs.insert(version.clone(), doc);
+	}
+	let latest = versions
+		.keys()
+		.max_by_key(|version| Version::from_str(version).unwrap())
+		.unwrap();
+	let doc = serde_json::json!({
+		"name": path,
+		"dist-tags": { "latest": latest },
+		"versions": docs,
+	});
+	respond(
+		&mut stream,
+		"200 OK",
+		"application/json",
+		doc.to_string().as_bytes(),
+	);
+}
+
+impl Registry {
+	pub fn start() -> Registry {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}/", listener.local_addr().unwrap());
+		let root = current_dir().unwrap().join("tests/testdata/registry/");
+
+		let published = Arc::new(Mutex::new(vec![]));
+
+		let server_url = url.clone();
+		let server_published = published.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let stream = stream.unwrap();
+				let root = root.clone();
+				let url = server_url.clone();
+				let published = server_published.clone();
+				thread::spawn(move || handle(stream, &root, &url, &published));
+			}
+		});
+
+		Registry { url, published }
+	}
+
+	/// A command which runs kirbo against this registry, with its own empty cache
+	pub fn command(&self, cache: &Path) -> Command {
+		let mut command = Command::new(&*EXE);
+		command
+			.env("KIRBO_REGISTRY", &self.url)
+			.env("KIRBO_CACHE_DIR", cache);
+		command
+	}
+}
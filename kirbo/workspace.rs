@@ -0,0 +1,154 @@
+use anyhow::anyhow;
+use kirbo_workspace::Package;
+use kirbo_workspace::PackageJson;
+use kirbo_workspace::Workspace;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::semver::SemverRange;
+use crate::semver::Version;
+
+/// A dependency on another package in the same workspace, written as
+/// `workspace:<range>` (the same syntax used by yarn and pnpm).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WorkspaceRange {
+	/// workspace:*
+	Any,
+	/// workspace:^
+	Compatible,
+	/// workspace:~
+	Patched,
+	/// workspace:^1.2.3, workspace:1.2.3, etc.
+	Range(String),
+}
+
+impl FromStr for WorkspaceRange {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let range = s
+			.strip_prefix("workspace:")
+			.ok_or_else(|| anyhow!("{} is not a workspace range", s))?;
+
+		Ok(match range {
+			"*" | "" => WorkspaceRange::Any,
+			"^" => WorkspaceRange::Compatible,
+			"~" => WorkspaceRange::Patched,
+			range => {
+				SemverRange::from_str(range)?;
+				WorkspaceRange::Range(range.to_string())
+			}
+		})
+	}
+}
+
+impl WorkspaceRange {
+	pub fn is_workspace_range(s: &str) -> bool {
+		s.starts_with("workspace:")
+	}
+
+	/// Checks if a workspace package at `version` can satisfy this range
+	pub fn matches(&self, version: &Version) -> bool {
+		match self {
+			WorkspaceRange::Range(range) => SemverRange::from_str(range)
+				.map(|range| range.matches(version))
+				.unwrap_or(false),
+			_ => true,
+		}
+	}
+
+	/// The range that should be written in place of this one when the package is
+	/// published, given the current version of the workspace package.
+	pub fn to_publish_range(&self, version: &str) -> String {
+		match self {
+			WorkspaceRange::Any => version.to_string(),
+			WorkspaceRange::Compatible => format!("^{}", version),
+			WorkspaceRange::Patched => format!("~{}", version),
+			WorkspaceRange::Range(range) => range.clone(),
+		}
+	}
+}
+
+/// The package a command should act on: the member named by `--workspace`, or else
+/// the package.json nearest to where kirbo was started
+pub fn target_package<'a>(
+	workspace: &'a Workspace,
+	name: Option<&str>,
+) -> anyhow::Result<&'a PackageJson> {
+	let Some(name) = name else {
+		return Ok(workspace.current_package());
+	};
+	workspace.get(name).ok_or_else(|| {
+		anyhow!(
+			"no workspace package named \"{}\" in {}",
+			name,
+			workspace.root().display()
+		)
+	})
+}
+
+/// Replaces every `workspace:` range in a package with a concrete range, so that it
+/// can be installed by people who aren't using our workspace.
+pub fn rewrite_for_publish(package: &mut Package, workspace: &Workspace) -> anyhow::Result<()> {
+	let rewrite = |dependencies: &mut HashMap<String, String>| -> anyhow::Result<()> {
+		for (name, range) in dependencies.iter_mut() {
+			if !WorkspaceRange::is_workspace_range(range) {
+				continue;
+			}
+
+			let version = workspace
+				.get(name)
+				.and_then(|package_json| package_json.1.version.as_ref())
+				.ok_or_else(|| anyhow!("no workspace package named {} with a version", name))?;
+			*range = range.parse::<WorkspaceRange>()?.to_publish_range(version);
+		}
+		Ok(())
+	};
+
+	rewrite(&mut package.dependencies)?;
+	rewrite(&mut package.peer_dependencies)?;
+	rewrite(&mut package.dev_dependencies)?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_workspace_range() {
+		assert_eq!(
+			"workspace:*".parse::<WorkspaceRange>().unwrap(),
+			WorkspaceRange::Any
+		);
+		assert_eq!(
+			"workspace:^".parse::<WorkspaceRange>().unwrap(),
+			WorkspaceRange::Compatible
+		);
+		assert_eq!(
+			"workspace:~".parse::<WorkspaceRange>().unwrap(),
+			WorkspaceRange::Patched
+		);
+		assert_eq!(
+			"workspace:^1.2.0".parse::<WorkspaceRange>().unwrap(),
+			WorkspaceRange::Range("^1.2.0".to_string())
+		);
+		assert!("^1.2.0".parse::<WorkspaceRange>().is_err());
+		assert!("workspace:poyo".parse::<WorkspaceRange>().is_err());
+	}
+
+	#[test]
+	fn publish_range() {
+		assert_eq!(WorkspaceRange::Any.to_publish_range("1.2.3"), "1.2.3");
+		assert_eq!(
+			WorkspaceRange::Compatible.to_publish_range("1.2.3"),
+			"^1.2.3"
+		);
+		assert_eq!(WorkspaceRange::Patched.to_publish_range("1.2.3"), "~1.2.3");
+		assert_eq!(
+			WorkspaceRange::Range("^1.0.0".to_string()).to_publish_range("1.2.3"),
+			"^1.0.0"
+		);
+	}
+}
@@ -0,0 +1,5 @@
+"use strict";
+
+throw new Error(
+	"crossenv is a known malicious package, and has been blocked by kirbo. Did you mean cross-env?",
+);
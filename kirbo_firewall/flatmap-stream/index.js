@@ -0,0 +1,5 @@
+"use strict";
+
+throw new Error(
+	"flatmap-stream is a known malicious package, and has been blocked by kirbo.",
+);
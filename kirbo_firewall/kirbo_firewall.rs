@@ -1,3 +1,91 @@
-#![allow(non_upper_case_globals)]
-pub const is_negative_zero: &str = include_str!("./is-negative-zero/index.js");
-pub const leftpad: &str = include_str!("./leftpad/index.js");
+//! Packages which kirbo will never download from the registry. Each one is either
+//! trivial enough to replace with a few lines of JavaScript, or known to be
+//! malicious, and is served from the source embedded here instead.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Reason {
+	/// Reimplements something the platform already provides
+	Polyfill,
+	/// The original package is deprecated, but still widely depended on
+	Deprecated,
+	/// The original package is malware. The substitute throws when it's loaded.
+	Malicious,
+}
+
+impl Reason {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Reason::Polyfill => "polyfill",
+			Reason::Deprecated => "deprecated",
+			Reason::Malicious => "malicious",
+		}
+	}
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Substitution {
+	pub name: &'static str,
+	/// The versions of the original package which this can stand in for. Malicious
+	/// packages use `*`, so that every request is caught.
+	pub range: &'static str,
+	/// The version that the substitute presents itself as
+	pub version: &'static str,
+	pub reason: Reason,
+	/// The contents of `index.js`
+	pub source: &'static str,
+}
+
+impl Substitution {
+	/// A package.json to install alongside `source`
+	pub fn package_json(&self) -> String {
+		format!(
+			"{{\n\t\"name\": \"{}\",\n\t\"version\": \"{}\",\n\t\"main\": \"index.js\"\n}}\n",
+			self.name, self.version
+		)
+	}
+}
+
+pub const SUBSTITUTIONS: &[Substitution] = &[
+	Substitution {
+		name: "crossenv",
+		range: "*",
+		version: "0.0.0",
+		reason: Reason::Malicious,
+		source: include_str!("./crossenv/index.js"),
+	},
+	Substitution {
+		name: "flatmap-stream",
+		range: "*",
+		version: "0.0.0",
+		reason: Reason::Malicious,
+		source: include_str!("./flatmap-stream/index.js"),
+	},
+	Substitution {
+		name: "is-negative-zero",
+		range: "^2.0.0",
+		version: "2.0.3",
+		reason: Reason::Polyfill,
+		source: include_str!("./is-negative-zero/index.js"),
+	},
+	Substitution {
+		name: "left-pad",
+		range: "^1.0.0",
+		version: "1.3.0",
+		reason: Reason::Deprecated,
+		source: include_str!("./left-pad/index.js"),
+	},
+	Substitution {
+		name: "object-assign",
+		range: "^4.0.0",
+		version: "4.1.1",
+		reason: Reason::Polyfill,
+		source: include_str!("./object-assign/index.js"),
+	},
+];
+
+/// The substitution for a package, if we have one
+pub fn find(name: &str) -> Option<&'static Substitution> {
+	SUBSTITUTIONS
+		.iter()
+		.find(|substitution| substitution.name == name)
+}
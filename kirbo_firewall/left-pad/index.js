@@ -0,0 +1,5 @@
+"use strict";
+
+module.exports = function leftPad(text, length, padding) {
+	return String(text).padStart(length, padding || padding === 0 ? String(padding) : " ");
+};
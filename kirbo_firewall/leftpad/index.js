@@ -1,5 +0,0 @@
-"use strict";
-
-module.exports = function leftpad(text, length, padding) {
-	String(text).padStart(length, padding || "0");
-};
@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Matches a single path segment (or a package name) against a pattern, where `*`
+/// matches any run of characters.
+pub fn matches(pattern: &str, text: &str) -> bool {
+	let pattern = pattern.as_bytes();
+	let text = text.as_bytes();
+
+	let (mut p, mut t) = (0, 0);
+	let mut backtrack = None;
+
+	while t < text.len() {
+		if p < pattern.len() && pattern[p] == b'*' {
+			backtrack = Some((p, t));
+			p += 1;
+		} else if p < pattern.len() && pattern[p] == text[t] {
+			p += 1;
+			t += 1;
+		} else if let Some((star, consumed)) = backtrack {
+			p = star + 1;
+			t = consumed + 1;
+			backtrack = Some((star, consumed + 1));
+		} else {
+			return false;
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Splits a workspace pattern like `./packages/*/` into its meaningful segments.
+fn segments(pattern: &str) -> Vec<&str> {
+	pattern
+		.split('/')
+		.filter(|segment| !segment.is_empty() && *segment != ".")
+		.collect()
+}
+
+/// Checks if a relative path is matched by a workspace pattern. `**` will match any
+/// number of directories.
+pub fn matches_path(pattern: &str, path: &Path) -> bool {
+	let path = path
+		.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>();
+	let path = path.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+
+	matches_segments(&segments(pattern), &path)
+}
+
+fn matches_segments(pattern: &[&str], path: &[&str]) -> bool {
+	match (pattern.first(), path.first()) {
+		(None, None) => true,
+		(Some(&"**"), _) => {
+			matches_segments(&pattern[1..], path)
+				|| (!path.is_empty() && matches_segments(pattern, &path[1..]))
+		}
+		(Some(segment), Some(part)) => {
+			matches(segment, part) && matches_segments(&pattern[1..], &path[1..])
+		}
+		_ => false,
+	}
+}
+
+/// Finds every directory beneath `root` that matches a workspace pattern.
+pub fn expand(root: &Path, pattern: &str) -> Vec<PathBuf> {
+	let mut found = vec![];
+	expand_segments(root.to_path_buf(), &segments(pattern), &mut found);
+	found.sort();
+	found.dedup();
+	found
+}
+
+fn expand_segments(dir: PathBuf, pattern: &[&str], found: &mut Vec<PathBuf>) {
+	let Some(&segment) = pattern.first() else {
+		found.push(dir);
+		return;
+	};
+
+	if !segment.contains('*') {
+		let next = dir.join(segment);
+		if next.is_dir() {
+			expand_segments(next, &pattern[1..], found);
+		}
+		return;
+	}
+
+	if segment == "**" {
+		expand_segments(dir.clone(), &pattern[1..], found);
+	}
+
+	let Ok(entries) = fs::read_dir(&dir) else {
+		return;
+	};
+
+	for entry in entries.flatten() {
+		let name = entry.file_name();
+		let name = name.to_string_lossy();
+		// Hidden directories and installed dependencies are never workspace members
+		if name.starts_with('.') || name == "node_modules" || !entry.path().is_dir() {
+			continue;
+		}
+
+		if segment == "**" {
+			expand_segments(entry.path(), pattern, found);
+		} else if matches(segment, &name) {
+			expand_segments(entry.path(), &pattern[1..], found);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wildcards() {
+		assert!(matches("*", "poyo"));
+		assert!(matches("@kirbo/*", "@kirbo/ui"));
+		assert!(matches("*-utils", "string-utils"));
+		assert!(matches("p*y*", "poyo"));
+		assert!(!matches("@kirbo/*", "kirbo"));
+		assert!(!matches("nya", "nyan"));
+	}
+
+	#[test]
+	fn paths() {
+		assert!(matches_path("./packages/*/", Path::new("packages/nya")));
+		assert!(matches_path("packages/**", Path::new("packages/a/b")));
+		assert!(matches_path("poyo/", Path::new("poyo")));
+		assert!(!matches_path("packages/*", Path::new("packages/a/b")));
+		assert!(!matches_path("packages/*", Path::new("internal")));
+	}
+}
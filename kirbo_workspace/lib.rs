@@ -1,7 +1,13 @@
+pub mod glob;
 mod package_json;
 mod workspace;
 
+pub use package_json::Bin;
+pub use package_json::FirewallSettings;
+pub use package_json::KirboSettings;
+pub use package_json::Override;
 pub use package_json::Package;
 pub use package_json::PackageJson;
 pub use package_json::PackageJsonError;
+pub use package_json::Workspaces;
 pub use workspace::Workspace;
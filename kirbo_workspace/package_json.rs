@@ -1,9 +1,11 @@
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fs;
 use std::io;
+use std::path::Path;
 use std::path::PathBuf;
 
 #[derive(Debug)]
@@ -38,6 +40,13 @@ impl TryFrom<PathBuf> for PackageJson {
 	}
 }
 
+impl PackageJson {
+	/// The directory containing this package.json
+	pub fn dir(&self) -> &Path {
+		self.0.parent().unwrap_or_else(|| Path::new("."))
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Package {
@@ -45,6 +54,26 @@ pub struct Package {
 	pub name: Option<String>,
 	pub version: Option<String>,
 	pub license: Option<String>,
+	/// The entry point of the package
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub main: Option<String>,
+	/// Executables that the package provides
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub bin: Option<Bin>,
+	/// Patterns for the files which should be included when the package is packed
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub files: Option<Vec<String>>,
+	/// Ranges of the runtimes (like `node`) that the package works with
+	#[serde(
+		default,
+		deserialize_with = "engines",
+		skip_serializing_if = "BTreeMap::is_empty"
+	)]
+	pub engines: BTreeMap<String, String>,
+	/// The package manager the project expects to be used with, like `kirbo@0.1.0` or
+	/// `yarn@3.6.0`. Only read from the root of a workspace.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub package_manager: Option<String>,
 	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
 	pub dependencies: HashMap<String, String>,
 	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
@@ -53,4 +82,123 @@ pub struct Package {
 	pub dev_dependencies: HashMap<String, String>,
 	#[serde(default, skip_serializing_if = "HashMap::is_empty")]
 	pub scripts: HashMap<String, String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub workspaces: Option<Workspaces>,
+	/// npm style overrides, which force the version of a (possibly transitive)
+	/// dependency. Only read from the root of a workspace.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub overrides: BTreeMap<String, Override>,
+	/// yarn style resolutions, which do the same thing as `overrides`, using paths
+	/// like `foo/bar` or `**/bar` instead of nesting
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub resolutions: BTreeMap<String, String>,
+	/// Settings for kirbo itself, which are only read from the root of a workspace
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub kirbo: Option<KirboSettings>,
+}
+
+/// Some very old packages list their engines as an array of strings like
+/// `"node >= 0.4"`, which npm has long since stopped paying attention to, so
+/// anything that isn't an object of ranges is ignored rather than rejected
+fn engines<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+	D: serde::Deserializer<'de>,
+{
+	let value = serde_json::Value::deserialize(deserializer)?;
+	Ok(
+		value
+			.as_object()
+			.into_iter()
+			.flatten()
+			.filter_map(|(engine, range)| Some((engine.clone(), range.as_str()?.to_string())))
+			.collect(),
+	)
+}
+
+/// `bin` can be a single path, in which case the executable is named after the
+/// package, or a map of executable names to paths.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Bin {
+	Path(String),
+	Map(BTreeMap<String, String>),
+}
+
+impl Bin {
+	/// The paths of every executable
+	pub fn paths(&self) -> Vec<&str> {
+		match self {
+			Bin::Path(path) => vec![path.as_str()],
+			Bin::Map(map) => map.values().map(String::as_str).collect(),
+		}
+	}
+
+	/// The name and path of every executable, where a single path is named after the
+	/// package (without its scope)
+	pub fn commands<'a>(&'a self, package_name: &'a str) -> Vec<(&'a str, &'a str)> {
+		match self {
+			Bin::Path(path) => {
+				let name = package_name.rsplit('/').next().unwrap_or(package_name);
+				vec![(name, path.as_str())]
+			}
+			Bin::Map(map) => map
+				.iter()
+				.map(|(name, path)| (name.as_str(), path.as_str()))
+				.collect(),
+		}
+	}
+}
+
+/// npm uses a plain list of patterns, while yarn also accepts an object with a
+/// `packages` key (alongside options like `nohoist` which we don't care about).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+	Patterns(Vec<String>),
+	Config { packages: Vec<String> },
+}
+
+impl Workspaces {
+	pub fn patterns(&self) -> &[String] {
+		match self {
+			Workspaces::Patterns(patterns) => patterns,
+			Workspaces::Config { packages } => packages,
+		}
+	}
+}
+
+/// The value of an entry in `overrides`, which is either the version to use, or more
+/// overrides which only apply beneath the package it's keyed by. Inside of a nested
+/// override, `"."` is the version of that package itself.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Override {
+	Version(String),
+	Nested(BTreeMap<String, Override>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KirboSettings {
+	#[serde(default)]
+	pub firewall: FirewallSettings,
+	/// Dependencies which are allowed to run `preinstall`, `install`, and
+	/// `postinstall` scripts. Registry packages are listed by their real name (even
+	/// when they're installed through an alias), and git or tarball dependencies by
+	/// their exact source.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub allow_scripts: Vec<String>,
+	/// Patches to apply to dependencies after they're extracted, keyed by either
+	/// `name@version` or just `name` to patch every version
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub patched_dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirewallSettings {
+	/// Packages which should always come from the registry, even if kirbo_firewall
+	/// has a substitute for them
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub ignore: Vec<String>,
 }
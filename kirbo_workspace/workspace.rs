@@ -1,54 +1,234 @@
 use anyhow::anyhow;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
 
+use crate::glob;
 use crate::PackageJson;
 use crate::PackageJsonError;
 
+#[derive(Clone, Debug)]
 pub struct Workspace {
 	root: PathBuf,
-	packages_by_name: HashMap<String, PackageJson>,
+	root_package: PackageJson,
+	current_package: PackageJson,
+	packages_by_name: BTreeMap<String, PackageJson>,
 }
 
 impl Workspace {
-	fn new(path: &Path) -> anyhow::Result<Self> {
-		// The farthest up directory which contains a package.json that eventually leads
-		// back up to us.
-		let mut root = None;
+	pub fn new(path: &Path) -> anyhow::Result<Self> {
+		// Every directory above us which contains a package.json, nearest first
+		let mut packages = vec![];
 		let mut next_path = Some(path);
 
 		while let Some(path) = next_path {
-			let package_json = PackageJson::try_from(path.join("package.json"));
-
-			match package_json {
-				Ok(package_json) => {
-					dbg!(&package_json);
-					root = Some(path);
-				}
-				Err(PackageJsonError::JsonError(_)) => {
-					println!("failed to parse package.json in {}", path.display())
+			match PackageJson::try_from(path.join("package.json")) {
+				Ok(package_json) => packages.push((path, package_json)),
+				// A broken package.json further up is probably none of our business, but
+				// the one we're actually working in had better be valid.
+				Err(PackageJsonError::JsonError(err)) if packages.is_empty() => {
+					return Err(anyhow!(
+						"failed to parse package.json in {}: {}",
+						path.display(),
+						err
+					));
 				}
-				Err(PackageJsonError::IoError(err)) if err.kind() == io::ErrorKind::NotFound => {
-					println!("no package.json in {}, that's fine", path.display())
+				Err(PackageJsonError::IoError(err)) if err.kind() != io::ErrorKind::NotFound => {
+					return Err(err.into());
 				}
-				Err(PackageJsonError::IoError(err)) => println!("other io::Error {:?}", err),
+				Err(_) => (),
 			}
 
 			// Process to check next parent if it exists.
 			next_path = path.parent();
 		}
 
-		let Some(root) = root else {
-			return Err(anyhow!("fuck off"));
+		let Some((nearest, current_package)) = packages.first() else {
+			return Err(anyhow!("no package.json found in {}", path.display()));
 		};
 
+		// The nearest package which lists us as one of its workspaces is the root. If
+		// no one claims us, then we're on our own.
+		let (root, root_package) = packages[1..]
+			.iter()
+			.find(|(dir, package_json)| {
+				let relative = nearest.strip_prefix(dir).unwrap_or(nearest);
+				package_json
+					.1
+					.workspaces
+					.iter()
+					.flat_map(|workspaces| workspaces.patterns())
+					.any(|pattern| glob::matches_path(pattern, relative))
+			})
+			.unwrap_or(&packages[0]);
+
+		let mut packages_by_name = BTreeMap::new();
+		if let Some(name) = &root_package.1.name {
+			packages_by_name.insert(name.clone(), root_package.clone());
+		}
+
+		let patterns = root_package
+			.1
+			.workspaces
+			.iter()
+			.flat_map(|workspaces| workspaces.patterns());
+		for pattern in patterns {
+			for dir in glob::expand(root, pattern) {
+				let package_json = match PackageJson::try_from(dir.join("package.json")) {
+					Ok(package_json) => package_json,
+					Err(PackageJsonError::IoError(err)) if err.kind() == io::ErrorKind::NotFound => continue,
+					Err(err) => {
+						return Err(anyhow!(
+							"failed to read workspace package in {}: {:?}",
+							dir.display(),
+							err
+						))
+					}
+				};
+
+				let Some(name) = package_json.1.name.clone() else {
+					return Err(anyhow!(
+						"workspace package in {} must have a name",
+						dir.display()
+					));
+				};
+
+				if let Some(existing) = packages_by_name.insert(name.clone(), package_json) {
+					if existing.dir() != dir {
+						return Err(anyhow!(
+							"multiple workspace packages are named \"{}\"",
+							name
+						));
+					}
+				}
+			}
+		}
+
 		Ok(Workspace {
 			root: root.to_path_buf(),
-			packages_by_name: Default::default(),
+			root_package: root_package.clone(),
+			current_package: current_package.clone(),
+			packages_by_name,
 		})
 	}
+
+	/// The directory containing the root package.json
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	pub fn root_package(&self) -> &PackageJson {
+		&self.root_package
+	}
+
+	/// The package.json nearest to the directory the workspace was found from, which is
+	/// the root unless we started inside of another package
+	pub fn current_package(&self) -> &PackageJson {
+		&self.current_package
+	}
+
+	/// Looks up a package in the workspace (including the root) by name
+	pub fn get(&self, name: &str) -> Option<&PackageJson> {
+		self.packages_by_name.get(name)
+	}
+
+	/// Every named package in the workspace, including the root, sorted by name
+	pub fn packages(&self) -> impl Iterator<Item = &PackageJson> {
+		self.packages_by_name.values()
+	}
+
+	/// Every package in the workspace except the root
+	pub fn members(&self) -> impl Iterator<Item = &PackageJson> {
+		self
+			.packages_by_name
+			.values()
+			.filter(|package_json| package_json.0 != self.root_package.0)
+	}
+
+	/// Names of the other workspace packages which `name` directly depends on
+	pub fn dependencies_of(&self, name: &str) -> BTreeSet<&str> {
+		let Some(package_json) = self.packages_by_name.get(name) else {
+			return BTreeSet::new();
+		};
+		let package = &package_json.1;
+
+		package
+			.dependencies
+			.keys()
+			.chain(package.dev_dependencies.keys())
+			.chain(package.peer_dependencies.keys())
+			.filter_map(|dependency| self.packages_by_name.get_key_value(dependency))
+			.map(|(dependency, _)| dependency.as_str())
+			.filter(|&dependency| dependency != name)
+			.collect()
+	}
+
+	/// Names of every workspace package which `name` depends on, directly or not
+	pub fn transitive_dependencies_of(&self, name: &str) -> BTreeSet<&str> {
+		let mut found = BTreeSet::new();
+		let mut queue = self.dependencies_of(name).into_iter().collect::<Vec<_>>();
+
+		while let Some(next) = queue.pop() {
+			if next != name && found.insert(next) {
+				queue.extend(self.dependencies_of(next));
+			}
+		}
+
+		found
+	}
+
+	/// Names of every workspace package which depends on `name`, directly or not
+	pub fn transitive_dependents_of(&self, name: &str) -> BTreeSet<&str> {
+		self
+			.packages_by_name
+			.keys()
+			.map(String::as_str)
+			.filter(|&package| package != name && self.transitive_dependencies_of(package).contains(name))
+			.collect()
+	}
+
+	/// Every package in the workspace, ordered so that each package comes after all
+	/// of the workspace packages that it depends on.
+	pub fn topological_order(&self) -> anyhow::Result<Vec<&PackageJson>> {
+		fn visit<'a>(
+			workspace: &'a Workspace,
+			name: &'a str,
+			visiting: &mut Vec<&'a str>,
+			visited: &mut BTreeSet<&'a str>,
+			order: &mut Vec<&'a PackageJson>,
+		) -> anyhow::Result<()> {
+			if visited.contains(name) {
+				return Ok(());
+			}
+			if let Some(start) = visiting.iter().position(|&it| it == name) {
+				return Err(anyhow!(
+					"workspace packages have a circular dependency: {} -> {}",
+					visiting[start..].join(" -> "),
+					name
+				));
+			}
+
+			visiting.push(name);
+			for dependency in workspace.dependencies_of(name) {
+				visit(workspace, dependency, visiting, visited, order)?;
+			}
+			visiting.pop();
+
+			visited.insert(name);
+			order.push(&workspace.packages_by_name[name]);
+			Ok(())
+		}
+
+		let mut order = vec![];
+		let mut visited = BTreeSet::new();
+		for name in self.packages_by_name.keys() {
+			visit(self, name, &mut vec![], &mut visited, &mut order)?;
+		}
+
+		Ok(order)
+	}
 }
 
 #[cfg(test)]
@@ -146,10 +326,72 @@ mod tests {
 	}
 
 	#[test]
-	#[ignore]
-	fn resolves_workspace_nested_wildcard_from_root() {}
+	fn finds_current_package() {
+		let workspace =
+			Workspace::new(Path::new("testdata/workspace_nested/packages/poyo/docs/")).unwrap();
+		assert_eq!(workspace.current_package().1.name.as_deref(), Some("poyo"));
+		let workspace = Workspace::new(Path::new("testdata/workspace_nested/docs/")).unwrap();
+		assert_eq!(workspace.current_package().0, workspace.root_package().0);
+	}
 
 	#[test]
-	#[ignore]
-	fn resolves_workspace_nested_wildcard_from_nested_directory() {}
+	fn resolves_workspace_nested_wildcard_from_root() {
+		let workspace = Workspace::new(Path::new("testdata/workspace_nested_wildcard/")).unwrap();
+		assert_eq!(
+			&workspace.root,
+			Path::new("testdata/workspace_nested_wildcard/")
+		);
+	}
+
+	#[test]
+	fn resolves_workspace_nested_wildcard_from_nested_directory() {
+		let workspace = Workspace::new(Path::new(
+			"testdata/workspace_nested_wildcard/packages/nya/docs/",
+		))
+		.unwrap();
+		assert_eq!(
+			&workspace.root,
+			Path::new("testdata/workspace_nested_wildcard/")
+		);
+	}
+
+	#[test]
+	fn collects_workspace_members() {
+		let workspace = Workspace::new(Path::new("testdata/workspace_nested_wildcard/")).unwrap();
+		let members = workspace
+			.members()
+			.map(|package_json| package_json.1.name.as_deref().unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(members, vec!["nya", "poyo"]);
+		assert_eq!(
+			workspace.get("poyo").unwrap().dir(),
+			Path::new("testdata/workspace_nested_wildcard/packages/poyo/")
+		);
+		assert!(workspace.get("secret-third-thing").is_none());
+	}
+
+	#[test]
+	fn orders_workspace_packages_by_dependencies() {
+		let workspace = Workspace::new(Path::new("testdata/workspace_dependencies/")).unwrap();
+		assert_eq!(
+			workspace.dependencies_of("app"),
+			BTreeSet::from(["components", "utils"])
+		);
+		assert_eq!(
+			workspace.transitive_dependencies_of("app"),
+			BTreeSet::from(["components", "theme", "utils"])
+		);
+		assert_eq!(
+			workspace.transitive_dependents_of("theme"),
+			BTreeSet::from(["app", "components"])
+		);
+
+		let order = workspace
+			.topological_order()
+			.unwrap()
+			.into_iter()
+			.map(|package_json| package_json.1.name.as_deref().unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(order, vec!["theme", "components", "utils", "app"]);
+	}
 }